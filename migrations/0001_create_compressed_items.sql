@@ -0,0 +1,10 @@
+-- Items stored through the /items API
+CREATE TABLE IF NOT EXISTS compressed_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT NOT NULL,
+    data TEXT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS compressed_items_created_at_idx ON compressed_items (created_at DESC);
@@ -0,0 +1,12 @@
+-- Hourly and daily compression statistics served on /stats
+CREATE TABLE IF NOT EXISTS compression_stats (
+    granularity TEXT NOT NULL,
+    bucket_start TIMESTAMPTZ NOT NULL,
+    format TEXT NOT NULL,
+    api_key TEXT NOT NULL,
+    total_processed BIGINT NOT NULL DEFAULT 0,
+    bytes_in BIGINT NOT NULL DEFAULT 0,
+    bytes_out BIGINT NOT NULL DEFAULT 0,
+    ratio_sum DOUBLE PRECISION NOT NULL DEFAULT 0,
+    PRIMARY KEY (granularity, bucket_start, format, api_key)
+);
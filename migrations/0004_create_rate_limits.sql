@@ -0,0 +1,15 @@
+-- Token buckets and quota counters shared by every instance
+CREATE TABLE IF NOT EXISTS rate_limit_buckets (
+    subject TEXT PRIMARY KEY,
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS usage_counters (
+    subject TEXT NOT NULL,
+    period TEXT NOT NULL,
+    period_start DATE NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    bytes BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (subject, period, period_start)
+);
@@ -0,0 +1,24 @@
+-- Item payloads are now stored compressed, as bytes, with the algorithm used.
+-- Existing rows held the client's base64 text verbatim; decode it where it is valid
+-- base64 and keep them uncompressed ("identity").
+ALTER TABLE compressed_items
+    ALTER COLUMN data TYPE BYTEA USING (
+        CASE
+            WHEN data ~ '^[A-Za-z0-9+/]*={0,2}$' AND length(data) % 4 = 0 THEN decode(data, 'base64')
+            ELSE convert_to(data, 'UTF8')
+        END
+    );
+
+ALTER TABLE compressed_items
+    ADD COLUMN algorithm TEXT NOT NULL DEFAULT 'identity',
+    ADD COLUMN level INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN original_size BIGINT,
+    ADD COLUMN compressed_size BIGINT;
+
+UPDATE compressed_items SET original_size = length(data), compressed_size = length(data);
+
+ALTER TABLE compressed_items
+    ALTER COLUMN algorithm DROP DEFAULT,
+    ALTER COLUMN level DROP DEFAULT,
+    ALTER COLUMN original_size SET NOT NULL,
+    ALTER COLUMN compressed_size SET NOT NULL;
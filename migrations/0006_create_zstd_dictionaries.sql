@@ -0,0 +1,16 @@
+-- Versioned zstd dictionaries trained from stored items; at most one is active and
+-- used for new zstd items. Rows keep the version they were compressed with, so a
+-- dictionary cannot be deleted while items still need it.
+CREATE TABLE zstd_dictionaries (
+    version SERIAL PRIMARY KEY,
+    data BYTEA NOT NULL,
+    sample_count INTEGER NOT NULL,
+    sample_bytes BIGINT NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX zstd_dictionaries_one_active ON zstd_dictionaries (active) WHERE active;
+
+ALTER TABLE compressed_items
+    ADD COLUMN dictionary_version INTEGER REFERENCES zstd_dictionaries (version);
@@ -0,0 +1,11 @@
+-- Items are paged by (created_at, id), so both timestamps must always be set
+UPDATE compressed_items SET created_at = NOW() WHERE created_at IS NULL;
+UPDATE compressed_items SET updated_at = created_at WHERE updated_at IS NULL;
+ALTER TABLE compressed_items ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE compressed_items ALTER COLUMN updated_at SET NOT NULL;
+
+DROP INDEX IF EXISTS compressed_items_created_at_idx;
+CREATE INDEX compressed_items_created_at_id_idx ON compressed_items (created_at, id);
+
+-- Name prefix search
+CREATE INDEX compressed_items_name_pattern_idx ON compressed_items (name text_pattern_ops);
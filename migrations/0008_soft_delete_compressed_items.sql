@@ -0,0 +1,4 @@
+-- Deleted items stay in the trash until restored or purged
+ALTER TABLE compressed_items ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX compressed_items_deleted_at_idx ON compressed_items (deleted_at) WHERE deleted_at IS NOT NULL;
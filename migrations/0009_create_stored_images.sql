@@ -0,0 +1,9 @@
+-- Compressed images kept by the service
+CREATE TABLE stored_images (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    format TEXT NOT NULL,
+    data BYTEA NOT NULL,
+    original_size BIGINT NOT NULL,
+    compressed_size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,13 @@
+-- Hex SHA-256 of the uncompressed and the stored payload. The stored payload can be
+-- hashed here; the uncompressed one only where nothing was compressed, the rest is
+-- filled in by `admin verify --backfill`
+ALTER TABLE compressed_items ADD COLUMN original_sha256 TEXT;
+ALTER TABLE compressed_items ADD COLUMN compressed_sha256 TEXT;
+
+UPDATE compressed_items SET compressed_sha256 = encode(sha256(data), 'hex');
+UPDATE compressed_items SET original_sha256 = compressed_sha256 WHERE algorithm = 'identity';
+
+ALTER TABLE stored_images ADD COLUMN original_sha256 TEXT;
+ALTER TABLE stored_images ADD COLUMN compressed_sha256 TEXT;
+
+UPDATE stored_images SET compressed_sha256 = encode(sha256(data), 'hex');
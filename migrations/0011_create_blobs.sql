@@ -0,0 +1,32 @@
+-- Content-addressed image data: each distinct compressed image or thumbnail is stored
+-- once, keyed by its hex SHA-256, and counts the stored images that refer to it. Source
+-- images are never stored; only their SHA-256 is, to find a derivative to reuse.
+CREATE TABLE blobs (
+    sha256 TEXT PRIMARY KEY,
+    data BYTEA NOT NULL,
+    size BIGINT NOT NULL,
+    ref_count BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- A stored image's data now lives in the blob named by `compressed_sha256`, which is
+-- computed below for images stored without one, so `data` is emptied for every image.
+-- `thumbnail_sha256` names the thumbnail's blob, and `options` the settings the image
+-- was compressed with, so a later request for the same source and settings can reuse it.
+ALTER TABLE stored_images ADD COLUMN thumbnail_sha256 TEXT;
+ALTER TABLE stored_images ADD COLUMN options TEXT;
+
+UPDATE stored_images SET compressed_sha256 = encode(sha256(data), 'hex') WHERE compressed_sha256 IS NULL;
+
+INSERT INTO blobs (sha256, data, size, ref_count)
+SELECT DISTINCT ON (compressed_sha256)
+    compressed_sha256,
+    data,
+    octet_length(data),
+    COUNT(*) OVER (PARTITION BY compressed_sha256)
+FROM stored_images
+ORDER BY compressed_sha256;
+
+UPDATE stored_images SET data = ''::BYTEA;
+
+CREATE INDEX idx_stored_images_derivative ON stored_images (original_sha256, options);
@@ -0,0 +1,40 @@
+-- SQLite schema for items, zstd dictionaries and stored images; matches the
+-- PostgreSQL schema built by the migrations in `migrations/`. IDs are hyphenated
+-- UUID text and timestamps are microseconds since the Unix epoch, so both sort
+-- the same way they do in PostgreSQL.
+CREATE TABLE zstd_dictionaries (
+    version INTEGER PRIMARY KEY AUTOINCREMENT,
+    data BLOB NOT NULL,
+    sample_count INTEGER NOT NULL,
+    sample_bytes INTEGER NOT NULL,
+    active BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at INTEGER NOT NULL
+);
+
+CREATE UNIQUE INDEX zstd_dictionaries_one_active ON zstd_dictionaries (active) WHERE active;
+
+CREATE TABLE compressed_items (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    data BLOB NOT NULL,
+    algorithm TEXT NOT NULL,
+    level INTEGER NOT NULL,
+    dictionary_version INTEGER REFERENCES zstd_dictionaries (version),
+    original_size INTEGER NOT NULL,
+    compressed_size INTEGER NOT NULL,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    deleted_at INTEGER
+);
+
+CREATE INDEX compressed_items_created_at_id_idx ON compressed_items (created_at, id);
+CREATE INDEX compressed_items_deleted_at_idx ON compressed_items (deleted_at) WHERE deleted_at IS NOT NULL;
+
+CREATE TABLE stored_images (
+    id TEXT PRIMARY KEY,
+    format TEXT NOT NULL,
+    data BLOB NOT NULL,
+    original_size INTEGER NOT NULL,
+    compressed_size INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
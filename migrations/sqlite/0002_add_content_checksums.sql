@@ -0,0 +1,8 @@
+-- Hex SHA-256 of the uncompressed and the stored payload, as in
+-- `migrations/0010_add_content_checksums.sql`. SQLite cannot hash, so existing rows
+-- are filled in by `admin verify --backfill`
+ALTER TABLE compressed_items ADD COLUMN original_sha256 TEXT;
+ALTER TABLE compressed_items ADD COLUMN compressed_sha256 TEXT;
+
+ALTER TABLE stored_images ADD COLUMN original_sha256 TEXT;
+ALTER TABLE stored_images ADD COLUMN compressed_sha256 TEXT;
@@ -0,0 +1,23 @@
+-- Content-addressed image data, as in `migrations/0011_create_blobs.sql`. Images
+-- without a checksum keep their data in `stored_images` until `admin verify --backfill`
+-- records one.
+CREATE TABLE blobs (
+    sha256 TEXT PRIMARY KEY,
+    data BLOB NOT NULL,
+    size INTEGER NOT NULL,
+    ref_count INTEGER NOT NULL,
+    created_at INTEGER NOT NULL
+);
+
+ALTER TABLE stored_images ADD COLUMN thumbnail_sha256 TEXT;
+ALTER TABLE stored_images ADD COLUMN options TEXT;
+
+INSERT INTO blobs (sha256, data, size, ref_count, created_at)
+SELECT compressed_sha256, MIN(data), length(MIN(data)), COUNT(*), MIN(created_at)
+FROM stored_images
+WHERE compressed_sha256 IS NOT NULL
+GROUP BY compressed_sha256;
+
+UPDATE stored_images SET data = X'' WHERE compressed_sha256 IS NOT NULL;
+
+CREATE INDEX idx_stored_images_derivative ON stored_images (original_sha256, options);
@@ -0,0 +1,103 @@
+use crate::api::extract::{ApiJson, ApiPath};
+use crate::core::models::{ApiKeyInfo, AppState, CreateApiKeyRequest, CreatedApiKey};
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
+use axum::{extract::State, http::StatusCode, response::Json};
+
+/// List API keys
+///
+/// Returns metadata for every active key, from the keys file and the database.
+/// Requires the `admin` scope.
+#[utoipa::path(
+    get,
+    path = "/admin/keys",
+    responses(
+        (status = 200, description = "Active API keys", body = Vec<ApiKeyInfo>),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn list_api_keys_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ApiKeyInfo>>, AppError> {
+    Ok(Json(state.auth.list_keys().await?))
+}
+
+/// Create an API key
+///
+/// Creates a database-backed key. The plaintext key is returned once and cannot be
+/// retrieved later. Requires the `admin` scope.
+///
+/// Response codes:
+/// - 201: Key created
+/// - 400: Missing name or scopes
+/// - 501: No database configured; static keys are read-only
+#[utoipa::path(
+    post,
+    path = "/admin/keys",
+    request_body = CreateApiKeyRequest,
+    responses(
+        (status = 201, description = "Key created", body = CreatedApiKey),
+        (status = 400, description = "Missing name or scopes (`request.validation_failed`) or malformed JSON (`request.malformed_json`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 501, description = "Key management requires the database (`auth.read_only`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn create_api_key_handler(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreatedApiKey>), AppError> {
+    let created = state.auth.create_key(&payload.name, &payload.scopes).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+/// Revoke an API key
+///
+/// Revokes a database-backed key immediately. Requires the `admin` scope.
+#[utoipa::path(
+    delete,
+    path = "/admin/keys/{id}",
+    params(
+        ("id" = String, Path, description = "API key ID")
+    ),
+    responses(
+        (status = 204, description = "Key revoked"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Key not found (`api_key.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 501, description = "Key management requires the database (`auth.read_only`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn revoke_api_key_handler(
+    State(state): State<AppState>,
+    ApiPath(id): ApiPath<String>,
+) -> Result<StatusCode, AppError> {
+    if state.auth.revoke_key(&id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::new(ErrorCode::ApiKeyNotFound, "API key not found"))
+    }
+}
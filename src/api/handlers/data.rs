@@ -0,0 +1,357 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use axum::{
+    Extension,
+    body::Bytes,
+    extract::{FromRequest, Request, State},
+    http::{HeaderMap, HeaderName, HeaderValue, header},
+    response::{IntoResponse, Json, Response},
+};
+use base64::prelude::*;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+use utoipa::IntoParams;
+
+use crate::api::extract::{ApiJson, ApiQuery};
+use crate::core::models::{
+    AppState, CompressDataRequest, CompressionAlgorithm, DataCompressionResponse, DecompressDataRequest,
+};
+use crate::services::codec;
+use crate::services::data::DataOutcome;
+use crate::services::rate_limit::Subject;
+use crate::utils::checksum::{content_digest, sha256_hex, verify_checksum, verify_content_digest};
+use crate::utils::spool::Spool;
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+const X_ORIGINAL_SIZE: HeaderName = HeaderName::from_static("x-original-size");
+const X_COMPRESSED_SIZE: HeaderName = HeaderName::from_static("x-compressed-size");
+const X_COMPRESSION_RATIO: HeaderName = HeaderName::from_static("x-compression-ratio");
+const X_THROUGHPUT: HeaderName = HeaderName::from_static("x-throughput-mb-per-sec");
+
+/// Query parameters for `POST /compress/data` with a raw body
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CompressDataParams {
+    /// Compression algorithm (default: zstd)
+    pub algorithm: Option<CompressionAlgorithm>,
+    /// Compression level (default: the algorithm's default)
+    pub level: Option<i32>,
+}
+
+/// Query parameters for `POST /decompress/data` with a raw body
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DecompressDataParams {
+    /// Algorithm the body was compressed with; required for raw bodies
+    pub algorithm: Option<CompressionAlgorithm>,
+}
+
+/// Compress arbitrary data
+///
+/// Send either a JSON body with base64 `data`, or the raw bytes with any other
+/// content type and the algorithm and level in the query string. Raw bodies are
+/// buffered (spilling to disk when large) and answered with the compressed bytes,
+/// with sizes, ratio and throughput in `X-*` headers.
+///
+/// Either kind of body may carry a `Content-Digest: sha-256=:<base64>:` header, and
+/// JSON bodies a `checksum` of the decoded data; a mismatch fails the request.
+///
+/// Algorithms: gzip, deflate (zlib), zstd, brotli, lz4, xz, bzip2 and identity.
+#[utoipa::path(
+    post,
+    path = "/compress/data",
+    params(CompressDataParams),
+    request_body(
+        description = "Base64 JSON, or the raw data with any other content type",
+        content(
+            (CompressDataRequest = "application/json"),
+            (Vec<u8> = "application/octet-stream")
+        )
+    ),
+    responses(
+        (status = 200, description = "Compressed data: JSON for JSON requests, raw bytes otherwise",
+            content(
+                (DataCompressionResponse = "application/json"),
+                (Vec<u8> = "application/octet-stream")
+            ),
+            headers(
+                ("X-Original-Size" = u64, description = "Uncompressed size in bytes (raw responses)"),
+                ("X-Compressed-Size" = u64, description = "Compressed size in bytes (raw responses)"),
+                ("X-Compression-Ratio" = f64, description = "Compressed size / original size (raw responses)"),
+                ("X-Throughput-MB-Per-Sec" = f64, description = "Uncompressed MB processed per second (raw responses)"),
+                ("Server-Timing" = String, description = "Processing time, e.g. `compress;dur=4.2`")
+            )),
+        (status = 400, description = "Invalid base64 data or level (`request.validation_failed`), checksum or `Content-Digest` mismatch (`request.checksum_mismatch`), malformed JSON or query",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the compress scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "Body exceeds limits.max_data_size (`request.body_too_large`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "JSON body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limit or quota exceeded (`rate_limit.exceeded`, `quota.*`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security((), ("api_key" = ["compress"]))
+)]
+pub async fn compress_data_handler(
+    State(state): State<AppState>,
+    subject: Option<Extension<Subject>>,
+    ApiQuery(params): ApiQuery<CompressDataParams>,
+    request: Request,
+) -> Result<Response, AppError> {
+    if is_json(request.headers()) {
+        let ApiJson(payload) = ApiJson::<CompressDataRequest>::from_request(request, &state).await?;
+        let algorithm = payload.algorithm.unwrap_or_default();
+        let level = codec::resolve_level(algorithm, payload.level)?;
+        let data = decode_data(&payload.data)?;
+        verify_checksum("checksum", payload.checksum.as_deref(), &sha256_hex(&data))?;
+        let input = state.data_service.receive(single_chunk(data)).await?;
+        let outcome = state.data_service.compress(input, algorithm, level).await?;
+        record_bytes(&state, subject, outcome.input_size).await;
+
+        let report = Report::compression(algorithm, level, &outcome);
+        return report.json(outcome.output).await;
+    }
+
+    let algorithm = params.algorithm.unwrap_or_default();
+    let level = codec::resolve_level(algorithm, params.level)?;
+    let expected = content_digest(request.headers())?;
+    let mut hasher = expected.map(|_| Sha256::new());
+    let body = request.into_body().into_data_stream().inspect(|chunk| {
+        if let (Some(hasher), Ok(chunk)) = (hasher.as_mut(), chunk) {
+            hasher.update(chunk);
+        }
+    });
+    let input = state.data_service.receive(body).await?;
+    check_digest(expected, hasher)?;
+    let outcome = state.data_service.compress(input, algorithm, level).await?;
+    record_bytes(&state, subject, outcome.input_size).await;
+
+    let report = Report::compression(algorithm, level, &outcome);
+    Ok(report.raw(outcome.output))
+}
+
+/// Decompress arbitrary data
+///
+/// The counterpart of `POST /compress/data`: send base64 JSON naming the algorithm,
+/// or the raw compressed bytes with `?algorithm=` in the query string. Output is
+/// limited to `limits.max_data_size` bytes.
+#[utoipa::path(
+    post,
+    path = "/decompress/data",
+    params(DecompressDataParams),
+    request_body(
+        description = "Base64 JSON, or the raw compressed data with any other content type",
+        content(
+            (DecompressDataRequest = "application/json"),
+            (Vec<u8> = "application/octet-stream")
+        )
+    ),
+    responses(
+        (status = 200, description = "Decompressed data: JSON for JSON requests, raw bytes otherwise",
+            content(
+                (DataCompressionResponse = "application/json"),
+                (Vec<u8> = "application/octet-stream")
+            ),
+            headers(
+                ("X-Original-Size" = u64, description = "Decompressed size in bytes (raw responses)"),
+                ("X-Compressed-Size" = u64, description = "Compressed size in bytes (raw responses)"),
+                ("X-Compression-Ratio" = f64, description = "Compressed size / original size (raw responses)"),
+                ("X-Throughput-MB-Per-Sec" = f64, description = "Decompressed MB produced per second (raw responses)"),
+                ("Server-Timing" = String, description = "Processing time, e.g. `decompress;dur=1.3`")
+            )),
+        (status = 400, description = "Data is not valid for the algorithm (`data.decompress_failed`), invalid base64 or missing algorithm (`request.validation_failed`), checksum or `Content-Digest` mismatch (`request.checksum_mismatch`), malformed JSON or query",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the compress scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "Body (`request.body_too_large`) or decompressed data (`data.too_large`) exceeds limits.max_data_size",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "JSON body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limit or quota exceeded (`rate_limit.exceeded`, `quota.*`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+    ),
+    security((), ("api_key" = ["compress"]))
+)]
+pub async fn decompress_data_handler(
+    State(state): State<AppState>,
+    subject: Option<Extension<Subject>>,
+    ApiQuery(params): ApiQuery<DecompressDataParams>,
+    request: Request,
+) -> Result<Response, AppError> {
+    if is_json(request.headers()) {
+        let ApiJson(payload) = ApiJson::<DecompressDataRequest>::from_request(request, &state).await?;
+        let data = decode_data(&payload.data)?;
+        verify_checksum("checksum", payload.checksum.as_deref(), &sha256_hex(&data))?;
+        let input = state.data_service.receive(single_chunk(data)).await?;
+        let outcome = state.data_service.decompress(input, payload.algorithm).await?;
+        record_bytes(&state, subject, outcome.input_size).await;
+
+        let report = Report::decompression(payload.algorithm, &outcome);
+        return report.json(outcome.output).await;
+    }
+
+    let algorithm = params.algorithm.ok_or_else(|| {
+        AppError::new(
+            ErrorCode::ValidationFailed,
+            "The `algorithm` query parameter is required for raw bodies",
+        )
+    })?;
+    let expected = content_digest(request.headers())?;
+    let mut hasher = expected.map(|_| Sha256::new());
+    let body = request.into_body().into_data_stream().inspect(|chunk| {
+        if let (Some(hasher), Ok(chunk)) = (hasher.as_mut(), chunk) {
+            hasher.update(chunk);
+        }
+    });
+    let input = state.data_service.receive(body).await?;
+    check_digest(expected, hasher)?;
+    let outcome = state.data_service.decompress(input, algorithm).await?;
+    record_bytes(&state, subject, outcome.input_size).await;
+
+    let report = Report::decompression(algorithm, &outcome);
+    Ok(report.raw(outcome.output))
+}
+
+/// Sizes and timing of one operation, reported in the JSON body or in headers
+struct Report {
+    algorithm: CompressionAlgorithm,
+    level: Option<i32>,
+    stage: &'static str,
+    original_size: u64,
+    compressed_size: u64,
+    elapsed: Duration,
+}
+
+impl Report {
+    fn compression(algorithm: CompressionAlgorithm, level: i32, outcome: &DataOutcome) -> Self {
+        Self {
+            algorithm,
+            level: Some(level),
+            stage: "compress",
+            original_size: outcome.input_size,
+            compressed_size: outcome.output.len(),
+            elapsed: outcome.elapsed,
+        }
+    }
+
+    fn decompression(algorithm: CompressionAlgorithm, outcome: &DataOutcome) -> Self {
+        Self {
+            algorithm,
+            level: None,
+            stage: "decompress",
+            original_size: outcome.output.len(),
+            compressed_size: outcome.input_size,
+            elapsed: outcome.elapsed,
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        if self.original_size == 0 {
+            return 1.0;
+        }
+        self.compressed_size as f64 / self.original_size as f64
+    }
+
+    fn throughput(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.original_size as f64 / secs / 1_000_000.0
+    }
+
+    fn duration_ms(&self) -> f64 {
+        self.elapsed.as_micros() as f64 / 1000.0
+    }
+
+    fn server_timing(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("{};dur={:.1}", self.stage, self.duration_ms()))
+            .expect("Server-Timing values are ASCII")
+    }
+
+    async fn json(self, output: Spool) -> Result<Response, AppError> {
+        let output = output
+            .into_bytes()
+            .await
+            .map_err(|e| AppError::internal("Failed to read buffered output", e))?;
+        let server_timing = self.server_timing();
+        let body = DataCompressionResponse {
+            algorithm: self.algorithm,
+            level: self.level,
+            original_size: self.original_size,
+            compressed_size: self.compressed_size,
+            compression_ratio: self.ratio(),
+            throughput_mb_per_sec: self.throughput(),
+            processing_duration_ms: self.duration_ms(),
+            data: BASE64_STANDARD.encode(output),
+        };
+        Ok(([(SERVER_TIMING, server_timing)], Json(body)).into_response())
+    }
+
+    fn raw(self, output: Spool) -> Response {
+        let headers = [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/octet-stream")),
+            (X_ORIGINAL_SIZE, HeaderValue::from(self.original_size)),
+            (X_COMPRESSED_SIZE, HeaderValue::from(self.compressed_size)),
+            (X_COMPRESSION_RATIO, float_header(self.ratio())),
+            (X_THROUGHPUT, float_header(self.throughput())),
+            (SERVER_TIMING, self.server_timing()),
+        ];
+        (headers, output.into_body()).into_response()
+    }
+}
+
+fn float_header(value: f64) -> HeaderValue {
+    HeaderValue::from_str(&format!("{value:.4}")).expect("formatted floats are ASCII")
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|mime| {
+            let mime = mime.trim();
+            mime.eq_ignore_ascii_case("application/json") || mime.ends_with("+json")
+        })
+}
+
+fn decode_data(data: &str) -> Result<Vec<u8>, AppError> {
+    BASE64_STANDARD
+        .decode(data)
+        .map_err(|_| AppError::new(ErrorCode::ValidationFailed, "`data` is not valid base64"))
+}
+
+/// Checks a raw body against its `Content-Digest`, once it has all been received
+fn check_digest(expected: Option<[u8; 32]>, hasher: Option<Sha256>) -> Result<(), AppError> {
+    match (expected, hasher) {
+        (Some(expected), Some(hasher)) => Ok(verify_content_digest(expected, &hasher.finalize())?),
+        _ => Ok(()),
+    }
+}
+
+/// A decoded JSON payload as a one-chunk body stream
+fn single_chunk(data: Vec<u8>) -> impl futures_util::Stream<Item = Result<Bytes, Infallible>> + Unpin {
+    futures_util::stream::iter([Ok(Bytes::from(data))])
+}
+
+/// Counts the processed bytes against the caller's byte quotas
+async fn record_bytes(state: &AppState, subject: Option<Extension<Subject>>, bytes: u64) {
+    if let Some(Extension(subject)) = subject
+        && let Err(e) = state.rate_limiter.record_bytes(&subject, bytes).await
+    {
+        warn!("Failed to record processed bytes: {}", e);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::api::extract::{ApiJson, ApiPath};
+use crate::api::handlers::items::repository;
+use crate::core::database::DbError;
+use crate::core::models::{AppState, DictionaryInfo, TrainDictionaryRequest};
+use crate::services::dictionary::{self, DEFAULT_DICTIONARY_SIZE, DEFAULT_SAMPLE_SIZE};
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
+use axum::{extract::State, http::StatusCode, response::Json};
+
+/// List zstd dictionaries
+///
+/// Returns every trained dictionary, newest first. Requires the `admin` scope.
+#[utoipa::path(
+    get,
+    path = "/admin/dictionaries",
+    responses(
+        (status = 200, description = "Trained dictionaries", body = Vec<DictionaryInfo>),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn list_dictionaries_handler(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<DictionaryInfo>>, AppError> {
+    Ok(Json(repository(&state)?.list_dictionaries().await?))
+}
+
+/// Train a zstd dictionary
+///
+/// Trains a new dictionary version on a random sample of stored items and, unless
+/// `activate` is false, compresses new zstd items with it from then on. Items keep
+/// the dictionary version they were written with. Requires the `admin` scope.
+///
+/// Response codes:
+/// - 201: Dictionary trained
+/// - 422: No items to sample, or too little data to train on
+#[utoipa::path(
+    post,
+    path = "/admin/dictionaries",
+    request_body = TrainDictionaryRequest,
+    responses(
+        (status = 201, description = "Dictionary trained", body = DictionaryInfo),
+        (status = 400, description = "Malformed JSON (`request.malformed_json`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`) or training failed (`dictionary.training_failed`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`) or server shutting down (`server.shutting_down`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn train_dictionary_handler(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<TrainDictionaryRequest>,
+) -> Result<(StatusCode, Json<DictionaryInfo>), AppError> {
+    let repo = repository(&state)?;
+    let set = state
+        .dictionaries
+        .training_set(repo, payload.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE))
+        .await?;
+    let max_size = payload.max_size.unwrap_or(DEFAULT_DICTIONARY_SIZE);
+    let trained = state.compute.run(move || dictionary::train(set, max_size)).await??;
+
+    let activate = payload.activate.unwrap_or(true);
+    let info = repo.insert_dictionary(&trained.data, trained.sample_count, trained.sample_bytes, activate).await?;
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+/// Activate a zstd dictionary
+///
+/// Compresses new zstd items with this dictionary version from then on. Requires the
+/// `admin` scope.
+#[utoipa::path(
+    post,
+    path = "/admin/dictionaries/{version}/activate",
+    params(
+        ("version" = i32, Path, description = "Dictionary version")
+    ),
+    responses(
+        (status = 200, description = "Dictionary activated", body = DictionaryInfo),
+        (status = 400, description = "Invalid version (`request.invalid_path`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Dictionary not found (`dictionary.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn activate_dictionary_handler(
+    State(state): State<AppState>,
+    ApiPath(version): ApiPath<i32>,
+) -> Result<Json<DictionaryInfo>, AppError> {
+    match repository(&state)?.activate_dictionary(version).await {
+        Ok(info) => Ok(Json(info)),
+        Err(DbError::NotFound) => Err(AppError::new(ErrorCode::DictionaryNotFound, "Dictionary not found")),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,15 @@
+use crate::utils::{AppError, ErrorCode};
+use axum::http::{Method, Uri};
+
+/// Fallback for paths no route matches
+pub async fn not_found(uri: Uri) -> AppError {
+    AppError::new(ErrorCode::RouteNotFound, format!("No route for {}", uri.path()))
+}
+
+/// Fallback for routes that exist but not for the request's method
+pub async fn method_not_allowed(method: Method, uri: Uri) -> AppError {
+    AppError::new(
+        ErrorCode::MethodNotAllowed,
+        format!("{} is not allowed on {}", method, uri.path()),
+    )
+}
@@ -1,3 +1,6 @@
+use crate::core::models::{AppState, LivenessResponse, ReadinessResponse, ReadinessStatus};
+use axum::{extract::State, http::StatusCode, response::Json};
+
 /// Welcome endpoint
 ///
 /// Returns a welcome message for the API
@@ -14,14 +17,60 @@ pub async fn root() -> &'static str {
 
 /// Health check endpoint
 ///
-/// Returns a simple OK message to indicate the service is running
+/// Returns a simple OK message to indicate the service is running, or
+/// DRAINING with 503 once shutdown has started
 #[utoipa::path(
     get,
     path = "/health",
     responses(
-        (status = 200, description = "Health check OK", body = String)
+        (status = 200, description = "Health check OK", body = String),
+        (status = 503, description = "Shutting down", body = String)
+    )
+)]
+pub async fn health_check(State(state): State<AppState>) -> (StatusCode, &'static str) {
+    if state.lifecycle.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "DRAINING")
+    } else {
+        (StatusCode::OK, "OK")
+    }
+}
+
+/// Liveness probe
+///
+/// Returns 200 as long as the process is running and able to serve requests
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    responses(
+        (status = 200, description = "Process is alive", body = LivenessResponse)
+    )
+)]
+pub async fn liveness_handler(State(state): State<AppState>) -> Json<LivenessResponse> {
+    Json(state.health.liveness())
+}
+
+/// Readiness probe
+///
+/// Checks database connectivity (when enabled), storage writability and compute-pool
+/// saturation, and reports not-ready while the server is draining. Each check includes
+/// its latency.
+///
+/// Response codes:
+/// - 200: Ready to receive traffic
+/// - 503: At least one check failed
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    responses(
+        (status = 200, description = "Ready", body = ReadinessResponse),
+        (status = 503, description = "Not ready", body = ReadinessResponse)
     )
 )]
-pub async fn health_check() -> &'static str {
-    "OK"
+pub async fn readiness_handler(State(state): State<AppState>) -> (StatusCode, Json<ReadinessResponse>) {
+    let report = state.health.readiness().await;
+    let status = match report.status {
+        ReadinessStatus::Ready => StatusCode::OK,
+        ReadinessStatus::NotReady => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, Json(report))
 }
@@ -1,68 +1,184 @@
-use crate::core::models::{CompressImageRequest, CompressImageResponse};
-use crate::services::ImageCompressionService;
+use crate::api::extract::ApiJson;
+use crate::core::models::{
+    AppState, BatchImageResult, CompressBatchRequest, CompressBatchResponse, CompressImageRequest, CompressImageResponse,
+};
+use crate::services::ApiKeyIdentity;
+use crate::services::rate_limit::Subject;
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
 use axum::{
-    http::StatusCode,
+    Extension,
+    extract::State,
+    http::{HeaderName, HeaderValue},
     response::Json,
 };
-use serde_json::{Value, json};
-use tracing::error;
+use tracing::{error, warn};
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// Largest number of images accepted in one batch
+pub const MAX_BATCH_IMAGES: usize = 16;
 
 /// Compress an image from URL with resize option
 ///
 /// Downloads an image from the provided URL, resizes it according to the specified percentage,
 /// and returns the compressed image data along with compression statistics.
 ///
-/// Response codes:
-/// - 200: Successfully compressed image
-/// - 400: Bad request (invalid URL, resize percentage, etc.)
-/// - 413: Image too large
-/// - 500: Internal server error
+/// Pass the source image's SHA-256 as `checksum`, or the request body's as a
+/// `Content-Digest: sha-256=:<base64>:` header, to have it checked before processing;
+/// the response carries the SHA-256 of both the source and the compressed image.
+///
+/// With a database the compressed image is stored under `file_id`. A request for the
+/// same source image with the same options is answered with the stored image and
+/// `deduplicated: true`, without processing the image again.
+///
+/// Errors are `application/problem+json` bodies whose `code` identifies the failure,
+/// e.g. `image.decode_failed` or `fetch.timeout`.
 #[utoipa::path(
     post,
     path = "/compress",
     request_body = CompressImageRequest,
     responses(
-        (status = 200, description = "Successfully compressed image", body = CompressImageResponse),
-        (status = 400, description = "Bad request", body = Value),
-        (status = 413, description = "Image too large", body = Value),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 200, description = "Successfully compressed image", body = CompressImageResponse,
+            headers(
+                ("Server-Timing" = String, description = "Per-stage durations, e.g. `fetch;dur=12.4, decode;dur=3.1, ..., total;dur=55.0`")
+            )),
+        (status = 400, description = "Invalid request (`request.malformed_json`, `request.validation_failed`, `image.invalid_input`, `image.invalid_resize`), checksum or `Content-Digest` mismatch (`request.checksum_mismatch`), undecodable image (`image.decode_failed`, `image.unsupported_format`) or invalid URL (`fetch.invalid_url`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the compress scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "Image or request body too large (`image.too_large`, `request.body_too_large`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limit or quota exceeded (`rate_limit.exceeded`, `quota.requests_exceeded`, `quota.bytes_exceeded`)",
+            body = ProblemDetails, content_type = "application/problem+json",
+            headers(
+                ("RateLimit-Limit" = u64, description = "Limit that was exceeded"),
+                ("RateLimit-Remaining" = u64, description = "Always 0"),
+                ("RateLimit-Reset" = u64, description = "Seconds until the limit resets"),
+                ("Retry-After" = u64, description = "Seconds to wait before retrying")
+            )),
+        (status = 500, description = "Image processing failed (`image.processing_failed`, `internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 502, description = "Image host unreachable or returned an error (`fetch.connect_failed`, `fetch.too_many_redirects`, `fetch.upstream_status`, `fetch.failed`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Server is shutting down (`server.shutting_down`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 504, description = "Image download timed out (`fetch.timeout`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["compress"]))
 )]
 pub async fn compress_image_handler(
-    Json(payload): Json<CompressImageRequest>,
-) -> Result<Json<CompressImageResponse>, (StatusCode, Json<Value>)> {
-    let service = ImageCompressionService::new();
-    
-    match service.compress_image(payload).await {
-        Ok(response) => Ok(Json(response)),
-        Err(e) => {
-            error!("Image compression failed: {:?}", e);
-            
-            let (status_code, error_message) = match e {
-                crate::services::ImageProcessingError::InvalidResizePercentage(percentage) => {
-                    (StatusCode::BAD_REQUEST, format!("Invalid resize percentage: {}. Must be between 1 and 100", percentage))
-                }
-                crate::services::ImageProcessingError::ImageTooLarge(size, max_size) => {
-                    (StatusCode::PAYLOAD_TOO_LARGE, format!("Image too large: {} bytes. Maximum allowed: {} bytes", size, max_size))
-                }
-                crate::services::ImageProcessingError::DownloadError(_) => {
-                    (StatusCode::BAD_REQUEST, "Failed to download image from URL".to_string())
-                }
-                crate::services::ImageProcessingError::DecodeError(_) => {
-                    (StatusCode::BAD_REQUEST, "Invalid or corrupted image format".to_string())
-                }
-                crate::services::ImageProcessingError::UnsupportedFormat => {
-                    (StatusCode::BAD_REQUEST, "Unsupported image format".to_string())
-                }
-                crate::services::ImageProcessingError::InvalidInput(msg) => {
-                    (StatusCode::BAD_REQUEST, msg)
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    subject: Option<Extension<Subject>>,
+    ApiJson(payload): ApiJson<CompressImageRequest>,
+) -> Result<([(HeaderName, HeaderValue); 1], Json<CompressImageResponse>), AppError> {
+    let response = compress_one(&state, identity.as_ref(), subject.as_ref(), payload).await?;
+    let server_timing = HeaderValue::from_str(&response.timings.server_timing())
+        .expect("Server-Timing values are ASCII");
+    Ok(([(SERVER_TIMING, server_timing)], Json(response)))
+}
+
+/// Compress several images in one request
+///
+/// Takes up to 16 images, each with the same fields and options as `POST /compress`,
+/// and compresses them concurrently. Results come back in request order; an image
+/// that fails carries its problem details and does not fail the rest of the batch.
+///
+/// Response codes:
+/// - 200: Every image was processed; check each result's `status`
+/// - 400: No images, or more than 16
+#[utoipa::path(
+    post,
+    path = "/compress/batch",
+    request_body = CompressBatchRequest,
+    responses(
+        (status = 200, description = "One result per image, in request order", body = CompressBatchResponse),
+        (status = 400, description = "Empty or oversized batch (`request.validation_failed`) or malformed JSON (`request.malformed_json`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the batch scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 413, description = "Request body too large (`request.body_too_large`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 429, description = "Rate limit or quota exceeded (`rate_limit.exceeded`, `quota.requests_exceeded`, `quota.bytes_exceeded`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Server is shutting down (`server.shutting_down`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["batch"]))
+)]
+pub async fn compress_batch_handler(
+    State(state): State<AppState>,
+    identity: Option<Extension<ApiKeyIdentity>>,
+    subject: Option<Extension<Subject>>,
+    ApiJson(payload): ApiJson<CompressBatchRequest>,
+) -> Result<Json<CompressBatchResponse>, AppError> {
+    if payload.images.is_empty() || payload.images.len() > MAX_BATCH_IMAGES {
+        return Err(AppError::new(
+            ErrorCode::ValidationFailed,
+            format!("A batch takes 1 to {MAX_BATCH_IMAGES} images, got {}", payload.images.len()),
+        ));
+    }
+
+    let jobs = payload
+        .images
+        .into_iter()
+        .map(|image| compress_one(&state, identity.as_ref(), subject.as_ref(), image));
+    let results = futures_util::future::join_all(jobs)
+        .await
+        .into_iter()
+        .map(|result| match result {
+            Ok(response) => BatchImageResult {
+                status: 200,
+                result: Some(response),
+                error: None,
+            },
+            Err(e) => {
+                let problem = e.problem();
+                BatchImageResult {
+                    status: problem.status,
+                    result: None,
+                    error: Some(problem),
                 }
-            };
-            
-            Err((
-                status_code,
-                Json(json!({"error": error_message})),
-            ))
-        }
+            }
+        })
+        .collect();
+    Ok(Json(CompressBatchResponse { results }))
+}
+
+/// Compresses one image and records it in the statistics and the caller's byte quota
+async fn compress_one(
+    state: &AppState,
+    identity: Option<&Extension<ApiKeyIdentity>>,
+    subject: Option<&Extension<Subject>>,
+    payload: CompressImageRequest,
+) -> Result<CompressImageResponse, AppError> {
+    let response = state.image_service.compress_image(payload).await.map_err(|e| {
+        error!("Image compression failed: {:?}", e);
+        AppError::from(e)
+    })?;
+
+    let format = response.content_type.trim_start_matches("image/");
+    let api_key = identity.map(|key| key.id.as_str());
+    state
+        .stats
+        .record(format, api_key, response.original_size, response.compressed_size);
+    if let Some(Extension(subject)) = subject
+        && let Err(e) = state.rate_limiter.record_bytes(subject, response.original_size).await
+    {
+        warn!("Failed to record processed bytes: {}", e);
     }
+    Ok(response)
 }
@@ -1,197 +1,927 @@
-use crate::core::database::{
-    DbError, create_item, delete_item, get_all_items, get_item_by_id, update_item,
+use crate::api::extract::{ApiJson, ApiPath, ApiQuery};
+use crate::api::handlers::stats::parse_bound;
+use crate::core::database::{DbError, ItemRepository};
+use crate::core::models::{
+    AppState, CompressedItem, CompressionAlgorithm, CreateCompressedItem, ExportCompression, ItemCursor, ItemPage,
+    ItemPayload, ItemQuery, SortOrder, StoredItem, UpdateCompressedItem,
 };
-use crate::core::models::{AppState, CompressedItem, CreateCompressedItem, UpdateCompressedItem};
+use crate::services::codec;
+use crate::services::dictionary::{self, Dictionaries};
+use crate::services::transfer::{ExportOptions, Exporter, NDJSON_CONTENT_TYPE};
+use crate::utils::checksum::verify_checksum;
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    body::{Body, Bytes},
+    extract::State,
+    http::{HeaderMap, HeaderName, StatusCode, header},
+    response::{IntoResponse, Json, Response},
 };
+use chrono::{DateTime, Utc};
+use base64::prelude::*;
+use serde::Deserialize;
 use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::io;
+use tokio::sync::mpsc;
+use tracing::{Instrument, error, info, warn};
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-/// Get all compressed items
+/// Export chunks buffered between the storage reader and the response body
+const EXPORT_QUEUE: usize = 4;
+
+const X_TOTAL_COUNT: HeaderName = HeaderName::from_static("x-total-count");
+
+/// A JSON body with its `ETag` header
+type WithEtag<T> = ([(HeaderName, String); 1], Json<T>);
+
+/// Items per page when `limit` is not given
+const DEFAULT_PAGE_SIZE: u32 = 50;
+/// Largest accepted `limit`
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Fields of [`CompressedItem`] that `fields` can select
+const ITEM_FIELDS: [&str; 14] = [
+    "id",
+    "name",
+    "data",
+    "algorithm",
+    "level",
+    "dictionary_version",
+    "original_size",
+    "compressed_size",
+    "original_sha256",
+    "compressed_sha256",
+    "etag",
+    "created_at",
+    "updated_at",
+    "deleted_at",
+];
+
+/// Query parameters for `GET /items`
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ItemListParams {
+    /// Items per page, 1-500 (default: 50)
+    pub limit: Option<u32>,
+    /// `next_cursor` from the previous page; keep the other parameters unchanged
+    pub cursor: Option<String>,
+    /// Comma-separated fields to return, e.g. `id,name,original_size` (default: all).
+    /// Leaving out `data` skips loading and decompressing it.
+    pub fields: Option<String>,
+    /// Only items whose name starts with this (case-sensitive)
+    pub name_prefix: Option<String>,
+    /// Only items whose name contains this (case-insensitive)
+    pub name_contains: Option<String>,
+    /// Only items created at or after this, RFC 3339 or `YYYY-MM-DD`
+    pub created_after: Option<String>,
+    /// Only items created before this, RFC 3339 or `YYYY-MM-DD` (inclusive day)
+    pub created_before: Option<String>,
+    /// Only items updated at or after this, RFC 3339 or `YYYY-MM-DD`
+    pub updated_after: Option<String>,
+    /// Only items updated before this, RFC 3339 or `YYYY-MM-DD` (inclusive day)
+    pub updated_before: Option<String>,
+    /// Creation-time order: `asc` or `desc` (default: `desc`, newest first)
+    pub sort: Option<SortOrder>,
+    /// Return the stored, compressed bytes instead of the original data (default: false)
+    pub raw: Option<bool>,
+}
+
+/// Query parameters for `GET /items/export`
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ItemExportParams {
+    /// Only items whose name starts with this (case-sensitive)
+    pub name_prefix: Option<String>,
+    /// Only items whose name contains this (case-insensitive)
+    pub name_contains: Option<String>,
+    /// Only items created at or after this, RFC 3339 or `YYYY-MM-DD`
+    pub created_after: Option<String>,
+    /// Only items created before this, RFC 3339 or `YYYY-MM-DD` (inclusive day)
+    pub created_before: Option<String>,
+    /// Only items updated at or after this, RFC 3339 or `YYYY-MM-DD`
+    pub updated_after: Option<String>,
+    /// Only items updated before this, RFC 3339 or `YYYY-MM-DD` (inclusive day)
+    pub updated_before: Option<String>,
+    /// Export matching items in the trash after the live ones (default: false)
+    pub include_trash: Option<bool>,
+    /// Export every stored image after the items (default: false)
+    pub images: Option<bool>,
+    /// Wrap the whole export: `gzip` or `zstd` (default: none)
+    pub compression: Option<ExportCompression>,
+}
+
+/// Query parameters for reading an item
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ItemReadParams {
+    /// Return the stored, compressed bytes instead of the original data (default: false)
+    pub raw: Option<bool>,
+}
+
+/// List compressed items
 ///
-/// Returns a list of all compressed items in the database
+/// Returns one page of items, decompressed unless `raw=true` is given. Pages are
+/// ordered by creation time and then ID; follow `next_cursor` until it is null to
+/// read every matching item. The total number of matching items is returned in
+/// `X-Total-Count`. The page's `ETag` changes whenever any item on it does, so
+/// `If-None-Match` can be used to poll for changes.
 ///
 /// Response codes:
-/// - 200: Successfully retrieved items
+/// - 200: One page of items
+/// - 304: Page unchanged since the `If-None-Match` ETag
+/// - 400: Invalid cursor, date, field or limit
 /// - 500: Internal server error
 #[utoipa::path(
     get,
     path = "/items",
+    params(
+        ItemListParams,
+        ("If-None-Match" = Option<String>, Header, description = "Reply 304 if the page still has one of these ETags")
+    ),
     responses(
-        (status = 200, description = "List of compressed items", body = [CompressedItem]),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 200, description = "One page of compressed items", body = ItemPage,
+            headers(
+                ("X-Total-Count" = i64, description = "Items matching the filters, across all pages"),
+                ("ETag" = String, description = "Strong entity tag of the page")
+            )),
+        (status = 304, description = "Page unchanged"),
+        (status = 400, description = "Invalid cursor, date, field, limit or query (`request.invalid_query`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:read scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:read"]))
 )]
 pub async fn get_items(
     State(state): State<AppState>,
-) -> Result<Json<Vec<CompressedItem>>, (StatusCode, Json<Value>)> {
-    match get_all_items(&state.db_pool).await {
-        Ok(items) => Ok(Json(items)),
-        Err(e) => {
-            eprintln!("Error getting items: {:?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to get items"})),
-            ))
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<ItemListParams>,
+) -> Result<Response, AppError> {
+    list_page(&state, &headers, params, false).await
+}
+
+/// List items in the trash
+///
+/// Returns one page of deleted items, with the same parameters, paging and headers as
+/// `GET /items`. Items are purged for good `database.trash_retention_days` after they
+/// were deleted; restore them before then with `POST /items/{id}/restore`.
+///
+/// Response codes:
+/// - 200: One page of deleted items
+/// - 304: Page unchanged since the `If-None-Match` ETag
+/// - 400: Invalid cursor, date, field or limit
+/// - 500: Internal server error
+#[utoipa::path(
+    get,
+    path = "/items/trash",
+    params(
+        ItemListParams,
+        ("If-None-Match" = Option<String>, Header, description = "Reply 304 if the page still has one of these ETags")
+    ),
+    responses(
+        (status = 200, description = "One page of deleted items", body = ItemPage,
+            headers(
+                ("X-Total-Count" = i64, description = "Deleted items matching the filters, across all pages"),
+                ("ETag" = String, description = "Strong entity tag of the page")
+            )),
+        (status = 304, description = "Page unchanged"),
+        (status = 400, description = "Invalid cursor, date, field, limit or query (`request.invalid_query`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:read scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:read"]))
+)]
+pub async fn get_trash(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<ItemListParams>,
+) -> Result<Response, AppError> {
+    list_page(&state, &headers, params, true).await
+}
+
+/// Export items as NDJSON
+///
+/// Streams every matching item, oldest first, one JSON record per line: items as
+/// `{"type": "item", ...}` with the same fields as `GET /items/{id}` and the original
+/// data, then with `images=true` stored images as `{"type": "image", ...}`. Items that
+/// cannot be read back are left out and logged. Load the export into another
+/// deployment with `admin import`.
+///
+/// Response codes:
+/// - 200: The export, streamed
+/// - 400: Invalid date or compression
+/// - 500: Internal server error
+#[utoipa::path(
+    get,
+    path = "/items/export",
+    params(ItemExportParams),
+    responses(
+        (status = 200, description = "NDJSON export, gzip- or zstd-wrapped when asked for",
+            content(
+                (String = "application/x-ndjson"),
+                (Vec<u8> = "application/gzip"),
+                (Vec<u8> = "application/zstd")
+            ),
+            headers(
+                ("Content-Disposition" = String, description = "Suggested file name, e.g. `items.ndjson.gz`")
+            )),
+        (status = 400, description = "Invalid date or query (`request.invalid_query`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:read scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:read"]))
+)]
+pub async fn export_items_handler(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<ItemExportParams>,
+) -> Result<Response, AppError> {
+    let (Some(items), Some(images)) = (state.items.clone(), state.images.clone()) else {
+        return Err(database_disabled());
+    };
+    let options = ExportOptions {
+        filter: ItemQuery {
+            name_prefix: params.name_prefix,
+            name_contains: params.name_contains,
+            created_after: date_param("created_after", params.created_after.as_deref(), false)?,
+            created_before: date_param("created_before", params.created_before.as_deref(), true)?,
+            updated_after: date_param("updated_after", params.updated_after.as_deref(), false)?,
+            updated_before: date_param("updated_before", params.updated_before.as_deref(), true)?,
+            ..ItemQuery::default()
+        },
+        include_trash: params.include_trash.unwrap_or(false),
+        images: params.images.unwrap_or(false),
+        compression: params.compression,
+    };
+    let mut exporter = Exporter::new(items, images, state.dictionaries.clone(), state.compute.clone(), options)?;
+
+    // The first chunk is read before answering, so a failure to read storage at all is
+    // still reported as a problem response
+    let first = exporter.next_chunk().await?;
+    let (sender, receiver) = mpsc::channel::<Result<Bytes, io::Error>>(EXPORT_QUEUE);
+    tokio::spawn(async move {
+        let mut chunk = first;
+        while let Some(bytes) = chunk {
+            if sender.send(Ok(Bytes::from(bytes))).await.is_err() {
+                return;
+            }
+            chunk = match exporter.next_chunk().await {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    // Too late for a problem response; cut the body short instead
+                    error!(error = %e, "Export failed part way through");
+                    let _ = sender.send(Err(io::Error::other(e.to_string()))).await;
+                    return;
+                }
+            };
+        }
+        let summary = exporter.summary();
+        for skipped in &summary.skipped {
+            warn!(kind = skipped.kind, id = %skipped.id, problem = %skipped.problem, "Left a damaged record out of an export");
         }
+        info!(items = summary.items, images = summary.images, skipped = summary.skipped.len(), "Export finished");
     }
+    .instrument(tracing::Span::current()));
+    let body = Body::from_stream(futures_util::stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
+    }));
+
+    let (content_type, file_name) = match params.compression {
+        Some(compression) => (compression.content_type(), format!("items.ndjson.{}", compression.extension())),
+        None => (NDJSON_CONTENT_TYPE, "items.ndjson".to_string()),
+    };
+    let headers = [
+        (header::CONTENT_TYPE, content_type.to_string()),
+        (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{file_name}\"")),
+    ];
+    Ok((headers, body).into_response())
+}
+
+/// Restore an item from the trash
+///
+/// Makes a deleted item live again, unchanged.
+///
+/// Response codes:
+/// - 200: Item restored
+/// - 404: No item with this ID in the trash
+/// - 500: Internal server error
+#[utoipa::path(
+    post,
+    path = "/items/{id}/restore",
+    params(
+        ("id" = Uuid, Path, description = "Item ID")
+    ),
+    responses(
+        (status = 200, description = "Restored item", body = CompressedItem,
+            headers(
+                ("ETag" = String, description = "Strong entity tag of the item")
+            )),
+        (status = 400, description = "Malformed item ID (`request.invalid_path`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:write scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Item not in the trash (`item.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:write"]))
+)]
+pub async fn restore_item_handler(
+    ApiPath(id): ApiPath<Uuid>,
+    State(state): State<AppState>,
+) -> Result<WithEtag<CompressedItem>, AppError> {
+    let repo = repository(&state)?;
+    let item = repo.restore_item(id).await?;
+    Ok(with_etag(read_one(&state, repo, item, false).await?))
+}
+
+/// One page of live or trashed items, for `GET /items` and `GET /items/trash`
+async fn list_page(
+    state: &AppState,
+    headers: &HeaderMap,
+    params: ItemListParams,
+    trashed: bool,
+) -> Result<Response, AppError> {
+    let repo = repository(state)?;
+    let fields = parse_fields(params.fields.as_deref())?;
+    let limit = params.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(invalid_query(format!("`limit` must be between 1 and {MAX_PAGE_SIZE}")));
+    }
+    let after = match params.cursor.as_deref() {
+        Some(token) => Some(ItemCursor::decode(token).ok_or_else(|| invalid_query("Invalid `cursor`".to_string()))?),
+        None => None,
+    };
+    let query = ItemQuery {
+        name_prefix: params.name_prefix,
+        name_contains: params.name_contains,
+        created_after: date_param("created_after", params.created_after.as_deref(), false)?,
+        created_before: date_param("created_before", params.created_before.as_deref(), true)?,
+        updated_after: date_param("updated_after", params.updated_after.as_deref(), false)?,
+        updated_before: date_param("updated_before", params.updated_before.as_deref(), true)?,
+        sort: params.sort.unwrap_or_default(),
+        trashed,
+        after,
+        // One extra row tells whether there is a next page
+        limit: limit + 1,
+        with_data: fields.contains(&"data"),
+    };
+
+    let (mut items, total) = tokio::try_join!(repo.list_items(&query), repo.count_items(&query))?;
+    let next_cursor = if items.len() > limit as usize {
+        items.truncate(limit as usize);
+        items
+            .last()
+            .and_then(|item| Some(ItemCursor { created_at: item.created_at?, id: item.id }))
+            .map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+
+    let raw = params.raw.unwrap_or(false);
+    let etag = page_etag(&items, &fields, raw, total, next_cursor.as_deref());
+    if not_modified(headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+
+    let items = if query.with_data {
+        read_items(state, repo, items, raw).await?
+    } else {
+        items
+            .into_iter()
+            .map(|item| to_response(item, String::new()))
+            .collect::<Result<_, _>>()?
+    };
+    let items = items
+        .into_iter()
+        .map(|item| select_fields(item, &fields))
+        .collect::<Result<_, _>>()?;
+
+    let headers = [(X_TOTAL_COUNT, total.to_string()), (header::ETAG, etag)];
+    Ok((headers, Json(ItemPage { items, next_cursor })).into_response())
 }
 
 /// Get a specific compressed item by ID
 ///
-/// Returns a single compressed item based on its ID
+/// Returns a single compressed item based on its ID, decompressed unless `raw=true`
+/// is given
 ///
 /// Response codes:
 /// - 200: Successfully retrieved item
+/// - 304: Item unchanged since the `If-None-Match` ETag
 /// - 404: Item not found
 /// - 500: Internal server error
 #[utoipa::path(
     get,
     path = "/items/{id}",
     params(
-        ("id" = Uuid, Path, description = "Item ID")
+        ("id" = Uuid, Path, description = "Item ID"),
+        ItemReadParams,
+        ("If-None-Match" = Option<String>, Header, description = "Reply 304 if the item still has one of these ETags")
     ),
     responses(
-        (status = 200, description = "Compressed item", body = CompressedItem),
-        (status = 404, description = "Item not found", body = Value),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 200, description = "Compressed item", body = CompressedItem,
+            headers(
+                ("ETag" = String, description = "Strong entity tag of this representation; `raw=true` has its own")
+            )),
+        (status = 304, description = "Item unchanged"),
+        (status = 400, description = "Malformed item ID (`request.invalid_path`) or query (`request.invalid_query`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:read scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Item not found (`item.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:read"]))
 )]
 pub async fn get_item(
-    Path(id): Path<Uuid>,
+    ApiPath(id): ApiPath<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<CompressedItem>, (StatusCode, Json<Value>)> {
-    match get_item_by_id(&state.db_pool, &id.to_string()).await {
-        Ok(item) => Ok(Json(item)),
-        Err(DbError::NotFound) => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Item not found"})),
-        )),
-        Err(e) => {
-            eprintln!("Error getting item: {:?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to get item"})),
-            ))
-        }
+    headers: HeaderMap,
+    ApiQuery(params): ApiQuery<ItemReadParams>,
+) -> Result<Response, AppError> {
+    let repo = repository(&state)?;
+    let item = repo.get_item(id).await?;
+    let raw = params.raw.unwrap_or(false);
+    let etag = representation_etag(&item, raw);
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
     }
+    let item = read_one(&state, repo, item, raw).await?;
+    Ok(([(header::ETAG, etag)], Json(item)).into_response())
 }
 
 /// Create a new compressed item
 ///
-/// Creates a new compressed item with the provided data
+/// Compresses the provided data with the requested algorithm and level (zstd at its
+/// default level when omitted) and stores it as a new item
+///
+/// Pass the data's SHA-256 as `checksum`, or the body's as a `Content-Digest` header,
+/// to have the upload checked before it is stored.
 ///
 /// Response codes:
 /// - 201: Successfully created item
-/// - 400: Bad request
+/// - 400: Invalid base64 data or compression level, or a checksum mismatch
 /// - 500: Internal server error
 #[utoipa::path(
     post,
     path = "/items",
     request_body = CreateCompressedItem,
     responses(
-        (status = 201, description = "Created compressed item", body = CompressedItem),
-        (status = 400, description = "Bad request", body = Value),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 201, description = "Created compressed item", body = CompressedItem,
+            headers(
+                ("ETag" = String, description = "Strong entity tag of the item")
+            )),
+        (status = 400, description = "Malformed JSON (`request.malformed_json`), invalid base64 data, level or checksum (`request.validation_failed`), or `checksum` or `Content-Digest` mismatch (`request.checksum_mismatch`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:write scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:write"]))
 )]
 pub async fn create_item_handler(
     State(state): State<AppState>,
-    Json(payload): Json<CreateCompressedItem>,
-) -> Result<(StatusCode, Json<CompressedItem>), (StatusCode, Json<Value>)> {
-    match create_item(&state.db_pool, payload).await {
-        Ok(item) => Ok((StatusCode::CREATED, Json(item))),
-        Err(e) => {
-            eprintln!("Error creating item: {:?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to create item"})),
-            ))
-        }
-    }
+    ApiJson(payload): ApiJson<CreateCompressedItem>,
+) -> Result<(StatusCode, WithEtag<CompressedItem>), AppError> {
+    let repo = repository(&state)?;
+    let data = decode_data(&payload.data)?;
+    let encoded = compress(&state, repo, data, payload.algorithm.unwrap_or_default(), payload.level).await?;
+    verify_checksum("checksum", payload.checksum.as_deref(), &encoded.original_sha256)?;
+    let item = to_response(repo.create_item(&payload.name, encoded).await?, payload.data)?;
+    Ok((StatusCode::CREATED, with_etag(item)))
 }
 
 /// Update an existing compressed item
 ///
-/// Updates an existing compressed item with the provided data
+/// Renames an item and/or replaces its data. Changing the algorithm or level without
+/// new data recompresses the current data. Send the item's ETag in `If-Match` to
+/// update only the version you read; without it, the last write wins.
 ///
 /// Response codes:
 /// - 200: Successfully updated item
 /// - 404: Item not found
+/// - 412: The item has changed since the `If-Match` ETag
 /// - 500: Internal server error
 #[utoipa::path(
     put,
     path = "/items/{id}",
     params(
-        ("id" = Uuid, Path, description = "Item ID")
+        ("id" = Uuid, Path, description = "Item ID"),
+        ("If-Match" = Option<String>, Header, description = "Update only if the item still has one of these ETags")
     ),
     request_body = UpdateCompressedItem,
     responses(
-        (status = 200, description = "Updated compressed item", body = CompressedItem),
-        (status = 404, description = "Item not found", body = Value),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 200, description = "Updated compressed item", body = CompressedItem,
+            headers(
+                ("ETag" = String, description = "Strong entity tag of the updated item")
+            )),
+        (status = 400, description = "Malformed JSON (`request.malformed_json`), invalid base64 data, level or checksum (`request.validation_failed`), or `checksum` or `Content-Digest` mismatch (`request.checksum_mismatch`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 415, description = "Body is not JSON (`request.unsupported_media_type`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 422, description = "Body does not match the schema (`request.invalid_body`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:write scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Item not found (`item.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Item changed since the `If-Match` ETag (`request.precondition_failed`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:write"]))
 )]
 pub async fn update_item_handler(
-    Path(id): Path<Uuid>,
+    ApiPath(id): ApiPath<Uuid>,
     State(state): State<AppState>,
-    Json(payload): Json<UpdateCompressedItem>,
-) -> Result<Json<CompressedItem>, (StatusCode, Json<Value>)> {
-    match update_item(&state.db_pool, &id.to_string(), payload).await {
-        Ok(item) => Ok(Json(item)),
-        Err(DbError::NotFound) => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Item not found"})),
-        )),
-        Err(e) => {
-            eprintln!("Error updating item: {:?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to update item"})),
-            ))
-        }
+    headers: HeaderMap,
+    ApiJson(payload): ApiJson<UpdateCompressedItem>,
+) -> Result<WithEtag<CompressedItem>, AppError> {
+    let repo = repository(&state)?;
+    if payload.checksum.is_some() && payload.data.is_none() {
+        return Err(AppError::new(ErrorCode::ValidationFailed, "`checksum` is only accepted with `data`"));
+    }
+    if payload.data.is_none() && payload.algorithm.is_none() && payload.level.is_none() {
+        let expected = match headers.contains_key(header::IF_MATCH) {
+            true => if_match(&headers, &repo.get_item(id).await?)?,
+            false => None,
+        };
+        let item = repo.update_item(id, payload.name.as_deref(), None, expected).await?;
+        return Ok(with_etag(read_one(&state, repo, item, false).await?));
     }
+
+    // The current item supplies whatever the request leaves out: the data to
+    // recompress, the algorithm, and the level when the algorithm is unchanged
+    let current = repo.get_item(id).await?;
+    let expected = if_match(&headers, &current)?;
+    let current_algorithm = stored_algorithm(&current)?;
+    let algorithm = payload.algorithm.unwrap_or(current_algorithm);
+    let level = payload
+        .level
+        .or((algorithm == current_algorithm).then_some(current.level));
+    let data = match &payload.data {
+        Some(data) => decode_data(data)?,
+        None => {
+            let dictionaries = state.dictionaries.for_items(repo, std::slice::from_ref(&current)).await?;
+            state
+                .compute
+                .run(move || decompress_stored(&current, &dictionaries))
+                .await??
+        }
+    };
+
+    let encoded = compress(&state, repo, data, algorithm, level).await?;
+    verify_checksum("checksum", payload.checksum.as_deref(), &encoded.original_sha256)?;
+    let item = repo.update_item(id, payload.name.as_deref(), Some(encoded), expected).await?;
+    Ok(with_etag(read_one(&state, repo, item, false).await?))
 }
 
 /// Delete a compressed item
 ///
-/// Deletes a compressed item based on its ID
+/// Moves a compressed item to the trash, from where it can be restored until it is
+/// purged `database.trash_retention_days` later. Send the item's ETag in `If-Match` to
+/// delete only the version you read.
 ///
 /// Response codes:
 /// - 200: Successfully deleted item
 /// - 404: Item not found
+/// - 412: The item has changed since the `If-Match` ETag
 /// - 500: Internal server error
 #[utoipa::path(
     delete,
     path = "/items/{id}",
     params(
-        ("id" = Uuid, Path, description = "Item ID")
+        ("id" = Uuid, Path, description = "Item ID"),
+        ("If-Match" = Option<String>, Header, description = "Delete only if the item still has one of these ETags")
     ),
     responses(
-        (status = 200, description = "Item deleted successfully", body = Value),
-        (status = 404, description = "Item not found", body = Value),
-        (status = 500, description = "Internal server error", body = Value)
-    )
+        (status = 200, description = "Item moved to the trash", body = Value),
+        (status = 400, description = "Malformed item ID (`request.invalid_path`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:write scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Item not found (`item.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 412, description = "Item changed since the `If-Match` ETag (`request.precondition_failed`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:write"]))
 )]
 pub async fn delete_item_handler(
-    Path(id): Path<Uuid>,
+    ApiPath(id): ApiPath<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    match delete_item(&state.db_pool, &id.to_string()).await {
-        Ok(_) => Ok(Json(json!({"message": "Item deleted successfully"}))),
-        Err(DbError::NotFound) => Err((
-            StatusCode::NOT_FOUND,
-            Json(json!({"error": "Item not found"})),
-        )),
-        Err(e) => {
-            eprintln!("Error deleting item: {:?}", e);
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to delete item"})),
-            ))
-        }
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    let repo = repository(&state)?;
+    let expected = match headers.contains_key(header::IF_MATCH) {
+        true => if_match(&headers, &repo.get_item(id).await?)?,
+        false => None,
+    };
+    repo.delete_item(id, expected).await?;
+    Ok(Json(json!({"message": "Item moved to the trash"})))
+}
+
+fn with_etag(item: CompressedItem) -> WithEtag<CompressedItem> {
+    ([(header::ETAG, item.etag.clone())], Json(item))
+}
+
+/// Download a stored image
+///
+/// Returns the bytes of an image `POST /compress` kept, by the `file_id` it answered
+/// with, under a strong ETag taken from their SHA-256
+///
+/// Response codes:
+/// - 200: The image
+/// - 304: Image unchanged since the `If-None-Match` ETag
+/// - 404: Image not found
+/// - 500: Internal server error
+#[utoipa::path(
+    get,
+    path = "/images/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Stored image ID (the `file_id` from `POST /compress`)"),
+        ("If-None-Match" = Option<String>, Header, description = "Reply 304 if the image still has one of these ETags")
+    ),
+    responses(
+        (status = 200, description = "Image bytes, typed by their format", body = [u8], content_type = "image/*",
+            headers(
+                ("ETag" = String, description = "Strong entity tag of the image")
+            )),
+        (status = 304, description = "Image unchanged"),
+        (status = 400, description = "Malformed image ID (`request.invalid_path`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the files:read scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 404, description = "Image not found (`image.not_found`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Internal server error (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 503, description = "Database not enabled (`database.disabled`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["files:read"]))
+)]
+pub async fn get_stored_image(
+    ApiPath(id): ApiPath<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let store = state.images.as_deref().ok_or_else(database_disabled)?;
+    let image = store.get_image(id).await.map_err(|e| match e {
+        DbError::NotFound => AppError::new(ErrorCode::ImageNotFound, "Image not found"),
+        e => e.into(),
+    })?;
+    let etag = image.etag();
+    if not_modified(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, [(header::ETAG, etag)]).into_response());
+    }
+    let content_type = format!("image/{}", image.format);
+    Ok(([(header::CONTENT_TYPE, content_type), (header::ETAG, etag)], image.data).into_response())
+}
+
+/// Entity tag of one representation of an item. The decoded body carries the item's
+/// own tag; the stored, compressed body (`raw=true`) gets a tag of its own so a cache
+/// never answers for one with the other.
+fn representation_etag(item: &StoredItem, raw: bool) -> String {
+    let etag = item.etag();
+    match raw {
+        true => format!("\"{}-raw\"", etag.trim_matches('"')),
+        false => etag,
+    }
+}
+
+/// Whether an `If-Match` or `If-None-Match` value lists `etag`; weak tags (`W/"..."`)
+/// only match when `weak` comparison is allowed
+fn etag_listed(value: &str, etag: &str, weak: bool) -> bool {
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag || (weak && tag.strip_prefix("W/") == Some(etag)))
+}
+
+/// Whether `If-None-Match` says the client already has `etag`
+fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| etag_listed(value, etag, true))
+}
+
+/// Checks `If-Match` against the current item. Returns the `updated_at` the write must
+/// still find, so a change between this check and the write is caught too; `None`
+/// without the header.
+fn if_match(headers: &HeaderMap, current: &StoredItem) -> Result<Option<DateTime<Utc>>, AppError> {
+    let values: Vec<_> = headers.get_all(header::IF_MATCH).iter().collect();
+    if values.is_empty() {
+        return Ok(None);
+    }
+    // Either representation's tag identifies the version the client read
+    let etag = current.etag();
+    let raw_etag = representation_etag(current, true);
+    if !values
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| etag_listed(value, &etag, false) || etag_listed(value, &raw_etag, false))
+    {
+        return Err(AppError::new(
+            ErrorCode::PreconditionFailed,
+            format!("Item has changed; its current ETag is {etag}"),
+        ));
+    }
+    Ok(current.updated_at)
+}
+
+/// Strong entity tag of a page: its items' tags plus everything else in the body
+fn page_etag(items: &[StoredItem], fields: &[&str], raw: bool, total: i64, next_cursor: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    for item in items {
+        hasher.update(item.etag());
+    }
+    hasher.update(fields.join(","));
+    hasher.update([u8::from(raw)]);
+    hasher.update(total.to_be_bytes());
+    hasher.update(next_cursor.unwrap_or_default());
+    let digest: String = hasher.finalize()[..16]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("\"{digest}\"")
+}
+
+fn invalid_query(detail: String) -> AppError {
+    AppError::new(ErrorCode::InvalidQuery, detail)
+}
+
+/// The requested fields, or all of them
+fn parse_fields(raw: Option<&str>) -> Result<Vec<&'static str>, AppError> {
+    let Some(raw) = raw else {
+        return Ok(ITEM_FIELDS.to_vec());
+    };
+    raw.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            ITEM_FIELDS
+                .into_iter()
+                .find(|known| *known == field)
+                .ok_or_else(|| invalid_query(format!("Unknown field `{field}`; expected one of {}", ITEM_FIELDS.join(", "))))
+        })
+        .collect()
+}
+
+fn date_param(name: &str, raw: Option<&str>, end: bool) -> Result<Option<DateTime<Utc>>, AppError> {
+    raw.map(|raw| parse_bound(raw, end).ok_or_else(|| invalid_query(format!("Invalid `{name}` date: {raw}"))))
+        .transpose()
+}
+
+/// The item as JSON with only `fields`
+fn select_fields(item: CompressedItem, fields: &[&str]) -> Result<Value, AppError> {
+    let mut value = serde_json::to_value(item).map_err(|e| AppError::internal("Failed to serialize item", e))?;
+    if let Value::Object(map) = &mut value {
+        map.retain(|key, _| fields.contains(&key.as_str()));
     }
+    Ok(value)
+}
+
+fn decode_data(data: &str) -> Result<Vec<u8>, AppError> {
+    BASE64_STANDARD
+        .decode(data)
+        .map_err(|_| AppError::new(ErrorCode::ValidationFailed, "`data` is not valid base64"))
+}
+
+/// Compresses item data on the compute pool; zstd uses the active dictionary, if any
+async fn compress(
+    state: &AppState,
+    repo: &dyn ItemRepository,
+    data: Vec<u8>,
+    algorithm: CompressionAlgorithm,
+    level: Option<i32>,
+) -> Result<ItemPayload, AppError> {
+    let level = codec::resolve_level(algorithm, level)?;
+    let dictionary = match algorithm {
+        CompressionAlgorithm::Zstd => state.dictionaries.active(repo).await?,
+        _ => None,
+    };
+    Ok(state
+        .compute
+        .run(move || dictionary::compress_item(algorithm, level, dictionary, &data))
+        .await??)
+}
+
+fn stored_algorithm(item: &StoredItem) -> Result<CompressionAlgorithm, AppError> {
+    item.algorithm
+        .parse()
+        .map_err(|e| AppError::internal("Stored item has an unknown algorithm", e))
+}
+
+/// The original data of a stored item; failures mean the row is corrupt
+fn decompress_stored(item: &StoredItem, dictionaries: &Dictionaries) -> Result<Vec<u8>, AppError> {
+    dictionary::decompress_item(stored_algorithm(item)?, item, dictionaries)
+        .map_err(|e| AppError::internal("Failed to decompress stored item", e))
+}
+
+/// Builds the responses for stored items on the compute pool, decompressing them
+/// unless `raw` is set
+async fn read_items(
+    state: &AppState,
+    repo: &dyn ItemRepository,
+    items: Vec<StoredItem>,
+    raw: bool,
+) -> Result<Vec<CompressedItem>, AppError> {
+    let dictionaries = if raw {
+        Dictionaries::new()
+    } else {
+        state.dictionaries.for_items(repo, &items).await?
+    };
+    state
+        .compute
+        .run(move || {
+            items
+                .into_iter()
+                .map(|item| {
+                    let data = if raw {
+                        BASE64_STANDARD.encode(&item.data)
+                    } else {
+                        BASE64_STANDARD.encode(decompress_stored(&item, &dictionaries)?)
+                    };
+                    to_response(item, data)
+                })
+                .collect()
+        })
+        .await?
+}
+
+async fn read_one(
+    state: &AppState,
+    repo: &dyn ItemRepository,
+    item: StoredItem,
+    raw: bool,
+) -> Result<CompressedItem, AppError> {
+    let mut items = read_items(state, repo, vec![item], raw).await?;
+    Ok(items.remove(0))
+}
+
+fn to_response(item: StoredItem, data: String) -> Result<CompressedItem, AppError> {
+    Ok(CompressedItem::from_stored(stored_algorithm(&item)?, item, data))
+}
+
+/// The item repository, or 503 when this instance runs without one
+pub(super) fn repository(state: &AppState) -> Result<&dyn ItemRepository, AppError> {
+    state.items.as_deref().ok_or_else(database_disabled)
+}
+
+fn database_disabled() -> AppError {
+    AppError::new(ErrorCode::DatabaseDisabled, "Items need the database; set database.enabled")
 }
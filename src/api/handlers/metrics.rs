@@ -0,0 +1,20 @@
+use crate::core::models::AppState;
+use axum::{extract::State, http::header, response::IntoResponse};
+
+/// Prometheus metrics
+///
+/// Returns request, byte, compression-ratio and stage-timing metrics in the
+/// Prometheus text exposition format
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses(
+        (status = 200, description = "Metrics in Prometheus text format", body = String, content_type = "text/plain")
+    )
+)]
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        state.metrics.render(),
+    )
+}
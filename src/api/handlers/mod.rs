@@ -1,7 +1,25 @@
-// pub mod items;
+#[cfg(feature = "database")]
+pub mod dictionaries;
+#[cfg(feature = "database")]
+pub mod items;
+pub mod admin;
+pub mod data;
+pub mod fallback;
 pub mod health;
 pub mod image;
+pub mod metrics;
+pub mod stats;
+pub mod usage;
 
-// pub use items::*;
+#[cfg(feature = "database")]
+pub use dictionaries::*;
+#[cfg(feature = "database")]
+pub use items::*;
+pub use admin::*;
+pub use data::*;
+pub use fallback::*;
 pub use health::*;
 pub use image::*;
+pub use metrics::*;
+pub use stats::*;
+pub use usage::*;
@@ -0,0 +1,90 @@
+use crate::api::extract::ApiQuery;
+use crate::core::models::AppState;
+use crate::services::stats::{Granularity, StatsQuery, StatsReport};
+use crate::utils::{AppError, ErrorCode, ProblemDetails};
+use axum::{extract::State, response::Json};
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+/// Query parameters for `GET /stats`
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StatsParams {
+    /// Start of the range, RFC 3339 or `YYYY-MM-DD` (default: 7 days before `to`)
+    pub from: Option<String>,
+    /// End of the range, RFC 3339 or `YYYY-MM-DD` (inclusive day; default: now)
+    pub to: Option<String>,
+    /// Bucket width: `hour` or `day` (default: `day`)
+    pub granularity: Option<Granularity>,
+    /// Only count images of this source format, e.g. `png`
+    pub format: Option<String>,
+    /// Only count requests made with this API key (`anonymous` for none)
+    pub api_key: Option<String>,
+}
+
+/// Compression statistics
+///
+/// Returns totals, per-format, per-API-key and per-bucket statistics for successful
+/// compressions in the requested date range.
+///
+/// Response codes:
+/// - 200: Aggregated statistics
+/// - 400: Invalid date range or parameters
+/// - 401/403: Missing API key or no `admin` scope (when authentication is enabled)
+/// - 500: Statistics storage unavailable
+#[utoipa::path(
+    get,
+    path = "/stats",
+    params(StatsParams),
+    responses(
+        (status = 200, description = "Aggregated statistics", body = StatsReport),
+        (status = 400, description = "Invalid date range (`stats.invalid_range`) or query (`request.invalid_query`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 401, description = "Missing or invalid API key (`auth.missing_key`, `auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 403, description = "API key lacks the admin scope (`auth.insufficient_scope`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Statistics storage unavailable (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = ["admin"]))
+)]
+pub async fn stats_handler(
+    State(state): State<AppState>,
+    ApiQuery(params): ApiQuery<StatsParams>,
+) -> Result<Json<StatsReport>, AppError> {
+    let bad_request = |message: String| AppError::new(ErrorCode::InvalidRange, message);
+
+    let to = match params.to.as_deref() {
+        Some(raw) => parse_bound(raw, true).ok_or_else(|| bad_request(format!("Invalid `to` date: {raw}")))?,
+        None => Utc::now(),
+    };
+    let from = match params.from.as_deref() {
+        Some(raw) => parse_bound(raw, false).ok_or_else(|| bad_request(format!("Invalid `from` date: {raw}")))?,
+        None => to - TimeDelta::days(7),
+    };
+    if from >= to {
+        return Err(bad_request("`from` must be before `to`".to_string()));
+    }
+
+    let query = StatsQuery {
+        granularity: params.granularity.unwrap_or_default(),
+        from,
+        to,
+        format: params.format,
+        api_key: params.api_key,
+    };
+
+    Ok(Json(state.stats.query(&query).await?))
+}
+
+/// Parses an RFC 3339 timestamp or a plain date. A plain date used as the end of
+/// the range covers that whole day.
+pub(super) fn parse_bound(raw: &str, end: bool) -> Option<DateTime<Utc>> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(raw) {
+        return Some(at.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(raw, "%Y-%m-%d").ok()?;
+    let date = if end { date.succ_opt()? } else { date };
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
@@ -0,0 +1,32 @@
+use crate::api::middleware::rate_limit::subject_for;
+use crate::core::models::{AppState, UsageResponse};
+use crate::utils::{AppError, ProblemDetails};
+use axum::{
+    extract::{Request, State},
+    response::Json,
+};
+
+/// Rate-limit and quota usage
+///
+/// Returns the caller's token-bucket state and daily/monthly request and byte usage.
+/// Callers with an API key see the key's usage; anonymous callers see their IP's.
+#[utoipa::path(
+    get,
+    path = "/usage",
+    responses(
+        (status = 200, description = "Current usage and limits", body = UsageResponse),
+        (status = 401, description = "Invalid API key (`auth.invalid_key`)",
+            body = ProblemDetails, content_type = "application/problem+json"),
+        (status = 500, description = "Usage storage unavailable (`internal.error`)",
+            body = ProblemDetails, content_type = "application/problem+json")
+    ),
+    security((), ("api_key" = []))
+)]
+pub async fn usage_handler(
+    State(state): State<AppState>,
+    request: Request,
+) -> Result<Json<UsageResponse>, AppError> {
+    let subject = subject_for(&state, request.headers(), request.extensions());
+
+    Ok(Json(state.rate_limiter.usage(&subject).await?))
+}
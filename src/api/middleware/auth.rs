@@ -0,0 +1,87 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::core::models::{AppState, Scope};
+use crate::services::AuthError;
+use crate::utils::{AppError, ErrorCode};
+
+/// Requires an API key carrying `scope` when authentication is enabled.
+///
+/// The key is read from `Authorization: Bearer <key>` or `X-API-Key`. On success the
+/// caller's [`ApiKeyIdentity`](crate::services::ApiKeyIdentity) is added to the request
+/// extensions for handlers to use.
+pub async fn require_scope(
+    State((state, scope)): State<(AppState, Scope)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !state.auth.enabled() {
+        return next.run(request).await;
+    }
+
+    let Some(key) = presented_key(request.headers()) else {
+        return unauthorized(ErrorCode::MissingApiKey, "Missing API key");
+    };
+
+    match state.auth.authenticate(&key).await {
+        Ok(Some(identity)) if identity.has_scope(scope) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Ok(Some(_)) => {
+            AppError::new(ErrorCode::InsufficientScope, format!("API key lacks the `{scope}` scope")).into_response()
+        }
+        Ok(None) => unauthorized(ErrorCode::InvalidApiKey, "Invalid API key"),
+        Err(e) => lookup_failed(e),
+    }
+}
+
+/// Attaches the caller's identity when a valid API key is presented, without requiring one.
+///
+/// Used by endpoints that serve both anonymous callers and API keys, such as `/usage`.
+/// A key that is presented but invalid is still rejected.
+pub async fn identify(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    if !state.auth.enabled() {
+        return next.run(request).await;
+    }
+    let Some(key) = presented_key(request.headers()) else {
+        return next.run(request).await;
+    };
+
+    match state.auth.authenticate(&key).await {
+        Ok(Some(identity)) => {
+            request.extensions_mut().insert(identity);
+            next.run(request).await
+        }
+        Ok(None) => unauthorized(ErrorCode::InvalidApiKey, "Invalid API key"),
+        Err(e) => lookup_failed(e),
+    }
+}
+
+fn presented_key(headers: &HeaderMap) -> Option<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let api_key = headers.get("x-api-key").and_then(|value| value.to_str().ok());
+
+    bearer
+        .or(api_key)
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(str::to_string)
+}
+
+fn unauthorized(code: ErrorCode, message: &str) -> Response {
+    AppError::new(code, message)
+        .with_header(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"))
+        .into_response()
+}
+
+fn lookup_failed(e: AuthError) -> Response {
+    AppError::internal("Failed to verify API key", e).into_response()
+}
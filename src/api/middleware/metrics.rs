@@ -0,0 +1,59 @@
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::core::models::AppState;
+
+/// Records request count and latency labelled by method, route template and status
+pub async fn track_metrics(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    // Use the route template (`/items/{id}`), not the raw path, to keep label cardinality bounded
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = method_label(request.method());
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    state
+        .metrics
+        .record_request(method, &route, response.status().as_u16(), start.elapsed());
+    response
+}
+
+/// Standard methods keep their name; anything else a client sends is `other`, so
+/// arbitrary method tokens cannot add label values
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::PATCH => "PATCH",
+        Method::DELETE => "DELETE",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_methods_share_one_label() {
+        assert_eq!(method_label(&Method::GET), "GET");
+        assert_eq!(method_label(&Method::DELETE), "DELETE");
+        assert_eq!(method_label(&Method::from_bytes(b"PURGE").unwrap()), "other");
+        assert_eq!(method_label(&Method::from_bytes(b"X-RANDOM-123").unwrap()), "other");
+    }
+}
@@ -0,0 +1,9 @@
+pub mod auth;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+
+pub use auth::{identify, require_scope};
+pub use metrics::track_metrics;
+pub use rate_limit::enforce_rate_limit;
+pub use request_id::{RequestId, X_REQUEST_ID, assign_request_id, request_span};
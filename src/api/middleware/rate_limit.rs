@@ -0,0 +1,70 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Extensions, HeaderMap, HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::warn;
+
+use crate::core::models::AppState;
+use crate::server::ClientAddr;
+use crate::services::ApiKeyIdentity;
+use crate::services::rate_limit::{Admission, LimitStatus, Subject};
+use crate::utils::AppError;
+
+/// Applies token-bucket rate limits and quotas to the caller.
+///
+/// Must run after authentication so API keys get their own limits. Admitted
+/// requests carry their [`Subject`] in the request extensions, so handlers can
+/// count processed bytes against it. Responses include `RateLimit-*` headers when
+/// limits are enforced; rejected requests get 429 with `Retry-After`.
+pub async fn enforce_rate_limit(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let subject = subject_for(&state, request.headers(), request.extensions());
+
+    let status = match state.rate_limiter.check(&subject).await {
+        Ok(Admission::Allowed(status)) => status,
+        Ok(Admission::Limited {
+            kind,
+            status,
+            retry_after_secs,
+        }) => {
+            let mut response = AppError::from(kind)
+                .with_header(header::RETRY_AFTER, HeaderValue::from(retry_after_secs))
+                .into_response();
+            set_headers(response.headers_mut(), status);
+            return response;
+        }
+        Err(e) => {
+            // Rate limiting must not take the service down with its backend
+            warn!("Rate limit check failed, admitting request: {}", e);
+            None
+        }
+    };
+
+    request.extensions_mut().insert(subject);
+    let mut response = next.run(request).await;
+    if let Some(status) = status {
+        set_headers(response.headers_mut(), status);
+    }
+    response
+}
+
+/// The rate-limit subject for a request: its API key, or its client IP
+pub fn subject_for(state: &AppState, headers: &HeaderMap, extensions: &Extensions) -> Subject {
+    let identity = extensions.get::<ApiKeyIdentity>();
+    let peer = extensions
+        .get::<ConnectInfo<ClientAddr>>()
+        .and_then(|ConnectInfo(addr)| addr.0);
+    state.rate_limiter.subject(identity, headers, peer)
+}
+
+fn set_headers(headers: &mut HeaderMap, status: LimitStatus) {
+    let values = [
+        ("ratelimit-limit", status.limit),
+        ("ratelimit-remaining", status.remaining),
+        ("ratelimit-reset", status.reset_secs),
+    ];
+    for (name, value) in values {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+use crate::server::telemetry;
+use crate::utils::errors::REQUEST_ID;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied request id that is propagated rather than replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Id assigned to each request, available to handlers as an extension
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Assigns every request an id, echoed in the `X-Request-Id` response header and
+/// included in error bodies and the request's tracing span.
+///
+/// A well-formed `X-Request-Id` from the client (or a proxy in front of us) is kept,
+/// so one id follows the request across services; otherwise a UUID v7 is generated.
+/// Must be the outermost layer so errors from every other layer carry the id.
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::now_v7().to_string());
+
+    request.extensions_mut().insert(RequestId(id.clone()));
+    let header = HeaderValue::from_str(&id).expect("request ids are visible ASCII");
+    let mut response = REQUEST_ID.scope(id, next.run(request)).await;
+    response.headers_mut().insert(X_REQUEST_ID.clone(), header);
+    response
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// Span for one HTTP request, tagged with its id so every log line can be correlated.
+/// With OTLP export enabled, the span continues the caller's `traceparent` trace.
+pub fn request_span<B>(request: &axum::http::Request<B>) -> tracing::Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|RequestId(id)| id.as_str())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    );
+    telemetry::set_remote_parent(&span, request.headers());
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+    use axum::{Router, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::utils::{AppError, ErrorCode};
+
+    fn app() -> Router {
+        Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/fail", get(|| async { AppError::new(ErrorCode::ValidationFailed, "bad input") }))
+            .layer(middleware::from_fn(assign_request_id))
+    }
+
+    async fn send(uri: &str, id: Option<&str>) -> Response {
+        let mut request = axum::http::Request::builder().uri(uri);
+        if let Some(id) = id {
+            request = request.header(&X_REQUEST_ID, id);
+        }
+        app().oneshot(request.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    fn response_id(response: &Response) -> String {
+        response.headers()[&X_REQUEST_ID].to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn an_incoming_id_is_echoed() {
+        let response = send("/ok", Some("edge-42")).await;
+        assert_eq!(response_id(&response), "edge-42");
+    }
+
+    #[tokio::test]
+    async fn missing_or_invalid_ids_are_replaced() {
+        let too_long = "x".repeat(MAX_REQUEST_ID_LEN + 1);
+        for id in [None, Some(""), Some("has space"), Some(too_long.as_str())] {
+            let response = send("/ok", id).await;
+            let generated = response_id(&response);
+            assert_ne!(Some(generated.as_str()), id);
+            assert_eq!(generated.parse::<Uuid>().unwrap().get_version_num(), 7, "{id:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn error_bodies_carry_the_same_id() {
+        for id in [Some("edge-42"), None] {
+            let response = send("/fail", id).await;
+            let header = response_id(&response);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(problem["request_id"], header.as_str());
+            assert_eq!(problem["code"], "request.validation_failed");
+        }
+    }
+}
@@ -1,4 +1,6 @@
+pub mod extract;
 pub mod handlers;
+pub mod middleware;
 pub mod routes;
 
 pub use routes::create_router;
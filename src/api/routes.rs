@@ -1,26 +1,109 @@
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post},
 };
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::Level;
+use tower_http::trace::TraceLayer;
 
 use crate::api::handlers::{
-    compress_image_handler, 
-    // create_item_handler, delete_item_handler, get_item, get_items,
-    health_check, root, 
-    // update_item_handler,
+    compress_batch_handler, compress_data_handler, compress_image_handler, create_api_key_handler, decompress_data_handler,
+    list_api_keys_handler, method_not_allowed, not_found,
+    revoke_api_key_handler,
+    health_check, liveness_handler, metrics_handler, readiness_handler, root, stats_handler, usage_handler,
 };
-use crate::core::models::AppState;
+#[cfg(feature = "database")]
+use crate::api::handlers::{
+    activate_dictionary_handler, create_item_handler, delete_item_handler, export_items_handler, get_item, get_items, get_stored_image,
+    get_trash, list_dictionaries_handler, restore_item_handler, train_dictionary_handler, update_item_handler,
+};
+use crate::api::handlers::image::MAX_BATCH_IMAGES;
+use crate::api::middleware::{
+    assign_request_id, enforce_rate_limit, identify, request_span, require_scope, track_metrics,
+};
+use crate::core::models::{AppState, Scope};
 use crate::docs::scalar_handler;
+use crate::server::track_in_flight;
+
+pub fn create_router(state: AppState) -> Router {
+    // Base64 inflates uploads by 4/3; leave headroom for the rest of the JSON body
+    let body_limit = state.image_service.max_image_size() as usize / 3 * 4 + 64 * 1024;
+
+    let require = |scope: Scope| middleware::from_fn_with_state((state.clone(), scope), require_scope);
+
+    // Routes that need an API key when `auth.enabled` is set, grouped by required scope.
+    // Rate limits are applied inside authentication so keys get their own limits.
+    let compress = Router::new()
+        .route("/compress", post(compress_image_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), enforce_rate_limit))
+        .route_layer(require(Scope::Compress));
+    // A batch may hold many images, each up to the single-image limit
+    let batch = Router::new()
+        .route("/compress/batch", post(compress_batch_handler))
+        .layer(DefaultBodyLimit::max(body_limit * MAX_BATCH_IMAGES))
+        .route_layer(middleware::from_fn_with_state(state.clone(), enforce_rate_limit))
+        .route_layer(require(Scope::Batch));
+    // Raw data bodies are spooled and limited by the data service itself; this limit
+    // covers base64 JSON bodies
+    let data_body_limit = state.data_service.max_data_size() as usize / 3 * 4 + 64 * 1024;
+    let data = Router::new()
+        .route("/compress/data", post(compress_data_handler))
+        .route("/decompress/data", post(decompress_data_handler))
+        .layer(DefaultBodyLimit::max(data_body_limit))
+        .route_layer(middleware::from_fn_with_state(state.clone(), enforce_rate_limit))
+        .route_layer(require(Scope::Compress));
+    let usage = Router::new()
+        .route("/usage", get(usage_handler))
+        .route_layer(middleware::from_fn_with_state(state.clone(), identify));
+    let admin = Router::new()
+        .route("/stats", get(stats_handler))
+        .route("/admin/keys", get(list_api_keys_handler).post(create_api_key_handler))
+        .route("/admin/keys/{id}", delete(revoke_api_key_handler))
+        .route_layer(require(Scope::Admin));
+
+    // Items share paths between the read and write scopes, split by method
+    #[allow(unused_mut)] // only extended when the database feature is enabled
+    let mut items = Router::new();
+    #[cfg(feature = "database")]
+    {
+        let read = Router::new()
+            .route("/items", get(get_items))
+            .route("/items/trash", get(get_trash))
+            .route("/items/export", get(export_items_handler))
+            .route("/items/{id}", get(get_item))
+            .route("/images/{id}", get(get_stored_image))
+            .route_layer(require(Scope::FilesRead));
+        let write = Router::new()
+            .route("/items", post(create_item_handler))
+            .route("/items/{id}", axum::routing::put(update_item_handler).delete(delete_item_handler))
+            .route("/items/{id}/restore", post(restore_item_handler))
+            .route_layer(require(Scope::FilesWrite));
+        let dictionaries = Router::new()
+            .route("/admin/dictionaries", get(list_dictionaries_handler).post(train_dictionary_handler))
+            .route("/admin/dictionaries/{version}/activate", post(activate_dictionary_handler))
+            .route_layer(require(Scope::Admin));
+        items = items.merge(read).merge(write).merge(dictionaries);
+    }
 
-pub fn create_router() -> Router<AppState> {
     Router::new()
         .route("/", get(root))
         .route("/health", get(health_check))
-        // .route("/items", get(get_items).post(create_item_handler))
-        // .route("/items/{id}", get(get_item).put(update_item_handler).delete(delete_item_handler))
-        .route("/compress", post(compress_image_handler))
+        .route("/health/live", get(liveness_handler))
+        .route("/health/ready", get(readiness_handler))
+        .route("/metrics", get(metrics_handler))
         .route("/scalar", get(scalar_handler))
-        .layer(TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::new().level(Level::INFO)))
+        .merge(compress)
+        .merge(batch)
+        .merge(data)
+        .merge(usage)
+        .merge(admin)
+        .merge(items)
+        .fallback(not_found)
+        .method_not_allowed_fallback(method_not_allowed)
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(middleware::from_fn_with_state(state.clone(), track_metrics))
+        .layer(middleware::from_fn_with_state(state.clone(), track_in_flight))
+        .layer(middleware::from_fn(assign_request_id))
+        .with_state(state)
 }
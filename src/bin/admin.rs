@@ -0,0 +1,1078 @@
+//! Administrative CLI for a Rust Compress API deployment
+//!
+//! Reads the same layered configuration as the server (`--config`, environment,
+//! `--set`). Every command prints JSON instead of text with `--json`; errors go to
+//! standard error and set the exit code listed in `admin --help`.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use serde_json::json;
+
+use rust_compress_api::core::models::{ConflictPolicy, ExportCompression, Scope};
+use rust_compress_api::{AppConfig, core::config::ConfigArgs, services::AuthService};
+
+#[cfg(feature = "database")]
+use std::fs::File;
+#[cfg(feature = "database")]
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+#[cfg(feature = "database")]
+use std::sync::Arc;
+
+#[cfg(feature = "database")]
+use rust_compress_api::core::database::{self, Backend, DbError, ItemRepository, Storage};
+#[cfg(feature = "database")]
+use rust_compress_api::core::models::{ItemCursor, ItemQuery, SortOrder, StoredItem};
+#[cfg(feature = "database")]
+use rust_compress_api::services::ComputePool;
+#[cfg(feature = "database")]
+use rust_compress_api::services::dictionary::{self, DEFAULT_DICTIONARY_SIZE, DEFAULT_SAMPLE_SIZE, DictionaryStore};
+#[cfg(feature = "database")]
+use rust_compress_api::services::integrity::{self, Defect};
+#[cfg(feature = "database")]
+use rust_compress_api::services::transfer::{ExportOptions, ExportSummary, Exporter, Importer, open_export};
+#[cfg(feature = "database")]
+use rust_compress_api::utils::checksum::sha256_hex;
+
+/// The command failed, e.g. the database could not be reached
+const EXIT_FAILURE: u8 = 1;
+/// Invalid arguments or configuration, or a missing confirmation flag
+const EXIT_USAGE: u8 = 2;
+/// The item, dictionary or API key does not exist
+const EXIT_NOT_FOUND: u8 = 3;
+/// `verify` found items or images that are missing or corrupted
+#[cfg(feature = "database")]
+const EXIT_DAMAGED: u8 = 4;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  0  Success
+  1  The command failed
+  2  Invalid arguments or configuration, or a missing confirmation flag
+  3  The item, dictionary or API key does not exist
+  4  verify found items or images that are missing or corrupted";
+
+/// Items read per page when walking the whole table
+#[cfg(feature = "database")]
+const WALK_PAGE_SIZE: u32 = 100;
+
+/// Administer a Rust Compress API deployment
+#[derive(Debug, Parser)]
+#[command(version, about, after_help = EXIT_CODES)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// Print results as JSON
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apply pending database migrations
+    Migrate {
+        /// List applied and pending migrations instead of applying them
+        #[arg(long)]
+        status: bool,
+    },
+    /// List, inspect, count and clear items
+    #[command(subcommand)]
+    Items(ItemCommand),
+    /// Write items, and optionally stored images, as NDJSON with their data in base64
+    Export(ExportArgs),
+    /// Load an NDJSON export, keeping IDs; rerunning an import skips what it already did
+    Import(ImportArgs),
+    /// List and purge items in the trash
+    #[command(subcommand)]
+    Trash(TrashCommand),
+    /// Train, list and activate zstd dictionaries
+    #[command(subcommand)]
+    Dictionaries(DictionaryCommand),
+    /// Create, list and revoke API keys
+    #[command(subcommand)]
+    Keys(KeyCommand),
+    /// Re-hash every item and stored image, and check that items decompress to their
+    /// recorded size and checksum
+    Verify {
+        /// Check items in the trash too
+        #[arg(long)]
+        include_trash: bool,
+        /// Record checksums for intact items and images stored before checksums were kept
+        #[arg(long)]
+        backfill: bool,
+    },
+    /// Item counts and sizes, overall and by algorithm, and the bytes image dedupe saves
+    Stats,
+}
+
+#[derive(Debug, Subcommand)]
+enum ItemCommand {
+    /// List items a page at a time, newest first
+    List(ListArgs),
+    /// Show an item's metadata and check that it decompresses
+    Inspect {
+        id: uuid::Uuid,
+        /// Write the decompressed data to this file
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Count live items
+    Count,
+    /// Move every item to the trash
+    Clear {
+        /// Permanently delete every item instead, the trash included
+        #[arg(long)]
+        hard: bool,
+        /// Confirm clearing
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum TrashCommand {
+    /// List items in the trash a page at a time, newest first
+    List(ListArgs),
+    /// Permanently delete items trashed longer than `database.trash_retention_days`
+    Purge {
+        /// Empty the trash whatever the age of its items
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum DictionaryCommand {
+    /// List trained dictionaries, newest first
+    List,
+    /// Train a dictionary on a random sample of stored items
+    Train {
+        /// Items to sample (default: 1000, at most 100000)
+        #[arg(long, value_name = "N")]
+        samples: Option<u32>,
+        /// Maximum dictionary size in bytes (default: 112640)
+        #[arg(long, value_name = "BYTES")]
+        max_size: Option<u32>,
+        /// Store the dictionary without compressing new items with it
+        #[arg(long)]
+        no_activate: bool,
+    },
+    /// Compress new zstd items with this dictionary version
+    Activate { version: i32 },
+    /// Compress new zstd items without a dictionary
+    Deactivate,
+}
+
+#[derive(Debug, Subcommand)]
+enum KeyCommand {
+    /// Create an API key in the database; the key is printed only once
+    Create {
+        name: String,
+        /// Comma-separated scopes: compress, batch, files:read, files:write, admin
+        #[arg(value_delimiter = ',', required = true)]
+        scopes: Vec<Scope>,
+        /// Generate the key and print its keys-file entry with this ID instead of
+        /// storing it in the database
+        #[arg(long = "static", value_name = "ID")]
+        static_id: Option<String>,
+    },
+    /// List active API keys
+    List,
+    /// Revoke a database API key
+    Revoke { id: String },
+}
+
+#[derive(Debug, Args)]
+struct ListArgs {
+    /// Items per page
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(u32).range(1..=500))]
+    limit: u32,
+    /// Continue after the page that printed this cursor
+    #[arg(long)]
+    cursor: Option<String>,
+    /// Only items whose name starts with this (case-sensitive)
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Only items whose name contains this (case-insensitive)
+    #[arg(long)]
+    contains: Option<String>,
+    /// Oldest first
+    #[arg(long)]
+    oldest_first: bool,
+}
+
+#[derive(Debug, Args)]
+struct ExportArgs {
+    /// Write to this file instead of standard output
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+    /// Wrap the export in gzip or zstd (default: from the output file's `.gz` or
+    /// `.zst` extension, otherwise none)
+    #[arg(long, value_name = "gzip|zstd")]
+    compress: Option<ExportCompression>,
+    /// Export items in the trash too
+    #[arg(long)]
+    include_trash: bool,
+    /// Export stored images after the items
+    #[arg(long)]
+    images: bool,
+    /// Only items whose name starts with this (case-sensitive)
+    #[arg(long)]
+    prefix: Option<String>,
+    /// Only items whose name contains this (case-insensitive)
+    #[arg(long)]
+    contains: Option<String>,
+    /// Only items created at or after this RFC 3339 time
+    #[arg(long, value_name = "TIME")]
+    created_after: Option<DateTime<Utc>>,
+    /// Only items created before this RFC 3339 time
+    #[arg(long, value_name = "TIME")]
+    created_before: Option<DateTime<Utc>>,
+    /// Only items updated at or after this RFC 3339 time
+    #[arg(long, value_name = "TIME")]
+    updated_after: Option<DateTime<Utc>>,
+    /// Only items updated before this RFC 3339 time
+    #[arg(long, value_name = "TIME")]
+    updated_before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Args)]
+struct ImportArgs {
+    /// Export to read, plain or gzip- or zstd-wrapped; standard input when omitted or `-`
+    input: Option<PathBuf>,
+    /// What to do with a record whose ID is taken by a different version: keep the
+    /// stored one, overwrite it, or import the record under a derived ID
+    #[arg(long, value_name = "skip|overwrite|rename", default_value_t = ConflictPolicy::Skip)]
+    on_conflict: ConflictPolicy,
+    /// Start at this line, e.g. the one a failed import stopped at
+    #[arg(long, value_name = "LINE", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    resume_from: u64,
+}
+
+/// Why a command failed, and the exit code to report it with
+#[derive(Debug)]
+struct Failure {
+    code: u8,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: u8, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl<E: std::error::Error> From<E> for Failure {
+    fn from(e: E) -> Self {
+        Self::new(EXIT_FAILURE, e.to_string())
+    }
+}
+
+/// Prints results as text or, with `--json`, as JSON
+struct Output {
+    json: bool,
+}
+
+impl Output {
+    /// Prints `value` as JSON, or calls `text` to print it for people
+    fn emit<T: Serialize>(&self, value: &T, text: impl FnOnce(&T)) -> Result<(), Failure> {
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(value)?);
+        } else {
+            text(value);
+        }
+        Ok(())
+    }
+
+    /// Reports a failure on standard error and returns its exit code
+    fn fail(&self, failure: Failure) -> ExitCode {
+        if self.json {
+            eprintln!("{}", json!({ "error": failure.message, "exit_code": failure.code }));
+        } else {
+            eprintln!("error: {}", failure.message);
+        }
+        ExitCode::from(failure.code)
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenvy::dotenv().ok();
+
+    let cli = Cli::parse();
+    let out = Output { json: cli.json };
+    let config = match AppConfig::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => return out.fail(Failure::new(EXIT_USAGE, e.to_string())),
+    };
+
+    match run(cli.command, &config, &out).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => out.fail(failure),
+    }
+}
+
+async fn run(command: Command, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    match command {
+        Command::Keys(command) => keys(command, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Migrate { status } => migrate(status, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Items(command) => items(command, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Export(args) => export(args, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Import(args) => import(args, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Trash(command) => trash(command, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Dictionaries(command) => dictionaries(command, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Verify { include_trash, backfill } => verify(include_trash, backfill, config, out).await,
+        #[cfg(feature = "database")]
+        Command::Stats => stats(config, out).await,
+        #[cfg(not(feature = "database"))]
+        _ => Err(Failure::new(
+            EXIT_USAGE,
+            "this command needs the database; rebuild with `--features database`",
+        )),
+    }
+}
+
+async fn keys(command: KeyCommand, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    match command {
+        KeyCommand::Create {
+            name,
+            scopes,
+            static_id: Some(id),
+        } => {
+            let (key, entry) = AuthService::static_key_entry(&id, &name, &scopes)?;
+            out.emit(&json!({ "key": key, "entry": entry }), |_| {
+                println!("API key (shown only once): {}", key);
+                println!();
+                println!("Add this entry to the keys file ({}):", keys_file_hint(config));
+                println!();
+                print!("{}", entry);
+            })
+        }
+        KeyCommand::Create { name, scopes, .. } => {
+            let created = key_service(config).await?.create_key(&name, &scopes).await?;
+            out.emit(&created, |created| {
+                println!("Created API key {} ({})", created.info.id, created.info.name);
+                println!("Scopes: {}", join_scopes(&created.info.scopes));
+                println!("API key (shown only once): {}", created.key);
+            })
+        }
+        KeyCommand::List => {
+            let keys = key_service(config).await?.list_keys().await?;
+            out.emit(&keys, |keys| {
+                if keys.is_empty() {
+                    println!("No API keys");
+                }
+                for key in keys {
+                    println!(
+                        "{}  {}  {}  [{}]  ({:?})",
+                        key.id,
+                        key.prefix,
+                        key.name,
+                        join_scopes(&key.scopes),
+                        key.source
+                    );
+                }
+            })
+        }
+        KeyCommand::Revoke { id } => {
+            if !key_service(config).await?.revoke_key(&id).await? {
+                return Err(Failure::new(EXIT_NOT_FOUND, format!("No active API key with id {id}")));
+            }
+            out.emit(&json!({ "revoked": id }), |_| println!("Revoked API key {}", id))
+        }
+    }
+}
+
+/// Key storage as the server sees it: the keys file plus PostgreSQL when enabled
+async fn key_service(config: &AppConfig) -> Result<AuthService, Failure> {
+    let service = AuthService::from_config(&config.auth)?;
+
+    #[cfg(feature = "database")]
+    if config.database.enabled && Backend::from_url(&config.database.url) == Some(Backend::Postgres) {
+        let db_pool = database::create_pool(&config.database.url).await?;
+        return Ok(service.with_database(db_pool));
+    }
+
+    Ok(service)
+}
+
+fn join_scopes(scopes: &[Scope]) -> String {
+    scopes.iter().map(|scope| scope.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+fn keys_file_hint(config: &AppConfig) -> &str {
+    if config.auth.keys_file.is_empty() {
+        "set auth.keys_file"
+    } else {
+        &config.auth.keys_file
+    }
+}
+
+/// The storage `database.url` points at; SQLite is migrated on opening, while
+/// PostgreSQL migrations are left to `admin migrate`
+#[cfg(feature = "database")]
+async fn storage(config: &AppConfig) -> Result<Storage, Failure> {
+    let migrate = Backend::from_url(&config.database.url) == Some(Backend::Sqlite);
+    Ok(database::connect(&config.database.url, migrate).await?)
+}
+
+#[cfg(feature = "database")]
+async fn item_repository(config: &AppConfig) -> Result<Arc<dyn ItemRepository>, Failure> {
+    Ok(storage(config).await?.items)
+}
+
+#[cfg(feature = "database")]
+async fn migrate(status: bool, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    if Backend::from_url(&config.database.url) != Some(Backend::Postgres) {
+        if status {
+            return Err(Failure::new(
+                EXIT_USAGE,
+                "Migration status is only tracked for PostgreSQL; SQLite is migrated whenever it is opened",
+            ));
+        }
+        // SQLite is migrated whenever it is opened, and memory has no schema
+        item_repository(config).await?;
+        return out.emit(&json!({ "up_to_date": true }), |_| println!("Database schema is up to date"));
+    }
+
+    let db_pool = database::create_pool(&config.database.url).await?;
+    if status {
+        let applied = rust_compress_api::services::admin::applied_migrations(&db_pool).await?;
+        let migrations: Vec<_> = database::MIGRATOR
+            .iter()
+            .map(|migration| {
+                json!({
+                    "version": migration.version,
+                    "description": migration.description,
+                    "applied": applied.contains(&migration.version),
+                })
+            })
+            .collect();
+        return out.emit(&migrations, |_| {
+            for migration in database::MIGRATOR.iter() {
+                let state = if applied.contains(&migration.version) { "applied" } else { "pending" };
+                println!("{:04}  {:<8}  {}", migration.version, state, migration.description);
+            }
+        });
+    }
+    database::run_migrations(&db_pool).await?;
+    out.emit(&json!({ "up_to_date": true }), |_| println!("Database schema is up to date"))
+}
+
+#[cfg(feature = "database")]
+async fn items(command: ItemCommand, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    match command {
+        ItemCommand::List(args) => list(args, false, config, out).await,
+        ItemCommand::Inspect { id, output } => {
+            let repo = item_repository(config).await?;
+            let item = match repo.get_item(id).await {
+                Ok(item) => item,
+                Err(DbError::NotFound) => return Err(Failure::new(EXIT_NOT_FOUND, format!("No item with id {id}"))),
+                Err(e) => return Err(e.into()),
+            };
+            let dictionaries = DictionaryStore::new()
+                .available_for_items(repo.as_ref(), std::slice::from_ref(&item))
+                .await?;
+            let checked = integrity::check_item(&item, &dictionaries);
+            if let (Some(path), Ok((_, data))) = (&output, &checked) {
+                std::fs::write(path, data)?;
+            }
+            let problem = checked.as_ref().err();
+            let mut report = item_summary(&item);
+            report["problem"] = json!(problem.map(ToString::to_string));
+            out.emit(&report, |_| {
+                println!("ID:          {}", item.id);
+                println!("Name:        {}", item.name);
+                println!("Algorithm:   {} (level {})", item.algorithm, item.level);
+                if let Some(version) = item.dictionary_version {
+                    println!("Dictionary:  version {}", version);
+                }
+                println!(
+                    "Size:        {} bytes, stored in {} ({})",
+                    item.original_size,
+                    item.compressed_size,
+                    ratio(item.original_size, item.compressed_size)
+                );
+                println!("SHA-256:     {}", item.original_sha256.as_deref().unwrap_or("-"));
+                println!("  stored:    {}", item.compressed_sha256.as_deref().unwrap_or("-"));
+                println!("ETag:        {}", item.etag());
+                println!("Created:     {}", format_time(item.created_at));
+                println!("Updated:     {}", format_time(item.updated_at));
+                match problem {
+                    Some(problem) => println!("Integrity:   {}: {}", problem.kind().to_uppercase(), problem),
+                    None => println!("Integrity:   ok"),
+                }
+                if let (Some(path), None) = (&output, problem) {
+                    println!("Wrote the data to {}", path.display());
+                }
+            })?;
+            match problem {
+                Some(problem) if output.is_some() => {
+                    Err(Failure::new(EXIT_FAILURE, format!("Item {id} cannot be read back: {problem}")))
+                }
+                _ => Ok(()),
+            }
+        }
+        ItemCommand::Count => {
+            let stats = item_repository(config).await?.item_stats().await?;
+            out.emit(&json!({ "items": stats.total_items }), |_| println!("{}", stats.total_items))
+        }
+        ItemCommand::Clear { hard, yes } => {
+            if !yes {
+                let what = if hard {
+                    "This permanently deletes every item, the trash included"
+                } else {
+                    "This moves every item to the trash"
+                };
+                return Err(Failure::new(EXIT_USAGE, format!("{what}; pass --yes to confirm")));
+            }
+            let cleared = item_repository(config).await?.clear_items(hard).await?;
+            let report = if hard { json!({ "deleted": cleared }) } else { json!({ "trashed": cleared }) };
+            out.emit(&report, |_| {
+                if hard {
+                    println!("Permanently deleted {} items", cleared);
+                } else {
+                    println!("Moved {} items to the trash", cleared);
+                }
+            })
+        }
+    }
+}
+
+/// Prints one page of live or trashed items
+#[cfg(feature = "database")]
+async fn list(args: ListArgs, trashed: bool, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let after = match args.cursor.as_deref() {
+        Some(token) => Some(ItemCursor::decode(token).ok_or_else(|| Failure::new(EXIT_USAGE, "Invalid --cursor"))?),
+        None => None,
+    };
+    let query = ItemQuery {
+        name_prefix: args.prefix,
+        name_contains: args.contains,
+        sort: if args.oldest_first { SortOrder::Asc } else { SortOrder::Desc },
+        trashed,
+        after,
+        // One extra row tells whether there is a next page
+        limit: args.limit + 1,
+        ..ItemQuery::default()
+    };
+    let mut items = item_repository(config).await?.list_items(&query).await?;
+    let next_cursor = if items.len() > args.limit as usize {
+        items.truncate(args.limit as usize);
+        items
+            .last()
+            .and_then(|item| Some(ItemCursor { created_at: item.created_at?, id: item.id }))
+            .map(|cursor| cursor.encode())
+    } else {
+        None
+    };
+
+    let page = json!({
+        "items": items.iter().map(item_summary).collect::<Vec<_>>(),
+        "next_cursor": next_cursor,
+    });
+    out.emit(&page, |_| {
+        if items.is_empty() {
+            println!("No items");
+        }
+        for item in &items {
+            let when = if trashed { item.deleted_at } else { item.created_at };
+            println!(
+                "{}  {:<8}  {:>10} -> {:<10}  {}  {}",
+                item.id,
+                item.algorithm,
+                item.original_size,
+                item.compressed_size,
+                format_time(when),
+                item.name
+            );
+        }
+        if let Some(cursor) = &next_cursor {
+            println!("More items: --cursor {}", cursor);
+        }
+    })
+}
+
+#[cfg(feature = "database")]
+async fn export(args: ExportArgs, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let storage = storage(config).await?;
+    let compression = args.compress.or_else(|| {
+        let name = args.output.as_ref()?.file_name()?.to_str()?;
+        ExportCompression::from_file_name(name)
+    });
+    let options = ExportOptions {
+        filter: ItemQuery {
+            name_prefix: args.prefix,
+            name_contains: args.contains,
+            created_after: args.created_after,
+            created_before: args.created_before,
+            updated_after: args.updated_after,
+            updated_before: args.updated_before,
+            ..ItemQuery::default()
+        },
+        include_trash: args.include_trash,
+        images: args.images,
+        compression,
+    };
+    let mut exporter = Exporter::new(
+        storage.items,
+        storage.images,
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(0)),
+        options,
+    )?;
+    let mut writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    while let Some(chunk) = exporter.next_chunk().await? {
+        writer.write_all(&chunk)?;
+    }
+    writer.flush()?;
+    drop(writer);
+
+    let summary = exporter.summary();
+    if args.output.is_some() {
+        out.emit(summary, print_export_summary)
+    } else {
+        // Standard output carries the export itself, so the summary goes to standard error
+        if out.json {
+            eprintln!("{}", serde_json::to_string(summary)?);
+        } else {
+            print_export_summary(summary);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "database")]
+fn print_export_summary(summary: &ExportSummary) {
+    eprintln!("Exported {} items and {} images", summary.items, summary.images);
+    for skipped in &summary.skipped {
+        eprintln!("Skipped damaged {} {}: {}", skipped.kind, skipped.id, skipped.problem);
+    }
+}
+
+#[cfg(feature = "database")]
+async fn import(args: ImportArgs, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let input: Box<dyn BufRead> = match args.input.as_deref() {
+        Some(path) if path.as_os_str() != "-" => Box::new(BufReader::new(File::open(path)?)),
+        _ => Box::new(BufReader::new(io::stdin().lock())),
+    };
+    let reader = open_export(input)?;
+    let storage = storage(config).await?;
+    let mut importer = Importer::new(
+        storage.items,
+        storage.images,
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(0)),
+        args.on_conflict,
+    );
+
+    for (index, line) in reader.lines().enumerate() {
+        let number = index as u64 + 1;
+        if number < args.resume_from {
+            continue;
+        }
+        let failed = |message: String| {
+            Failure::new(
+                EXIT_FAILURE,
+                format!("line {number}: {message}; fix it and rerun with --resume-from {number}"),
+            )
+        };
+        let line = line.map_err(|e| failed(e.to_string()))?;
+        importer.import_line(&line).await.map_err(|e| failed(e.to_string()))?;
+    }
+
+    let summary = importer.summary();
+    out.emit(summary, |summary| {
+        for (kind, counts) in [("items", summary.items), ("images", summary.images)] {
+            println!(
+                "Imported {} {kind}: {} created, {} unchanged, {} skipped, {} overwritten, {} renamed",
+                counts.total(),
+                counts.created,
+                counts.unchanged,
+                counts.skipped,
+                counts.overwritten,
+                counts.renamed
+            );
+        }
+    })
+}
+
+#[cfg(feature = "database")]
+async fn trash(command: TrashCommand, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    match command {
+        TrashCommand::List(args) => list(args, true, config, out).await,
+        TrashCommand::Purge { all } => {
+            let deleted_before = if all {
+                None
+            } else if config.database.trash_retention_days == 0 {
+                return Err(Failure::new(
+                    EXIT_USAGE,
+                    "database.trash_retention_days is 0, so nothing expires; pass --all to empty the trash",
+                ));
+            } else {
+                let retention = chrono::TimeDelta::days(i64::from(config.database.trash_retention_days));
+                Some(chrono::Utc::now() - retention)
+            };
+            let purged = item_repository(config).await?.purge_trash(deleted_before).await?;
+            out.emit(&json!({ "purged": purged }), |_| println!("Purged {} items from the trash", purged))
+        }
+    }
+}
+
+#[cfg(feature = "database")]
+async fn dictionaries(command: DictionaryCommand, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let repo = item_repository(config).await?;
+    match command {
+        DictionaryCommand::List => {
+            let dictionaries = repo.list_dictionaries().await?;
+            out.emit(&dictionaries, |dictionaries| {
+                if dictionaries.is_empty() {
+                    println!("No dictionaries");
+                }
+                for info in dictionaries {
+                    println!(
+                        "{:>4}  {:>8} bytes  {:>6} samples  {}{}",
+                        info.version,
+                        info.size,
+                        info.sample_count,
+                        info.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        if info.active { "  (active)" } else { "" }
+                    );
+                }
+            })
+        }
+        DictionaryCommand::Train {
+            samples,
+            max_size,
+            no_activate,
+        } => {
+            let max_size = max_size.unwrap_or(DEFAULT_DICTIONARY_SIZE);
+            let set = DictionaryStore::new()
+                .training_set(repo.as_ref(), samples.unwrap_or(DEFAULT_SAMPLE_SIZE))
+                .await?;
+            let trained = tokio::task::spawn_blocking(move || dictionary::train(set, max_size)).await??;
+            let info = repo
+                .insert_dictionary(&trained.data, trained.sample_count, trained.sample_bytes, !no_activate)
+                .await?;
+            out.emit(&info, |info| {
+                println!(
+                    "Trained dictionary version {} ({} bytes) from {} items ({} bytes)",
+                    info.version, info.size, info.sample_count, info.sample_bytes
+                );
+                if info.active {
+                    println!("New zstd items are now compressed with it");
+                }
+            })
+        }
+        DictionaryCommand::Activate { version } => match repo.activate_dictionary(version).await {
+            Ok(info) => out.emit(&info, |info| println!("Activated dictionary version {}", info.version)),
+            Err(DbError::NotFound) => Err(Failure::new(
+                EXIT_NOT_FOUND,
+                format!("No dictionary with version {version}"),
+            )),
+            Err(e) => Err(e.into()),
+        },
+        DictionaryCommand::Deactivate => {
+            repo.deactivate_dictionaries().await?;
+            out.emit(&json!({ "active": null }), |_| {
+                println!("New zstd items are compressed without a dictionary")
+            })
+        }
+    }
+}
+
+/// What `verify` found in items or in images
+#[cfg(feature = "database")]
+#[derive(Debug, Default, Serialize)]
+struct VerifyCounts {
+    checked: u64,
+    missing: u64,
+    corrupted: u64,
+    /// Intact, but stored before checksums were kept
+    unhashed: u64,
+    /// Of those, the ones whose checksums `--backfill` recorded
+    backfilled: u64,
+}
+
+#[cfg(feature = "database")]
+impl VerifyCounts {
+    fn add(&mut self, defect: &Defect) {
+        match defect {
+            Defect::Missing(_) => self.missing += 1,
+            Defect::Corrupted(_) => self.corrupted += 1,
+        }
+    }
+
+    fn damaged(&self) -> u64 {
+        self.missing + self.corrupted
+    }
+}
+
+#[cfg(feature = "database")]
+async fn verify(include_trash: bool, backfill: bool, config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let storage = storage(config).await?;
+    let store = DictionaryStore::new();
+
+    let mut items = VerifyCounts::default();
+    let mut images = VerifyCounts::default();
+    let mut damaged = Vec::new();
+    for &trashed in sections(include_trash) {
+        let mut pages = Pages::new(storage.items.as_ref(), trashed);
+        while let Some(page) = pages.next().await? {
+            items.checked += page.len() as u64;
+            let dictionaries = store.available_for_items(storage.items.as_ref(), &page).await?;
+            let (defects, unhashed) = tokio::task::spawn_blocking(move || {
+                let mut defects = Vec::new();
+                let mut unhashed = Vec::new();
+                for item in page {
+                    match integrity::check_item(&item, &dictionaries) {
+                        Ok((_, data)) if item.original_sha256.is_none() || item.compressed_sha256.is_none() => {
+                            unhashed.push((item.id, sha256_hex(&data), sha256_hex(&item.data)));
+                        }
+                        Ok(_) => {}
+                        Err(defect) => defects.push((item, defect)),
+                    }
+                }
+                (defects, unhashed)
+            })
+            .await?;
+            for (item, defect) in defects {
+                items.add(&defect);
+                damaged.push(json!({
+                    "type": "item",
+                    "id": item.id,
+                    "name": item.name,
+                    "defect": defect.kind(),
+                    "problem": defect.to_string(),
+                }));
+            }
+            items.unhashed += unhashed.len() as u64;
+            if backfill {
+                for (id, original_sha256, compressed_sha256) in unhashed {
+                    storage.items.record_checksums(id, &original_sha256, &compressed_sha256).await?;
+                    items.backfilled += 1;
+                }
+            }
+        }
+    }
+
+    let mut after = None;
+    loop {
+        let page = storage.images.list_images(after, WALK_PAGE_SIZE).await?;
+        let done = page.len() < WALK_PAGE_SIZE as usize;
+        after = page.last().map(|image| image.id);
+        images.checked += page.len() as u64;
+        let (defects, unhashed) = tokio::task::spawn_blocking(move || {
+            let mut defects = Vec::new();
+            let mut unhashed = Vec::new();
+            for image in page {
+                match integrity::check_image(&image) {
+                    Ok(()) if image.compressed_sha256.is_none() => unhashed.push((image.id, sha256_hex(&image.data))),
+                    Ok(()) => {}
+                    Err(defect) => defects.push((image.id, defect)),
+                }
+            }
+            (defects, unhashed)
+        })
+        .await?;
+        for (id, defect) in defects {
+            images.add(&defect);
+            damaged.push(json!({
+                "type": "image",
+                "id": id,
+                "defect": defect.kind(),
+                "problem": defect.to_string(),
+            }));
+        }
+        images.unhashed += unhashed.len() as u64;
+        if backfill {
+            for (id, compressed_sha256) in unhashed {
+                storage.images.record_image_checksum(id, &compressed_sha256).await?;
+                images.backfilled += 1;
+            }
+        }
+        if done {
+            break;
+        }
+    }
+
+    let report = json!({ "items": items, "images": images, "damaged": damaged });
+    out.emit(&report, |_| {
+        for record in &damaged {
+            let name = record["name"].as_str().map(|name| format!("  {name}")).unwrap_or_default();
+            println!(
+                "{:<5}  {}{}: {}: {}",
+                record["type"].as_str().unwrap_or_default(),
+                record["id"].as_str().unwrap_or_default(),
+                name,
+                record["defect"].as_str().unwrap_or_default().to_uppercase(),
+                record["problem"].as_str().unwrap_or_default()
+            );
+        }
+        println!(
+            "Checked {} items ({} missing, {} corrupted) and {} images ({} missing, {} corrupted)",
+            items.checked, items.missing, items.corrupted, images.checked, images.missing, images.corrupted
+        );
+        if backfill {
+            println!(
+                "Recorded checksums for {} items and {} images",
+                items.backfilled, images.backfilled
+            );
+        } else if items.unhashed + images.unhashed > 0 {
+            println!(
+                "{} items and {} images have no checksums yet; rerun with --backfill to record them",
+                items.unhashed, images.unhashed
+            );
+        }
+    })?;
+    if damaged.is_empty() {
+        Ok(())
+    } else {
+        Err(Failure::new(
+            EXIT_DAMAGED,
+            format!(
+                "{} items and {} images are missing or corrupted",
+                items.damaged(),
+                images.damaged()
+            ),
+        ))
+    }
+}
+
+#[cfg(feature = "database")]
+async fn stats(config: &AppConfig, out: &Output) -> Result<(), Failure> {
+    let storage = storage(config).await?;
+    let (totals, algorithms, blobs) = tokio::try_join!(
+        storage.items.item_stats(),
+        storage.items.algorithm_stats(),
+        storage.images.blob_stats()
+    )?;
+    out.emit(&json!({ "totals": totals, "algorithms": algorithms, "images": blobs }), |_| {
+        println!("Items:        {} ({} in the trash)", totals.total_items, totals.trashed_items);
+        println!("Stored data:  {} bytes", totals.total_data_size);
+        println!(
+            "Image blobs:  {} ({} bytes, {} references)",
+            blobs.blobs, blobs.stored_bytes, blobs.references
+        );
+        println!("Dedupe saved: {} bytes", blobs.bytes_saved);
+        if algorithms.is_empty() {
+            return;
+        }
+        println!();
+        println!("{:<10} {:>8} {:>14} {:>14} {:>8}", "ALGORITHM", "ITEMS", "ORIGINAL", "COMPRESSED", "RATIO");
+        for stats in &algorithms {
+            println!(
+                "{:<10} {:>8} {:>14} {:>14} {:>8}",
+                stats.algorithm,
+                stats.items,
+                stats.original_size,
+                stats.compressed_size,
+                ratio(stats.original_size, stats.compressed_size)
+            );
+        }
+    })
+}
+
+/// Live items, then the trash when asked for
+#[cfg(feature = "database")]
+fn sections(include_trash: bool) -> &'static [bool] {
+    if include_trash { &[false, true] } else { &[false] }
+}
+
+/// Walks live or trashed items with their data, oldest first
+#[cfg(feature = "database")]
+struct Pages<'a> {
+    repo: &'a dyn ItemRepository,
+    query: ItemQuery,
+    done: bool,
+}
+
+#[cfg(feature = "database")]
+impl<'a> Pages<'a> {
+    fn new(repo: &'a dyn ItemRepository, trashed: bool) -> Self {
+        let query = ItemQuery {
+            sort: SortOrder::Asc,
+            trashed,
+            limit: WALK_PAGE_SIZE,
+            with_data: true,
+            ..ItemQuery::default()
+        };
+        Self {
+            repo,
+            query,
+            done: false,
+        }
+    }
+
+    async fn next(&mut self) -> Result<Option<Vec<StoredItem>>, DbError> {
+        if self.done {
+            return Ok(None);
+        }
+        let items = self.repo.list_items(&self.query).await?;
+        self.done = items.len() < self.query.limit as usize;
+        match items.last() {
+            Some(last) => {
+                self.query.after = Some(ItemCursor {
+                    created_at: last.created_at.unwrap_or_default(),
+                    id: last.id,
+                });
+                Ok(Some(items))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// An item's metadata as the items API reports it, without the data
+#[cfg(feature = "database")]
+fn item_summary(item: &StoredItem) -> serde_json::Value {
+    json!({
+        "id": item.id,
+        "name": item.name,
+        "algorithm": item.algorithm,
+        "level": item.level,
+        "dictionary_version": item.dictionary_version,
+        "original_size": item.original_size,
+        "compressed_size": item.compressed_size,
+        "original_sha256": item.original_sha256,
+        "compressed_sha256": item.compressed_sha256,
+        "etag": item.etag(),
+        "created_at": item.created_at,
+        "updated_at": item.updated_at,
+        "deleted_at": item.deleted_at,
+    })
+}
+
+#[cfg(feature = "database")]
+fn ratio(original_size: i64, compressed_size: i64) -> String {
+    if compressed_size == 0 {
+        return "-".to_string();
+    }
+    format!("{:.2}x", original_size as f64 / compressed_size as f64)
+}
+
+#[cfg(feature = "database")]
+fn format_time(time: Option<chrono::DateTime<chrono::Utc>>) -> String {
+    time.map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
@@ -0,0 +1,373 @@
+//! Layered configuration loading
+//!
+//! Layers are applied in order, each overriding the previous one:
+//!
+//! 1. Built-in defaults (`AppConfig::default()`)
+//! 2. An optional TOML file (`--config <FILE>` or `CONFIG_FILE`)
+//! 3. Environment variables, named after the key path: `server.port` is `SERVER_PORT`,
+//!    `limits.max_image_size` is `LIMITS_MAX_IMAGE_SIZE`
+//! 4. Command-line flags (`--host`, `--port`, `--set key.path=value`)
+//!
+//! Every layer is checked against the shape of the defaults, so unknown keys and
+//! values of the wrong type are collected and reported together.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use serde::Deserialize;
+use toml::{Table, Value};
+
+use super::AppConfig;
+
+/// Command-line flags that feed the configuration, shared by every binary
+#[derive(Debug, Clone, Default, Args)]
+pub struct ConfigArgs {
+    /// Path to a TOML configuration file
+    #[arg(long = "config", value_name = "FILE", env = "CONFIG_FILE", global = true)]
+    pub config_file: Option<PathBuf>,
+
+    /// Override `server.host`
+    #[arg(long, value_name = "HOST", global = true)]
+    pub host: Option<String>,
+
+    /// Override `server.port`
+    #[arg(long, value_name = "PORT", global = true)]
+    pub port: Option<String>,
+
+    /// Override any configuration key, e.g. `--set limits.max_image_size=5242880`
+    #[arg(long = "set", value_name = "KEY=VALUE", global = true)]
+    pub overrides: Vec<String>,
+}
+
+impl ConfigArgs {
+    /// Arguments for callers without a command line: only `CONFIG_FILE` is honored
+    pub fn from_env() -> Self {
+        Self {
+            config_file: std::env::var_os("CONFIG_FILE").map(PathBuf::from),
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to parse config file {}: {message}", path.display())]
+    Parse { path: PathBuf, message: String },
+
+    #[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+    Invalid(Vec<String>),
+}
+
+pub(super) fn load(args: &ConfigArgs) -> Result<AppConfig, ConfigError> {
+    let defaults = Table::try_from(AppConfig::default()).expect("default configuration serializes to TOML");
+    let mut merged = defaults.clone();
+    let mut errors = Vec::new();
+
+    // File layer
+    if let Some(path) = &args.config_file {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let file: Table = contents.parse().map_err(|e: toml::de::Error| ConfigError::Parse {
+            path: path.clone(),
+            message: e.message().to_string(),
+        })?;
+        merge_file(&mut merged, &defaults, file, "", &mut errors);
+    }
+
+    // Environment layer
+    for (key, expected) in leaves(&defaults, "") {
+        let name = env_name(&key);
+        if let Ok(raw) = std::env::var(&name) {
+            match coerce(&raw, expected) {
+                Ok(value) => set_path(&mut merged, &key, value),
+                Err(e) => errors.push(format!("{key} (from {name}): {e}")),
+            }
+        }
+    }
+
+    // Command-line layer
+    let mut overrides = Vec::new();
+    if let Some(host) = &args.host {
+        overrides.push(("server.host".to_string(), host.clone()));
+    }
+    if let Some(port) = &args.port {
+        overrides.push(("server.port".to_string(), port.clone()));
+    }
+    for raw in &args.overrides {
+        match raw.split_once('=') {
+            Some((key, value)) => overrides.push((key.trim().to_string(), value.to_string())),
+            None => errors.push(format!("--set {raw}: expected KEY=VALUE")),
+        }
+    }
+    for (key, raw) in overrides {
+        match get_path(&defaults, &key) {
+            Some(expected) => match coerce(&raw, expected) {
+                Ok(value) => set_path(&mut merged, &key, value),
+                Err(e) => errors.push(format!("{key} (from command line): {e}")),
+            },
+            None => errors.push(format!("{key} (from command line): unknown configuration key")),
+        }
+    }
+
+    // Values that failed to parse were skipped above, so the remaining layers can still be
+    // deserialized and validated and every problem is reported at once
+    match AppConfig::deserialize(Value::Table(merged)) {
+        Ok(config) => {
+            if let Err(ConfigError::Invalid(invalid)) = config.validate() {
+                errors.extend(invalid);
+            }
+            if errors.is_empty() {
+                Ok(config)
+            } else {
+                Err(ConfigError::Invalid(errors))
+            }
+        }
+        Err(e) => {
+            // toml reports the offending key on a trailing "in `key`" line
+            let text = e.to_string();
+            let message = match text.trim().rsplit_once("\nin `") {
+                Some((message, key)) => format!("{}: {message}", key.trim_end_matches('`')),
+                None => text.trim().to_string(),
+            };
+            errors.push(message);
+            Err(ConfigError::Invalid(errors))
+        }
+    }
+}
+
+/// Environment variable name for a dotted key path
+fn env_name(key: &str) -> String {
+    key.replace('.', "_").to_uppercase()
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{prefix}.{key}")
+    }
+}
+
+/// Every non-table value in `table`, keyed by its dotted path
+fn leaves<'a>(table: &'a Table, prefix: &str) -> Vec<(String, &'a Value)> {
+    let mut out = Vec::new();
+    for (key, value) in table {
+        let path = join(prefix, key);
+        match value {
+            Value::Table(inner) => out.extend(leaves(inner, &path)),
+            _ => out.push((path, value)),
+        }
+    }
+    out
+}
+
+fn get_path<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let mut parts = key.split('.');
+    let mut value = table.get(parts.next()?)?;
+    for part in parts {
+        value = value.as_table()?.get(part)?;
+    }
+    Some(value)
+}
+
+fn set_path(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            if let Some(Value::Table(inner)) = table.get_mut(head) {
+                set_path(inner, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Merges a parsed file into `merged`, rejecting keys and types the defaults do not have
+fn merge_file(merged: &mut Table, defaults: &Table, file: Table, prefix: &str, errors: &mut Vec<String>) {
+    for (key, value) in file {
+        let path = join(prefix, &key);
+        let Some(expected) = defaults.get(&key) else {
+            errors.push(format!("{path} (from file): unknown configuration key"));
+            continue;
+        };
+        match (expected, value) {
+            (Value::Table(expected), Value::Table(value)) => {
+                if let Some(Value::Table(target)) = merged.get_mut(&key) {
+                    merge_file(target, expected, value, &path, errors);
+                }
+            }
+            (Value::Float(_), Value::Integer(i)) => {
+                merged.insert(key, Value::Float(i as f64));
+            }
+            (expected, value) if expected.same_type(&value) => {
+                merged.insert(key, value);
+            }
+            (expected, value) => errors.push(format!(
+                "{path} (from file): expected {}, found {}",
+                expected.type_str(),
+                value.type_str()
+            )),
+        }
+    }
+}
+
+/// Parses a raw string from the environment or command line into the type of `expected`
+fn coerce(raw: &str, expected: &Value) -> Result<Value, String> {
+    let trimmed = raw.trim();
+    match expected {
+        Value::String(_) => Ok(Value::String(raw.to_string())),
+        Value::Integer(_) => trimmed
+            .parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| format!("expected an integer, got `{raw}`")),
+        Value::Float(_) => trimmed
+            .parse::<f64>()
+            .map(Value::Float)
+            .map_err(|_| format!("expected a number, got `{raw}`")),
+        Value::Boolean(_) => match trimmed.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" | "on" => Ok(Value::Boolean(true)),
+            "false" | "0" | "no" | "off" => Ok(Value::Boolean(false)),
+            _ => Err(format!("expected a boolean, got `{raw}`")),
+        },
+        Value::Array(_) => match parse_inline(trimmed) {
+            Some(Value::Array(array)) => Ok(Value::Array(array)),
+            // Plain comma-separated lists are accepted for arrays of strings
+            _ if !trimmed.starts_with('[') => Ok(Value::Array(
+                trimmed
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(|item| Value::String(item.to_string()))
+                    .collect(),
+            )),
+            _ => Err(format!("expected a TOML array, got `{raw}`")),
+        },
+        Value::Table(_) => match parse_inline(trimmed) {
+            Some(Value::Table(table)) => Ok(Value::Table(table)),
+            _ => Err(format!("expected a TOML inline table, got `{raw}`")),
+        },
+        Value::Datetime(_) => Err("datetime values can only be set from a file".to_string()),
+    }
+}
+
+/// Parses a TOML value literal such as `[1, 2]` or `{ a = 1 }`
+fn parse_inline(raw: &str) -> Option<Value> {
+    let mut table: Table = format!("value = {raw}").parse().ok()?;
+    table.remove("value")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_file(contents: &str, args: ConfigArgs) -> Result<AppConfig, ConfigError> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, contents).unwrap();
+        load(&ConfigArgs {
+            config_file: Some(path),
+            ..args
+        })
+    }
+
+    fn invalid(result: Result<AppConfig, ConfigError>) -> Vec<String> {
+        match result {
+            Err(ConfigError::Invalid(errors)) => errors,
+            other => panic!("expected an invalid configuration, got {other:?}"),
+        }
+    }
+
+    // These tests only use keys a developer is unlikely to have set in the environment,
+    // which would otherwise take precedence over the file
+
+    #[test]
+    fn the_file_overrides_defaults() {
+        let config = load_file(
+            "[limits]\nmax_image_size = 1024\n\n[rate_limit.anonymous]\nrequests_per_second = 2\nburst = 4\n",
+            ConfigArgs::default(),
+        )
+        .unwrap();
+        assert_eq!(config.limits.max_image_size, 1024);
+        // Integers are accepted where the default is a float
+        assert_eq!(config.rate_limit.anonymous.requests_per_second, 2.0);
+        assert_eq!(config.limits.max_data_size, AppConfig::default().limits.max_data_size);
+    }
+
+    #[test]
+    fn flags_override_the_file() {
+        let config = load_file(
+            "[server]\nport = 8080\nhost = \"127.0.0.1\"\n\n[defaults]\nquality = 50\n",
+            ConfigArgs {
+                port: Some("9090".to_string()),
+                overrides: vec!["server.host=0.0.0.0".to_string(), "defaults.quality=60".to_string()],
+                ..ConfigArgs::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(config.server.port, 9090);
+        assert_eq!(config.server.host, "0.0.0.0");
+        assert_eq!(config.defaults.quality, 60);
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let errors = invalid(load_file(
+            "[server]\nport = \"eighty\"\nbogus = 1\n\n[defaults]\nquality = 0\n",
+            ConfigArgs {
+                overrides: vec!["limits.max_image_size=big".to_string(), "nope.key=1".to_string(), "novalue".to_string()],
+                ..ConfigArgs::default()
+            },
+        ));
+        let expected = [
+            "server.port (from file): expected integer, found string",
+            "server.bogus (from file): unknown configuration key",
+            "limits.max_image_size (from command line): expected an integer, got `big`",
+            "nope.key (from command line): unknown configuration key",
+            "--set novalue: expected KEY=VALUE",
+            "defaults.quality: must be between 1 and 100, got 0",
+        ];
+        for message in expected {
+            assert!(errors.iter().any(|error| error == message), "missing `{message}` in {errors:?}");
+        }
+    }
+
+    #[test]
+    fn unreadable_files_are_reported() {
+        let result = load(&ConfigArgs {
+            config_file: Some(PathBuf::from("/nonexistent/config.toml")),
+            ..ConfigArgs::default()
+        });
+        assert!(matches!(result, Err(ConfigError::Io { .. })));
+        assert!(matches!(load_file("[server", ConfigArgs::default()), Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn environment_names_follow_the_key_path() {
+        assert_eq!(env_name("server.port"), "SERVER_PORT");
+        assert_eq!(env_name("rate_limit.anonymous.burst"), "RATE_LIMIT_ANONYMOUS_BURST");
+    }
+
+    #[test]
+    fn raw_values_are_coerced_to_the_default_type() {
+        assert_eq!(coerce(" 42 ", &Value::Integer(0)), Ok(Value::Integer(42)));
+        assert_eq!(coerce("on", &Value::Boolean(false)), Ok(Value::Boolean(true)));
+        assert_eq!(coerce("0.5", &Value::Float(0.0)), Ok(Value::Float(0.5)));
+        assert!(coerce("maybe", &Value::Boolean(false)).is_err());
+        assert_eq!(
+            coerce("a, b,", &Value::Array(Vec::new())),
+            Ok(Value::Array(vec![Value::String("a".into()), Value::String("b".into())]))
+        );
+        assert_eq!(coerce("[1, 2]", &Value::Array(Vec::new())), Ok(Value::Array(vec![Value::Integer(1), Value::Integer(2)])));
+        assert!(coerce("[1,", &Value::Array(Vec::new())).is_err());
+    }
+}
@@ -1,30 +1,21 @@
+use sqlx::migrate::{MigrateError, Migrator};
 use sqlx::{PgPool, Pool, Postgres};
 use tracing::info;
 
 pub type DbPool = Pool<Postgres>;
 
+/// Versioned schema migrations from `migrations/`, embedded at build time
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
 pub async fn create_pool(database_url: &str) -> Result<DbPool, sqlx::Error> {
     info!("Creating database connection pool");
     PgPool::connect(database_url).await
 }
 
-pub async fn init_database(pool: &DbPool) -> Result<(), sqlx::Error> {
-    info!("Initializing database schema");
-    
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS compressed_items (
-            id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
-            name TEXT NOT NULL,
-            data TEXT NOT NULL,
-            created_at TIMESTAMPTZ DEFAULT NOW(),
-            updated_at TIMESTAMPTZ DEFAULT NOW()
-        )
-        "#,
-    )
-    .execute(pool)
-    .await?;
-
-    info!("Database schema initialized successfully");
+/// Applies every pending migration, recording each in `_sqlx_migrations`
+pub async fn run_migrations(pool: &DbPool) -> Result<(), MigrateError> {
+    info!("Applying database migrations");
+    MIGRATOR.run(pool).await?;
+    info!("Database schema is up to date");
     Ok(())
 }
@@ -0,0 +1,545 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, MutexGuard};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use rand::seq::SliceRandom;
+use uuid::Uuid;
+
+use crate::core::models::{
+    AlgorithmStats, BlobStats, DictionaryInfo, ItemPayload, ItemQuery, ItemStats, NewStoredImage, SortOrder, StoredImage, StoredItem,
+};
+
+use crate::utils::checksum::sha256_hex;
+
+use super::repository::{DbError, ImageStore, ItemRepository};
+
+#[derive(Debug)]
+struct StoredDictionary {
+    info: DictionaryInfo,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    items: HashMap<Uuid, StoredItem>,
+    /// In version order
+    dictionaries: Vec<StoredDictionary>,
+}
+
+/// Items and dictionaries kept in process memory, for tests and throwaway
+/// deployments; nothing survives a restart
+#[derive(Debug, Default)]
+pub struct MemoryItemRepository {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryItemRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MemoryState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl ItemRepository for MemoryItemRepository {
+    async fn list_items(&self, query: &ItemQuery) -> Result<Vec<StoredItem>, DbError> {
+        let state = self.state();
+        let mut items: Vec<&StoredItem> = state
+            .items
+            .values()
+            .filter(|item| matches(item, query))
+            .filter(|item| match query.after {
+                Some(cursor) => {
+                    let key = (item.created_at.unwrap_or_default(), item.id);
+                    let cursor = (cursor.created_at, cursor.id);
+                    match query.sort {
+                        SortOrder::Asc => key > cursor,
+                        SortOrder::Desc => key < cursor,
+                    }
+                }
+                None => true,
+            })
+            .collect();
+        items.sort_by_key(|item| (item.created_at, item.id));
+        if query.sort == SortOrder::Desc {
+            items.reverse();
+        }
+
+        Ok(items
+            .into_iter()
+            .take(query.limit as usize)
+            .map(|item| {
+                let mut item = item.clone();
+                if !query.with_data {
+                    item.data = Vec::new();
+                }
+                item
+            })
+            .collect())
+    }
+
+    async fn count_items(&self, query: &ItemQuery) -> Result<i64, DbError> {
+        let state = self.state();
+        Ok(state.items.values().filter(|item| matches(item, query)).count() as i64)
+    }
+
+    async fn get_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        let state = self.state();
+        state
+            .items
+            .get(&id)
+            .filter(|item| item.deleted_at.is_none())
+            .cloned()
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn find_item(&self, id: Uuid) -> Result<Option<StoredItem>, DbError> {
+        Ok(self.state().items.get(&id).cloned())
+    }
+
+    async fn create_item(&self, name: &str, payload: ItemPayload) -> Result<StoredItem, DbError> {
+        let now = now();
+        let item = StoredItem {
+            id: Uuid::now_v7(),
+            name: name.to_string(),
+            compressed_size: payload.data.len() as i64,
+            data: payload.data,
+            algorithm: payload.algorithm.as_str().to_string(),
+            level: payload.level,
+            dictionary_version: payload.dictionary_version,
+            original_size: payload.original_size as i64,
+            original_sha256: Some(payload.original_sha256),
+            compressed_sha256: Some(payload.compressed_sha256),
+            created_at: Some(now),
+            updated_at: Some(now),
+            deleted_at: None,
+        };
+        self.state().items.insert(item.id, item.clone());
+        Ok(item)
+    }
+
+    async fn put_item(&self, mut item: StoredItem) -> Result<StoredItem, DbError> {
+        let now = now();
+        item.compressed_size = item.data.len() as i64;
+        item.created_at = Some(item.created_at.map_or(now, micros));
+        item.updated_at = Some(item.updated_at.map_or(now, micros));
+        item.deleted_at = item.deleted_at.map(micros);
+        self.state().items.insert(item.id, item.clone());
+        Ok(item)
+    }
+
+    async fn record_checksums(&self, id: Uuid, original_sha256: &str, compressed_sha256: &str) -> Result<(), DbError> {
+        let mut state = self.state();
+        let item = state.items.get_mut(&id).ok_or(DbError::NotFound)?;
+        item.original_sha256 = Some(original_sha256.to_string());
+        item.compressed_sha256 = Some(compressed_sha256.to_string());
+        Ok(())
+    }
+
+    async fn update_item(
+        &self,
+        id: Uuid,
+        name: Option<&str>,
+        payload: Option<ItemPayload>,
+        expected_updated_at: Option<DateTime<Utc>>,
+    ) -> Result<StoredItem, DbError> {
+        let mut state = self.state();
+        let item = live_item(&mut state, id, expected_updated_at)?;
+        if let Some(name) = name {
+            item.name = name.to_string();
+        }
+        if let Some(payload) = payload {
+            item.compressed_size = payload.data.len() as i64;
+            item.data = payload.data;
+            item.algorithm = payload.algorithm.as_str().to_string();
+            item.level = payload.level;
+            item.dictionary_version = payload.dictionary_version;
+            item.original_size = payload.original_size as i64;
+            item.original_sha256 = Some(payload.original_sha256);
+            item.compressed_sha256 = Some(payload.compressed_sha256);
+        }
+        // Strictly later than before, so every write changes the item's ETag
+        item.updated_at = Some(match item.updated_at {
+            Some(previous) => now().max(previous + Duration::microseconds(1)),
+            None => now(),
+        });
+        Ok(item.clone())
+    }
+
+    async fn delete_item(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> Result<(), DbError> {
+        let mut state = self.state();
+        let item = live_item(&mut state, id, expected_updated_at)?;
+        item.deleted_at = Some(now());
+        Ok(())
+    }
+
+    async fn restore_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        let mut state = self.state();
+        let item = state
+            .items
+            .get_mut(&id)
+            .filter(|item| item.deleted_at.is_some())
+            .ok_or(DbError::NotFound)?;
+        item.deleted_at = None;
+        Ok(item.clone())
+    }
+
+    async fn purge_trash(&self, deleted_before: Option<DateTime<Utc>>) -> Result<u64, DbError> {
+        let mut state = self.state();
+        let before = state.items.len();
+        state.items.retain(|_, item| match (item.deleted_at, deleted_before) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(deleted_at), Some(cutoff)) => deleted_at >= cutoff,
+        });
+        Ok((before - state.items.len()) as u64)
+    }
+
+    async fn clear_items(&self, hard: bool) -> Result<u64, DbError> {
+        let mut state = self.state();
+        if hard {
+            let count = state.items.len();
+            state.items.clear();
+            return Ok(count as u64);
+        }
+        let now = now();
+        let mut count = 0;
+        for item in state.items.values_mut().filter(|item| item.deleted_at.is_none()) {
+            item.deleted_at = Some(now);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    async fn item_stats(&self) -> Result<ItemStats, DbError> {
+        let state = self.state();
+        let trashed_items = state.items.values().filter(|item| item.deleted_at.is_some()).count() as i64;
+        Ok(ItemStats {
+            total_items: state.items.len() as i64 - trashed_items,
+            trashed_items,
+            total_data_size: state.items.values().map(|item| item.data.len() as i64).sum(),
+        })
+    }
+
+    async fn algorithm_stats(&self) -> Result<Vec<AlgorithmStats>, DbError> {
+        let state = self.state();
+        let mut by_algorithm = BTreeMap::<&str, AlgorithmStats>::new();
+        for item in state.items.values().filter(|item| item.deleted_at.is_none()) {
+            let stats = by_algorithm.entry(&item.algorithm).or_insert_with(|| AlgorithmStats {
+                algorithm: item.algorithm.clone(),
+                items: 0,
+                original_size: 0,
+                compressed_size: 0,
+            });
+            stats.items += 1;
+            stats.original_size += item.original_size;
+            stats.compressed_size += item.compressed_size;
+        }
+        Ok(by_algorithm.into_values().collect())
+    }
+
+    async fn sample_items(&self, limit: u32) -> Result<Vec<StoredItem>, DbError> {
+        let state = self.state();
+        let mut items: Vec<StoredItem> = state
+            .items
+            .values()
+            .filter(|item| item.deleted_at.is_none())
+            .cloned()
+            .collect();
+        items.shuffle(&mut rand::rng());
+        items.truncate(limit as usize);
+        Ok(items)
+    }
+
+    async fn list_dictionaries(&self) -> Result<Vec<DictionaryInfo>, DbError> {
+        let state = self.state();
+        Ok(state.dictionaries.iter().rev().map(|dictionary| dictionary.info.clone()).collect())
+    }
+
+    async fn get_dictionary_data(&self, version: i32) -> Result<Vec<u8>, DbError> {
+        let state = self.state();
+        state
+            .dictionaries
+            .iter()
+            .find(|dictionary| dictionary.info.version == version)
+            .map(|dictionary| dictionary.data.clone())
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn active_dictionary_version(&self) -> Result<Option<i32>, DbError> {
+        let state = self.state();
+        Ok(state
+            .dictionaries
+            .iter()
+            .find(|dictionary| dictionary.info.active)
+            .map(|dictionary| dictionary.info.version))
+    }
+
+    async fn insert_dictionary(
+        &self,
+        data: &[u8],
+        sample_count: i32,
+        sample_bytes: i64,
+        activate: bool,
+    ) -> Result<DictionaryInfo, DbError> {
+        let mut state = self.state();
+        if activate {
+            deactivate(&mut state);
+        }
+        let info = DictionaryInfo {
+            version: state.dictionaries.last().map_or(1, |dictionary| dictionary.info.version + 1),
+            size: data.len() as i32,
+            sample_count,
+            sample_bytes,
+            active: activate,
+            created_at: now(),
+        };
+        state.dictionaries.push(StoredDictionary {
+            info: info.clone(),
+            data: data.to_vec(),
+        });
+        Ok(info)
+    }
+
+    async fn activate_dictionary(&self, version: i32) -> Result<DictionaryInfo, DbError> {
+        let mut state = self.state();
+        if !state.dictionaries.iter().any(|dictionary| dictionary.info.version == version) {
+            return Err(DbError::NotFound);
+        }
+        let mut activated = None;
+        for dictionary in &mut state.dictionaries {
+            dictionary.info.active = dictionary.info.version == version;
+            if dictionary.info.active {
+                activated = Some(dictionary.info.clone());
+            }
+        }
+        activated.ok_or(DbError::NotFound)
+    }
+
+    async fn deactivate_dictionaries(&self) -> Result<(), DbError> {
+        deactivate(&mut self.state());
+        Ok(())
+    }
+}
+
+fn deactivate(state: &mut MemoryState) {
+    for dictionary in &mut state.dictionaries {
+        dictionary.info.active = false;
+    }
+}
+
+/// A live item for a conditional write, or why there is none
+fn live_item(
+    state: &mut MemoryState,
+    id: Uuid,
+    expected_updated_at: Option<DateTime<Utc>>,
+) -> Result<&mut StoredItem, DbError> {
+    let item = state
+        .items
+        .get_mut(&id)
+        .filter(|item| item.deleted_at.is_none())
+        .ok_or(DbError::NotFound)?;
+    match expected_updated_at {
+        Some(expected) if item.updated_at != Some(expected) => Err(DbError::Conflict),
+        _ => Ok(item),
+    }
+}
+
+/// Whether an item passes the filters of `query`, with the same semantics as the
+/// SQL backends: a case-sensitive name prefix and a case-insensitive substring
+fn matches(item: &StoredItem, query: &ItemQuery) -> bool {
+    let created_at = item.created_at.unwrap_or_default();
+    let updated_at = item.updated_at.unwrap_or_default();
+    query.name_prefix.as_deref().is_none_or(|prefix| item.name.starts_with(prefix))
+        && query
+            .name_contains
+            .as_deref()
+            .is_none_or(|part| item.name.to_lowercase().contains(&part.to_lowercase()))
+        && query.created_after.is_none_or(|after| created_at >= after)
+        && query.created_before.is_none_or(|before| created_at < before)
+        && query.updated_after.is_none_or(|after| updated_at >= after)
+        && query.updated_before.is_none_or(|before| updated_at < before)
+        && item.deleted_at.is_some() == query.trashed
+}
+
+/// The current time at the microsecond precision the SQL backends keep
+fn now() -> DateTime<Utc> {
+    micros(Utc::now())
+}
+
+/// `at` at the microsecond precision the databases keep
+fn micros(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.duration_trunc(Duration::microseconds(1)).unwrap_or(at)
+}
+
+/// Compressed images kept in process memory
+#[derive(Debug, Default)]
+pub struct MemoryImageStore {
+    state: Mutex<ImageState>,
+}
+
+#[derive(Debug, Default)]
+struct ImageState {
+    /// Without their data and thumbnails, which are in `blobs`
+    images: HashMap<Uuid, StoredImage>,
+    blobs: HashMap<String, Blob>,
+}
+
+#[derive(Debug)]
+struct Blob {
+    data: Vec<u8>,
+    ref_count: i64,
+}
+
+impl ImageState {
+    /// Adds a reference to the blob holding `data`, storing it if it is new, and
+    /// returns its name
+    fn acquire(&mut self, data: Vec<u8>) -> String {
+        let sha256 = sha256_hex(&data);
+        self.blobs
+            .entry(sha256.clone())
+            .and_modify(|blob| blob.ref_count += 1)
+            .or_insert(Blob { data, ref_count: 1 });
+        sha256
+    }
+
+    fn release(&mut self, sha256: &str) {
+        if let Some(blob) = self.blobs.get_mut(sha256) {
+            blob.ref_count -= 1;
+            if blob.ref_count <= 0 {
+                self.blobs.remove(sha256);
+            }
+        }
+    }
+
+    /// Moves the data and thumbnail of `image` into blobs and keeps the rest
+    fn insert(&mut self, mut image: StoredImage) -> StoredImage {
+        image.compressed_size = image.data.len() as i64;
+        image.compressed_sha256 = Some(self.acquire(std::mem::take(&mut image.data)));
+        image.thumbnail_sha256 = image.thumbnail.take().map(|thumbnail| self.acquire(thumbnail));
+        if let Some(replaced) = self.images.insert(image.id, image.clone()) {
+            self.release_image(&replaced);
+        }
+        self.resolve(&image)
+    }
+
+    fn release_image(&mut self, image: &StoredImage) {
+        for sha256 in [&image.compressed_sha256, &image.thumbnail_sha256].into_iter().flatten() {
+            self.release(sha256);
+        }
+    }
+
+    /// `image` with its data and thumbnail read back from their blobs
+    fn resolve(&self, image: &StoredImage) -> StoredImage {
+        let blob = |sha256: &Option<String>| sha256.as_ref().and_then(|sha256| self.blobs.get(sha256));
+        let mut image = image.clone();
+        if let Some(blob) = blob(&image.compressed_sha256) {
+            image.data = blob.data.clone();
+        }
+        image.thumbnail = blob(&image.thumbnail_sha256).map(|blob| blob.data.clone());
+        image
+    }
+}
+
+impl MemoryImageStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn state(&self) -> MutexGuard<'_, ImageState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[async_trait]
+impl ImageStore for MemoryImageStore {
+    async fn save_image(&self, image: NewStoredImage) -> Result<StoredImage, DbError> {
+        let image = StoredImage {
+            id: Uuid::now_v7(),
+            format: image.format,
+            data: image.data,
+            original_size: image.original_size as i64,
+            compressed_size: 0,
+            original_sha256: image.original_sha256,
+            compressed_sha256: None,
+            thumbnail: image.thumbnail,
+            thumbnail_sha256: None,
+            options: image.options,
+            created_at: now(),
+        };
+        Ok(self.state().insert(image))
+    }
+
+    async fn get_image(&self, id: Uuid) -> Result<StoredImage, DbError> {
+        let state = self.state();
+        state.images.get(&id).map(|image| state.resolve(image)).ok_or(DbError::NotFound)
+    }
+
+    async fn find_derivative(&self, original_sha256: &str, options: &str) -> Result<Option<StoredImage>, DbError> {
+        let state = self.state();
+        let derivative = state
+            .images
+            .values()
+            .filter(|image| {
+                image.original_sha256.as_deref() == Some(original_sha256) && image.options.as_deref() == Some(options)
+            })
+            .max_by_key(|image| (image.created_at, image.id));
+        Ok(derivative.map(|image| state.resolve(image)))
+    }
+
+    async fn list_images(&self, after: Option<Uuid>, limit: u32) -> Result<Vec<StoredImage>, DbError> {
+        let state = self.state();
+        let mut page: Vec<&StoredImage> = state
+            .images
+            .values()
+            .filter(|image| after.is_none_or(|after| image.id > after))
+            .collect();
+        page.sort_by_key(|image| image.id);
+        Ok(page.into_iter().take(limit as usize).map(|image| state.resolve(image)).collect())
+    }
+
+    async fn put_image(&self, mut image: StoredImage) -> Result<StoredImage, DbError> {
+        image.created_at = micros(image.created_at);
+        Ok(self.state().insert(image))
+    }
+
+    async fn record_image_checksum(&self, id: Uuid, compressed_sha256: &str) -> Result<(), DbError> {
+        let mut state = self.state();
+        let image = state.images.get_mut(&id).ok_or(DbError::NotFound)?;
+        if image.compressed_sha256.is_some() {
+            return Ok(());
+        }
+        image.compressed_sha256 = Some(compressed_sha256.to_string());
+        let data = std::mem::take(&mut image.data);
+        state
+            .blobs
+            .entry(compressed_sha256.to_string())
+            .and_modify(|blob| blob.ref_count += 1)
+            .or_insert(Blob { data, ref_count: 1 });
+        Ok(())
+    }
+
+    async fn delete_image(&self, id: Uuid) -> Result<(), DbError> {
+        let mut state = self.state();
+        let image = state.images.remove(&id).ok_or(DbError::NotFound)?;
+        state.release_image(&image);
+        Ok(())
+    }
+
+    async fn blob_stats(&self) -> Result<BlobStats, DbError> {
+        let state = self.state();
+        let mut stats = BlobStats::default();
+        for blob in state.blobs.values() {
+            let size = blob.data.len() as i64;
+            stats.blobs += 1;
+            stats.references += blob.ref_count;
+            stats.stored_bytes += size;
+            stats.bytes_saved += size * (blob.ref_count - 1);
+        }
+        Ok(stats)
+    }
+}
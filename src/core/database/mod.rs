@@ -1,5 +1,8 @@
-// pub mod connection;
-// pub mod operations;
+pub mod connection;
+pub mod memory;
+pub mod postgres;
+pub mod repository;
+pub mod sqlite;
 
-// pub use connection::{DbPool, create_pool, init_database};
-// pub use operations::*;
+pub use connection::{DbPool, MIGRATOR, create_pool, run_migrations};
+pub use repository::*;
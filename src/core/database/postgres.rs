@@ -0,0 +1,742 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::core::models::{
+    AlgorithmStats, BlobStats, DictionaryInfo, ItemPayload, ItemQuery, ItemStats, NewStoredImage, SortOrder, StoredImage, StoredItem,
+};
+
+use crate::utils::checksum::sha256_hex;
+
+use super::connection::DbPool;
+use super::repository::{DbError, ImageStore, ItemRepository};
+
+const ITEM_COLUMNS: &str = "id, name, data, algorithm, level, dictionary_version, original_size, \
+     compressed_size, original_sha256, compressed_sha256, created_at, updated_at, deleted_at";
+
+/// Item columns without the stored data, which is returned empty
+const ITEM_SUMMARY_COLUMNS: &str = "id, name, ''::BYTEA AS data, algorithm, level, dictionary_version, \
+     original_size, compressed_size, original_sha256, compressed_sha256, created_at, updated_at, deleted_at";
+
+/// Conditions shared by listing and counting; `$1`-`$7` are the `ItemQuery` filters
+const ITEM_FILTERS: &str = r#"
+    ($1::TEXT IS NULL OR name LIKE $1 ESCAPE '\')
+    AND ($2::TEXT IS NULL OR name ILIKE $2 ESCAPE '\')
+    AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+    AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+    AND ($5::TIMESTAMPTZ IS NULL OR updated_at >= $5)
+    AND ($6::TIMESTAMPTZ IS NULL OR updated_at < $6)
+    AND (deleted_at IS NOT NULL) = $7
+"#;
+
+const DICTIONARY_INFO_COLUMNS: &str =
+    "version, octet_length(data) AS size, sample_count, sample_bytes, active, created_at";
+
+/// Image columns with the data and thumbnail read from their blobs, selected from
+/// `IMAGES`. Images stored before checksums were kept still hold their own data.
+const IMAGE_COLUMNS: &str = "i.id, i.format, COALESCE(b.data, i.data) AS data, i.original_size, \
+     i.compressed_size, i.original_sha256, i.compressed_sha256, t.data AS thumbnail, i.thumbnail_sha256, \
+     i.options, i.created_at";
+
+const IMAGES: &str = "stored_images i \
+     LEFT JOIN blobs b ON b.sha256 = i.compressed_sha256 \
+     LEFT JOIN blobs t ON t.sha256 = i.thumbnail_sha256";
+
+/// Items and dictionaries in PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgItemRepository {
+    pool: DbPool,
+}
+
+impl PgItemRepository {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// Why a conditional write matched no row: the item is gone, or it changed
+    async fn missing_or_changed(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> DbError {
+        if expected_updated_at.is_none() {
+            return DbError::NotFound;
+        }
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM compressed_items WHERE id = $1 AND deleted_at IS NULL)",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await;
+        match exists {
+            Ok(true) => DbError::Conflict,
+            Ok(false) => DbError::NotFound,
+            Err(e) => e.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl ItemRepository for PgItemRepository {
+    async fn list_items(&self, query: &ItemQuery) -> Result<Vec<StoredItem>, DbError> {
+        let (prefix_pattern, contains_pattern) = name_patterns(query);
+        let columns = if query.with_data { ITEM_COLUMNS } else { ITEM_SUMMARY_COLUMNS };
+        let (direction, after) = match query.sort {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        let items = sqlx::query_as::<_, StoredItem>(&format!(
+            r#"
+            SELECT {columns} FROM compressed_items
+            WHERE {ITEM_FILTERS}
+              AND ($8::TIMESTAMPTZ IS NULL OR (created_at, id) {after} ($8, $9))
+            ORDER BY created_at {direction}, id {direction}
+            LIMIT $10
+            "#
+        ))
+        .bind(prefix_pattern)
+        .bind(contains_pattern)
+        .bind(query.created_after)
+        .bind(query.created_before)
+        .bind(query.updated_after)
+        .bind(query.updated_before)
+        .bind(query.trashed)
+        .bind(query.after.map(|cursor| cursor.created_at))
+        .bind(query.after.map(|cursor| cursor.id))
+        .bind(i64::from(query.limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    async fn count_items(&self, query: &ItemQuery) -> Result<i64, DbError> {
+        let (prefix_pattern, contains_pattern) = name_patterns(query);
+        let count = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM compressed_items WHERE {ITEM_FILTERS}"))
+            .bind(prefix_pattern)
+            .bind(contains_pattern)
+            .bind(query.created_after)
+            .bind(query.created_before)
+            .bind(query.updated_after)
+            .bind(query.updated_before)
+            .bind(query.trashed)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn get_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        let item = sqlx::query_as::<_, StoredItem>(&format!(
+            "SELECT {ITEM_COLUMNS} FROM compressed_items WHERE id = $1 AND deleted_at IS NULL"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DbError::NotFound)?;
+
+        Ok(item)
+    }
+
+    async fn find_item(&self, id: Uuid) -> Result<Option<StoredItem>, DbError> {
+        let item = sqlx::query_as::<_, StoredItem>(&format!("SELECT {ITEM_COLUMNS} FROM compressed_items WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(item)
+    }
+
+    async fn create_item(&self, name: &str, payload: ItemPayload) -> Result<StoredItem, DbError> {
+        let compressed_size = payload.data.len() as i64;
+        let created_item = sqlx::query_as::<_, StoredItem>(&format!(
+            r#"
+            INSERT INTO compressed_items
+                (name, data, algorithm, level, dictionary_version, original_size, compressed_size,
+                 original_sha256, compressed_sha256)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(name)
+        .bind(payload.data)
+        .bind(payload.algorithm.as_str())
+        .bind(payload.level)
+        .bind(payload.dictionary_version)
+        .bind(payload.original_size as i64)
+        .bind(compressed_size)
+        .bind(payload.original_sha256)
+        .bind(payload.compressed_sha256)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(created_item)
+    }
+
+    async fn put_item(&self, item: StoredItem) -> Result<StoredItem, DbError> {
+        let compressed_size = item.data.len() as i64;
+        let stored = sqlx::query_as::<_, StoredItem>(&format!(
+            r#"
+            INSERT INTO compressed_items
+                (id, name, data, algorithm, level, dictionary_version, original_size, compressed_size,
+                 original_sha256, compressed_sha256, created_at, updated_at, deleted_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, COALESCE($11, NOW()), COALESCE($12, NOW()), $13)
+            ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                data = EXCLUDED.data,
+                algorithm = EXCLUDED.algorithm,
+                level = EXCLUDED.level,
+                dictionary_version = EXCLUDED.dictionary_version,
+                original_size = EXCLUDED.original_size,
+                compressed_size = EXCLUDED.compressed_size,
+                original_sha256 = EXCLUDED.original_sha256,
+                compressed_sha256 = EXCLUDED.compressed_sha256,
+                created_at = EXCLUDED.created_at,
+                updated_at = EXCLUDED.updated_at,
+                deleted_at = EXCLUDED.deleted_at
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(item.id)
+        .bind(item.name)
+        .bind(item.data)
+        .bind(item.algorithm)
+        .bind(item.level)
+        .bind(item.dictionary_version)
+        .bind(item.original_size)
+        .bind(compressed_size)
+        .bind(item.original_sha256)
+        .bind(item.compressed_sha256)
+        .bind(item.created_at)
+        .bind(item.updated_at)
+        .bind(item.deleted_at)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(stored)
+    }
+
+    async fn record_checksums(&self, id: Uuid, original_sha256: &str, compressed_sha256: &str) -> Result<(), DbError> {
+        let result = sqlx::query(
+            "UPDATE compressed_items SET original_sha256 = $2, compressed_sha256 = $3 WHERE id = $1",
+        )
+        .bind(id)
+        .bind(original_sha256)
+        .bind(compressed_sha256)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn update_item(
+        &self,
+        id: Uuid,
+        name: Option<&str>,
+        payload: Option<ItemPayload>,
+        expected_updated_at: Option<DateTime<Utc>>,
+    ) -> Result<StoredItem, DbError> {
+        let compressed_size = payload.as_ref().map(|payload| payload.data.len() as i64);
+        let (data, algorithm, level, dictionary_version, original_size, checksums) = match payload {
+            Some(payload) => (
+                Some(payload.data),
+                Some(payload.algorithm.as_str()),
+                Some(payload.level),
+                payload.dictionary_version,
+                Some(payload.original_size as i64),
+                Some((payload.original_sha256, payload.compressed_sha256)),
+            ),
+            None => (None, None, None, None, None, None),
+        };
+        let (original_sha256, compressed_sha256) = checksums.unzip();
+        let updated_item = sqlx::query_as::<_, StoredItem>(&format!(
+            r#"
+            UPDATE compressed_items
+            SET name = COALESCE($2, name),
+                data = COALESCE($3, data),
+                algorithm = COALESCE($4, algorithm),
+                level = COALESCE($5, level),
+                original_size = COALESCE($6, original_size),
+                compressed_size = COALESCE($7, compressed_size),
+                original_sha256 = COALESCE($10, original_sha256),
+                compressed_sha256 = COALESCE($11, compressed_sha256),
+                -- A new payload replaces the dictionary too, including with none
+                dictionary_version = CASE WHEN $3 IS NULL THEN dictionary_version ELSE $8 END,
+                -- Strictly later than before, so every write changes the item's ETag
+                updated_at = GREATEST(clock_timestamp(), updated_at + INTERVAL '1 microsecond')
+            WHERE id = $1
+              AND deleted_at IS NULL
+              AND ($9::TIMESTAMPTZ IS NULL OR updated_at = $9)
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .bind(name)
+        .bind(data)
+        .bind(algorithm)
+        .bind(level)
+        .bind(original_size)
+        .bind(compressed_size)
+        .bind(dictionary_version)
+        .bind(expected_updated_at)
+        .bind(original_sha256)
+        .bind(compressed_sha256)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match updated_item {
+            Some(item) => Ok(item),
+            None => Err(self.missing_or_changed(id, expected_updated_at).await),
+        }
+    }
+
+    async fn delete_item(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE compressed_items SET deleted_at = clock_timestamp()
+            WHERE id = $1
+              AND deleted_at IS NULL
+              AND ($2::TIMESTAMPTZ IS NULL OR updated_at = $2)
+            "#,
+        )
+        .bind(id)
+        .bind(expected_updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(self.missing_or_changed(id, expected_updated_at).await);
+        }
+
+        Ok(())
+    }
+
+    async fn restore_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        let item = sqlx::query_as::<_, StoredItem>(&format!(
+            r#"
+            UPDATE compressed_items SET deleted_at = NULL
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or(DbError::NotFound)?;
+
+        Ok(item)
+    }
+
+    async fn purge_trash(&self, deleted_before: Option<DateTime<Utc>>) -> Result<u64, DbError> {
+        let result = sqlx::query(
+            "DELETE FROM compressed_items WHERE deleted_at IS NOT NULL AND ($1::TIMESTAMPTZ IS NULL OR deleted_at < $1)",
+        )
+        .bind(deleted_before)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn clear_items(&self, hard: bool) -> Result<u64, DbError> {
+        let sql = if hard {
+            "DELETE FROM compressed_items"
+        } else {
+            "UPDATE compressed_items SET deleted_at = clock_timestamp() WHERE deleted_at IS NULL"
+        };
+        let result = sqlx::query(sql).execute(&self.pool).await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn item_stats(&self) -> Result<ItemStats, DbError> {
+        let (total_items, trashed_items, total_data_size) = sqlx::query_as::<_, (i64, i64, i64)>(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE deleted_at IS NULL),
+                COUNT(*) FILTER (WHERE deleted_at IS NOT NULL),
+                COALESCE(SUM(LENGTH(data)), 0)::BIGINT
+            FROM compressed_items
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ItemStats {
+            total_items,
+            trashed_items,
+            total_data_size,
+        })
+    }
+
+    async fn algorithm_stats(&self) -> Result<Vec<AlgorithmStats>, DbError> {
+        let rows = sqlx::query_as::<_, (String, i64, i64, i64)>(
+            r#"
+            SELECT algorithm, COUNT(*), SUM(original_size)::BIGINT, SUM(compressed_size)::BIGINT
+            FROM compressed_items
+            WHERE deleted_at IS NULL
+            GROUP BY algorithm
+            ORDER BY algorithm
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.into_iter().map(algorithm_stats).collect())
+    }
+
+    async fn sample_items(&self, limit: u32) -> Result<Vec<StoredItem>, DbError> {
+        let items = sqlx::query_as::<_, StoredItem>(&format!(
+            "SELECT {ITEM_COLUMNS} FROM compressed_items WHERE deleted_at IS NULL ORDER BY random() LIMIT $1"
+        ))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(items)
+    }
+
+    async fn list_dictionaries(&self) -> Result<Vec<DictionaryInfo>, DbError> {
+        let dictionaries = sqlx::query_as::<_, DictionaryInfo>(&format!(
+            "SELECT {DICTIONARY_INFO_COLUMNS} FROM zstd_dictionaries ORDER BY version DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(dictionaries)
+    }
+
+    async fn get_dictionary_data(&self, version: i32) -> Result<Vec<u8>, DbError> {
+        sqlx::query_scalar("SELECT data FROM zstd_dictionaries WHERE version = $1")
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn active_dictionary_version(&self) -> Result<Option<i32>, DbError> {
+        let version = sqlx::query_scalar("SELECT version FROM zstd_dictionaries WHERE active")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(version)
+    }
+
+    async fn insert_dictionary(
+        &self,
+        data: &[u8],
+        sample_count: i32,
+        sample_bytes: i64,
+        activate: bool,
+    ) -> Result<DictionaryInfo, DbError> {
+        let mut tx = self.pool.begin().await?;
+        if activate {
+            sqlx::query("UPDATE zstd_dictionaries SET active = FALSE WHERE active")
+                .execute(&mut *tx)
+                .await?;
+        }
+        let dictionary = sqlx::query_as::<_, DictionaryInfo>(&format!(
+            r#"
+            INSERT INTO zstd_dictionaries (data, sample_count, sample_bytes, active)
+            VALUES ($1, $2, $3, $4)
+            RETURNING {DICTIONARY_INFO_COLUMNS}
+            "#
+        ))
+        .bind(data)
+        .bind(sample_count)
+        .bind(sample_bytes)
+        .bind(activate)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(dictionary)
+    }
+
+    async fn activate_dictionary(&self, version: i32) -> Result<DictionaryInfo, DbError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE zstd_dictionaries SET active = FALSE WHERE active AND version <> $1")
+            .bind(version)
+            .execute(&mut *tx)
+            .await?;
+        let dictionary = sqlx::query_as::<_, DictionaryInfo>(&format!(
+            "UPDATE zstd_dictionaries SET active = TRUE WHERE version = $1 RETURNING {DICTIONARY_INFO_COLUMNS}"
+        ))
+        .bind(version)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DbError::NotFound)?;
+        tx.commit().await?;
+
+        Ok(dictionary)
+    }
+
+    async fn deactivate_dictionaries(&self) -> Result<(), DbError> {
+        sqlx::query("UPDATE zstd_dictionaries SET active = FALSE WHERE active")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+fn algorithm_stats((algorithm, items, original_size, compressed_size): (String, i64, i64, i64)) -> AlgorithmStats {
+    AlgorithmStats {
+        algorithm,
+        items,
+        original_size,
+        compressed_size,
+    }
+}
+
+/// `LIKE` patterns for the name prefix and substring filters
+fn name_patterns(query: &ItemQuery) -> (Option<String>, Option<String>) {
+    (
+        query.name_prefix.as_deref().map(|prefix| format!("{}%", escape_like(prefix))),
+        query.name_contains.as_deref().map(|part| format!("%{}%", escape_like(part))),
+    )
+}
+
+/// Escapes `LIKE` wildcards so user input matches literally
+fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Compressed images in PostgreSQL
+#[derive(Debug, Clone)]
+pub struct PgImageStore {
+    pool: DbPool,
+}
+
+impl PgImageStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ImageStore for PgImageStore {
+    async fn save_image(&self, image: NewStoredImage) -> Result<StoredImage, DbError> {
+        let mut tx = self.pool.begin().await?;
+        let compressed_sha256 = acquire_blob(&mut tx, &image.data).await?;
+        let thumbnail_sha256 = match &image.thumbnail {
+            Some(thumbnail) => Some(acquire_blob(&mut tx, thumbnail).await?),
+            None => None,
+        };
+        let id: Uuid = sqlx::query_scalar(
+            r#"
+            INSERT INTO stored_images
+                (format, data, original_size, compressed_size, original_sha256, compressed_sha256, thumbnail_sha256, options)
+            VALUES ($1, ''::BYTEA, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(image.format)
+        .bind(image.original_size as i64)
+        .bind(image.data.len() as i64)
+        .bind(image.original_sha256)
+        .bind(compressed_sha256)
+        .bind(thumbnail_sha256)
+        .bind(image.options)
+        .fetch_one(&mut *tx)
+        .await?;
+        let stored = fetch_image(&mut tx, id).await?.ok_or(DbError::NotFound)?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn get_image(&self, id: Uuid) -> Result<StoredImage, DbError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_image(&mut conn, id).await?.ok_or(DbError::NotFound)
+    }
+
+    async fn find_derivative(&self, original_sha256: &str, options: &str) -> Result<Option<StoredImage>, DbError> {
+        let image = sqlx::query_as::<_, StoredImage>(&format!(
+            r#"
+            SELECT {IMAGE_COLUMNS} FROM {IMAGES}
+            WHERE i.original_sha256 = $1 AND i.options = $2
+            ORDER BY i.created_at DESC, i.id DESC
+            LIMIT 1
+            "#
+        ))
+        .bind(original_sha256)
+        .bind(options)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(image)
+    }
+
+    async fn list_images(&self, after: Option<Uuid>, limit: u32) -> Result<Vec<StoredImage>, DbError> {
+        let images = sqlx::query_as::<_, StoredImage>(&format!(
+            "SELECT {IMAGE_COLUMNS} FROM {IMAGES} WHERE ($1::UUID IS NULL OR i.id > $1) ORDER BY i.id LIMIT $2"
+        ))
+        .bind(after)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(images)
+    }
+
+    async fn put_image(&self, image: StoredImage) -> Result<StoredImage, DbError> {
+        let mut tx = self.pool.begin().await?;
+        // Take the new references before dropping the old ones, which may be the same blobs
+        let compressed_sha256 = acquire_blob(&mut tx, &image.data).await?;
+        let thumbnail_sha256 = match &image.thumbnail {
+            Some(thumbnail) => Some(acquire_blob(&mut tx, thumbnail).await?),
+            None => None,
+        };
+        let replaced: Option<(Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT compressed_sha256, thumbnail_sha256 FROM stored_images WHERE id = $1 FOR UPDATE")
+                .bind(image.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO stored_images
+                (id, format, data, original_size, compressed_size, original_sha256, compressed_sha256,
+                 thumbnail_sha256, options, created_at)
+            VALUES ($1, $2, ''::BYTEA, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (id) DO UPDATE SET
+                format = EXCLUDED.format,
+                data = EXCLUDED.data,
+                original_size = EXCLUDED.original_size,
+                compressed_size = EXCLUDED.compressed_size,
+                original_sha256 = EXCLUDED.original_sha256,
+                compressed_sha256 = EXCLUDED.compressed_sha256,
+                thumbnail_sha256 = EXCLUDED.thumbnail_sha256,
+                options = EXCLUDED.options,
+                created_at = EXCLUDED.created_at
+            "#,
+        )
+        .bind(image.id)
+        .bind(image.format)
+        .bind(image.original_size)
+        .bind(image.data.len() as i64)
+        .bind(image.original_sha256)
+        .bind(compressed_sha256)
+        .bind(thumbnail_sha256)
+        .bind(image.options)
+        .bind(image.created_at)
+        .execute(&mut *tx)
+        .await?;
+        if let Some((compressed_sha256, thumbnail_sha256)) = replaced {
+            release_blobs(&mut tx, [compressed_sha256, thumbnail_sha256]).await?;
+        }
+        let stored = fetch_image(&mut tx, image.id).await?.ok_or(DbError::NotFound)?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn record_image_checksum(&self, id: Uuid, compressed_sha256: &str) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        let recorded: Option<String> =
+            sqlx::query_scalar("SELECT compressed_sha256 FROM stored_images WHERE id = $1 FOR UPDATE")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(DbError::NotFound)?;
+        if recorded.is_some() {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO blobs (sha256, data, size, ref_count)
+            SELECT $2, data, octet_length(data), 1 FROM stored_images WHERE id = $1
+            ON CONFLICT (sha256) DO UPDATE SET ref_count = blobs.ref_count + 1
+            "#,
+        )
+        .bind(id)
+        .bind(compressed_sha256)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE stored_images SET compressed_sha256 = $2, data = ''::BYTEA WHERE id = $1")
+            .bind(id)
+            .bind(compressed_sha256)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_image(&self, id: Uuid) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        let (compressed_sha256, thumbnail_sha256): (Option<String>, Option<String>) =
+            sqlx::query_as("DELETE FROM stored_images WHERE id = $1 RETURNING compressed_sha256, thumbnail_sha256")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(DbError::NotFound)?;
+        release_blobs(&mut tx, [compressed_sha256, thumbnail_sha256]).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn blob_stats(&self) -> Result<BlobStats, DbError> {
+        let (blobs, references, stored_bytes, bytes_saved): (i64, i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT
+                COUNT(*),
+                COALESCE(SUM(ref_count), 0)::BIGINT,
+                COALESCE(SUM(size), 0)::BIGINT,
+                COALESCE(SUM(size * (ref_count - 1)), 0)::BIGINT
+            FROM blobs
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(BlobStats {
+            blobs,
+            references,
+            stored_bytes,
+            bytes_saved,
+        })
+    }
+}
+
+async fn fetch_image(conn: &mut PgConnection, id: Uuid) -> Result<Option<StoredImage>, sqlx::Error> {
+    sqlx::query_as::<_, StoredImage>(&format!("SELECT {IMAGE_COLUMNS} FROM {IMAGES} WHERE i.id = $1"))
+        .bind(id)
+        .fetch_optional(conn)
+        .await
+}
+
+/// Adds a reference to the blob holding `data`, storing it if it is new, and returns
+/// its name
+async fn acquire_blob(conn: &mut PgConnection, data: &[u8]) -> Result<String, sqlx::Error> {
+    let sha256 = sha256_hex(data);
+    sqlx::query(
+        r#"
+        INSERT INTO blobs (sha256, data, size, ref_count) VALUES ($1, $2, $3, 1)
+        ON CONFLICT (sha256) DO UPDATE SET ref_count = blobs.ref_count + 1
+        "#,
+    )
+    .bind(&sha256)
+    .bind(data)
+    .bind(data.len() as i64)
+    .execute(conn)
+    .await?;
+    Ok(sha256)
+}
+
+/// Drops a reference to each named blob, deleting those no image refers to anymore
+async fn release_blobs(conn: &mut PgConnection, blobs: [Option<String>; 2]) -> Result<(), sqlx::Error> {
+    for sha256 in blobs.into_iter().flatten() {
+        sqlx::query("UPDATE blobs SET ref_count = ref_count - 1 WHERE sha256 = $1")
+            .bind(&sha256)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM blobs WHERE sha256 = $1 AND ref_count <= 0")
+            .bind(&sha256)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::migrate::MigrateError;
+use uuid::Uuid;
+
+use crate::core::models::{
+    AlgorithmStats, BlobStats, DictionaryInfo, ItemPayload, ItemQuery, ItemStats, NewStoredImage, StoredImage, StoredItem,
+};
+
+use super::connection::{DbPool, create_pool, run_migrations};
+use super::memory::{MemoryImageStore, MemoryItemRepository};
+use super::postgres::{PgImageStore, PgItemRepository};
+use super::sqlite::{SqliteImageStore, SqliteItemRepository, connect_sqlite};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error("Not found")]
+    NotFound,
+    #[error("Item was changed by another request")]
+    Conflict,
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Storage for items, their trash and the zstd dictionaries they are compressed with
+#[async_trait]
+pub trait ItemRepository: Debug + Send + Sync {
+    /// One page of items matching `query`, in creation order and then by ID
+    async fn list_items(&self, query: &ItemQuery) -> Result<Vec<StoredItem>, DbError>;
+
+    /// Number of items matching the filters of `query`, ignoring its cursor and limit
+    async fn count_items(&self, query: &ItemQuery) -> Result<i64, DbError>;
+
+    /// A live item; trashed items are `NotFound`
+    async fn get_item(&self, id: Uuid) -> Result<StoredItem, DbError>;
+
+    /// An item whether live or in the trash, with its data
+    async fn find_item(&self, id: Uuid) -> Result<Option<StoredItem>, DbError>;
+
+    async fn create_item(&self, name: &str, payload: ItemPayload) -> Result<StoredItem, DbError>;
+
+    /// Writes `item` as given, ID and timestamps included, replacing any item with the
+    /// same ID; used to import items from another deployment
+    async fn put_item(&self, item: StoredItem) -> Result<StoredItem, DbError>;
+
+    /// Records the checksums of an item stored before they were kept; `updated_at`
+    /// stays as it is, since the item itself has not changed
+    async fn record_checksums(&self, id: Uuid, original_sha256: &str, compressed_sha256: &str) -> Result<(), DbError>;
+
+    /// Renames an item and/or replaces its payload; `None` leaves the field unchanged.
+    /// With `expected_updated_at`, fails with `Conflict` if the item has changed since.
+    /// Every update moves `updated_at` strictly forward, so the item's ETag changes.
+    async fn update_item(
+        &self,
+        id: Uuid,
+        name: Option<&str>,
+        payload: Option<ItemPayload>,
+        expected_updated_at: Option<DateTime<Utc>>,
+    ) -> Result<StoredItem, DbError>;
+
+    /// Moves an item to the trash; with `expected_updated_at`, fails with `Conflict` if
+    /// the item has changed since
+    async fn delete_item(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> Result<(), DbError>;
+
+    /// Takes an item back out of the trash
+    async fn restore_item(&self, id: Uuid) -> Result<StoredItem, DbError>;
+
+    /// Permanently deletes items moved to the trash before `deleted_before`, or every
+    /// trashed item when it is `None`
+    async fn purge_trash(&self, deleted_before: Option<DateTime<Utc>>) -> Result<u64, DbError>;
+
+    /// Moves every live item to the trash, or with `hard` permanently deletes every
+    /// item, trashed or not
+    async fn clear_items(&self, hard: bool) -> Result<u64, DbError>;
+
+    async fn item_stats(&self) -> Result<ItemStats, DbError>;
+
+    /// Live item totals for each algorithm in use, by algorithm name
+    async fn algorithm_stats(&self) -> Result<Vec<AlgorithmStats>, DbError>;
+
+    /// A random sample of live items to train a dictionary on
+    async fn sample_items(&self, limit: u32) -> Result<Vec<StoredItem>, DbError>;
+
+    /// Every dictionary, newest first
+    async fn list_dictionaries(&self) -> Result<Vec<DictionaryInfo>, DbError>;
+
+    /// The bytes of one dictionary version
+    async fn get_dictionary_data(&self, version: i32) -> Result<Vec<u8>, DbError>;
+
+    async fn active_dictionary_version(&self) -> Result<Option<i32>, DbError>;
+
+    /// Stores a new dictionary version, optionally making it the active one
+    async fn insert_dictionary(
+        &self,
+        data: &[u8],
+        sample_count: i32,
+        sample_bytes: i64,
+        activate: bool,
+    ) -> Result<DictionaryInfo, DbError>;
+
+    /// Makes `version` the active dictionary
+    async fn activate_dictionary(&self, version: i32) -> Result<DictionaryInfo, DbError>;
+
+    /// Stops compressing new items with a dictionary
+    async fn deactivate_dictionaries(&self) -> Result<(), DbError>;
+}
+
+/// Storage for compressed images. Image data and thumbnails are kept in blobs keyed by
+/// their SHA-256, each stored once and counting the images that refer to it; a blob
+/// goes when its last image does.
+#[async_trait]
+pub trait ImageStore: Debug + Send + Sync {
+    /// Stores an image, recording the size and SHA-256 of its data
+    async fn save_image(&self, image: NewStoredImage) -> Result<StoredImage, DbError>;
+
+    async fn get_image(&self, id: Uuid) -> Result<StoredImage, DbError>;
+
+    /// The latest image compressed from the source with hex SHA-256 `original_sha256`
+    /// using `options`, if there is one
+    async fn find_derivative(&self, original_sha256: &str, options: &str) -> Result<Option<StoredImage>, DbError>;
+
+    /// One page of images in ID order, starting after `after`
+    async fn list_images(&self, after: Option<Uuid>, limit: u32) -> Result<Vec<StoredImage>, DbError>;
+
+    /// Writes `image` as given, ID and timestamp included, replacing any image with the
+    /// same ID; used to import images from another deployment
+    async fn put_image(&self, image: StoredImage) -> Result<StoredImage, DbError>;
+
+    /// Records the checksum of an image stored before checksums were kept, moving its
+    /// data into the blob of that name; an image that has one keeps it
+    async fn record_image_checksum(&self, id: Uuid, compressed_sha256: &str) -> Result<(), DbError>;
+
+    async fn delete_image(&self, id: Uuid) -> Result<(), DbError>;
+
+    /// How many blobs there are, and how many bytes sharing them saves
+    async fn blob_stats(&self) -> Result<BlobStats, DbError>;
+}
+
+/// Where items and images are kept, chosen by the scheme of `database.url`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// `postgres://` or `postgresql://`
+    Postgres,
+    /// `sqlite:`, e.g. `sqlite://data/items.db` or `sqlite::memory:`
+    Sqlite,
+    /// `memory:`; nothing survives a restart
+    Memory,
+}
+
+impl Backend {
+    pub fn from_url(url: &str) -> Option<Self> {
+        let scheme = url.split_once(':')?.0;
+        match scheme {
+            "postgres" | "postgresql" => Some(Backend::Postgres),
+            "sqlite" => Some(Backend::Sqlite),
+            "memory" => Some(Backend::Memory),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectError {
+    /// Carries only the URL's scheme, so credentials never reach logs
+    #[error("Unsupported database URL scheme `{0}`; expected postgres://, sqlite: or memory:")]
+    UnsupportedUrl(String),
+    #[error("Failed to connect to the database: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to apply database migrations: {0}")]
+    Migrate(#[from] MigrateError),
+}
+
+/// Item and image storage opened from `database.url`
+#[derive(Debug, Clone)]
+pub struct Storage {
+    pub backend: Backend,
+    pub items: Arc<dyn ItemRepository>,
+    pub images: Arc<dyn ImageStore>,
+    /// The PostgreSQL pool, for statistics, API keys and shared rate limits, which are
+    /// only kept in PostgreSQL
+    pub pool: Option<DbPool>,
+}
+
+/// The scheme of a database URL, the only part safe to show in an error
+fn url_scheme(url: &str) -> String {
+    match url.split_once(':') {
+        Some((scheme, _)) => scheme.to_string(),
+        None => "(none)".to_string(),
+    }
+}
+
+/// Opens the storage `url` points at, applying pending migrations first when
+/// `migrate` is set
+pub async fn connect(url: &str, migrate: bool) -> Result<Storage, ConnectError> {
+    let backend = Backend::from_url(url).ok_or_else(|| ConnectError::UnsupportedUrl(url_scheme(url)))?;
+    let storage = match backend {
+        Backend::Postgres => {
+            let pool = create_pool(url).await?;
+            if migrate {
+                run_migrations(&pool).await?;
+            }
+            Storage {
+                backend,
+                items: Arc::new(PgItemRepository::new(pool.clone())),
+                images: Arc::new(PgImageStore::new(pool.clone())),
+                pool: Some(pool),
+            }
+        }
+        Backend::Sqlite => {
+            let pool = connect_sqlite(url, migrate).await?;
+            Storage {
+                backend,
+                items: Arc::new(SqliteItemRepository::new(pool.clone())),
+                images: Arc::new(SqliteImageStore::new(pool)),
+                pool: None,
+            }
+        }
+        Backend::Memory => Storage {
+            backend,
+            items: Arc::new(MemoryItemRepository::new()),
+            images: Arc::new(MemoryImageStore::new()),
+            pool: None,
+        },
+    };
+    Ok(storage)
+}
@@ -0,0 +1,845 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::migrate::Migrator;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::{Row, SqliteConnection};
+use uuid::Uuid;
+
+use crate::core::models::{
+    AlgorithmStats, BlobStats, DictionaryInfo, ItemPayload, ItemQuery, ItemStats, NewStoredImage, SortOrder, StoredImage, StoredItem,
+};
+
+use crate::utils::checksum::sha256_hex;
+
+use super::repository::{ConnectError, DbError, ImageStore, ItemRepository};
+
+/// Migrations for the SQLite schema, kept apart from the PostgreSQL ones
+pub static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+const ITEM_COLUMNS: &str = "id, name, data, algorithm, level, dictionary_version, original_size, \
+     compressed_size, original_sha256, compressed_sha256, created_at, updated_at, deleted_at";
+
+/// Item columns without the stored data, which is returned empty
+const ITEM_SUMMARY_COLUMNS: &str = "id, name, X'' AS data, algorithm, level, dictionary_version, \
+     original_size, compressed_size, original_sha256, compressed_sha256, created_at, updated_at, deleted_at";
+
+/// Conditions shared by listing and counting; `?1`-`?7` are the `ItemQuery` filters
+const ITEM_FILTERS: &str = r#"
+    (?1 IS NULL OR substr(name, 1, length(?1)) = ?1)
+    AND (?2 IS NULL OR instr(lower(name), lower(?2)) > 0)
+    AND (?3 IS NULL OR created_at >= ?3)
+    AND (?4 IS NULL OR created_at < ?4)
+    AND (?5 IS NULL OR updated_at >= ?5)
+    AND (?6 IS NULL OR updated_at < ?6)
+    AND (deleted_at IS NOT NULL) = ?7
+"#;
+
+const DICTIONARY_INFO_COLUMNS: &str = "version, length(data) AS size, sample_count, sample_bytes, active, created_at";
+
+/// Image columns with the data and thumbnail read from their blobs, selected from
+/// `IMAGES`. Images stored before checksums were kept still hold their own data.
+const IMAGE_COLUMNS: &str = "i.id, i.format, COALESCE(b.data, i.data) AS data, i.original_size, \
+     i.compressed_size, i.original_sha256, i.compressed_sha256, t.data AS thumbnail, i.thumbnail_sha256, \
+     i.options, i.created_at";
+
+const IMAGES: &str = "stored_images i \
+     LEFT JOIN blobs b ON b.sha256 = i.compressed_sha256 \
+     LEFT JOIN blobs t ON t.sha256 = i.thumbnail_sha256";
+
+/// Opens a SQLite database, creating the file if needed. An in-memory database
+/// lives only as long as its connection, so the pool keeps exactly one open.
+pub async fn connect_sqlite(url: &str, migrate: bool) -> Result<SqlitePool, ConnectError> {
+    let options = SqliteConnectOptions::from_str(url)?
+        .create_if_missing(true)
+        .foreign_keys(true);
+    let in_memory = url.contains(":memory:") || url.contains("mode=memory");
+    let pool_options = if in_memory {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new().max_connections(5)
+    };
+    let pool = pool_options.connect_with(options).await?;
+    if migrate {
+        SQLITE_MIGRATOR.run(&pool).await?;
+    }
+    Ok(pool)
+}
+
+/// Items and dictionaries in SQLite
+#[derive(Debug, Clone)]
+pub struct SqliteItemRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteItemRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Why a conditional write matched no row: the item is gone, or it changed
+    async fn missing_or_changed(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> DbError {
+        if expected_updated_at.is_none() {
+            return DbError::NotFound;
+        }
+        let exists = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM compressed_items WHERE id = ?1 AND deleted_at IS NULL)",
+        )
+        .bind(id.hyphenated().to_string())
+        .fetch_one(&self.pool)
+        .await;
+        match exists {
+            Ok(true) => DbError::Conflict,
+            Ok(false) => DbError::NotFound,
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn fetch_item(&self, id: Uuid, live: bool) -> Result<Option<StoredItem>, DbError> {
+        let condition = if live { "deleted_at IS NULL" } else { "deleted_at IS NOT NULL" };
+        let row = sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM compressed_items WHERE id = ?1 AND {condition}"
+        ))
+        .bind(id.hyphenated().to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(item_from_row).transpose().map_err(Into::into)
+    }
+}
+
+#[async_trait]
+impl ItemRepository for SqliteItemRepository {
+    async fn list_items(&self, query: &ItemQuery) -> Result<Vec<StoredItem>, DbError> {
+        let columns = if query.with_data { ITEM_COLUMNS } else { ITEM_SUMMARY_COLUMNS };
+        let (direction, after) = match query.sort {
+            SortOrder::Asc => ("ASC", ">"),
+            SortOrder::Desc => ("DESC", "<"),
+        };
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {columns} FROM compressed_items
+            WHERE {ITEM_FILTERS}
+              AND (?8 IS NULL OR (created_at, id) {after} (?8, ?9))
+            ORDER BY created_at {direction}, id {direction}
+            LIMIT ?10
+            "#
+        ))
+        .bind(query.name_prefix.as_deref())
+        .bind(query.name_contains.as_deref())
+        .bind(query.created_after.map(micros))
+        .bind(query.created_before.map(micros))
+        .bind(query.updated_after.map(micros))
+        .bind(query.updated_before.map(micros))
+        .bind(query.trashed)
+        .bind(query.after.map(|cursor| micros(cursor.created_at)))
+        .bind(query.after.map(|cursor| cursor.id.hyphenated().to_string()))
+        .bind(i64::from(query.limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(item_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn count_items(&self, query: &ItemQuery) -> Result<i64, DbError> {
+        let count = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM compressed_items WHERE {ITEM_FILTERS}"))
+            .bind(query.name_prefix.as_deref())
+            .bind(query.name_contains.as_deref())
+            .bind(query.created_after.map(micros))
+            .bind(query.created_before.map(micros))
+            .bind(query.updated_after.map(micros))
+            .bind(query.updated_before.map(micros))
+            .bind(query.trashed)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count)
+    }
+
+    async fn get_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        self.fetch_item(id, true).await?.ok_or(DbError::NotFound)
+    }
+
+    async fn find_item(&self, id: Uuid) -> Result<Option<StoredItem>, DbError> {
+        let row = sqlx::query(&format!("SELECT {ITEM_COLUMNS} FROM compressed_items WHERE id = ?1"))
+            .bind(id.hyphenated().to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.as_ref().map(item_from_row).transpose()?)
+    }
+
+    async fn create_item(&self, name: &str, payload: ItemPayload) -> Result<StoredItem, DbError> {
+        let compressed_size = payload.data.len() as i64;
+        let now = micros(Utc::now());
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO compressed_items
+                (id, name, data, algorithm, level, dictionary_version, original_size, compressed_size,
+                 original_sha256, compressed_sha256, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?11)
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::now_v7().hyphenated().to_string())
+        .bind(name)
+        .bind(payload.data)
+        .bind(payload.algorithm.as_str())
+        .bind(payload.level)
+        .bind(payload.dictionary_version)
+        .bind(payload.original_size as i64)
+        .bind(compressed_size)
+        .bind(payload.original_sha256)
+        .bind(payload.compressed_sha256)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(item_from_row(&row)?)
+    }
+
+    async fn put_item(&self, item: StoredItem) -> Result<StoredItem, DbError> {
+        let compressed_size = item.data.len() as i64;
+        let now = Utc::now();
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO compressed_items
+                (id, name, data, algorithm, level, dictionary_version, original_size, compressed_size,
+                 original_sha256, compressed_sha256, created_at, updated_at, deleted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+            ON CONFLICT (id) DO UPDATE SET
+                name = excluded.name,
+                data = excluded.data,
+                algorithm = excluded.algorithm,
+                level = excluded.level,
+                dictionary_version = excluded.dictionary_version,
+                original_size = excluded.original_size,
+                compressed_size = excluded.compressed_size,
+                original_sha256 = excluded.original_sha256,
+                compressed_sha256 = excluded.compressed_sha256,
+                created_at = excluded.created_at,
+                updated_at = excluded.updated_at,
+                deleted_at = excluded.deleted_at
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(item.id.hyphenated().to_string())
+        .bind(item.name)
+        .bind(item.data)
+        .bind(item.algorithm)
+        .bind(item.level)
+        .bind(item.dictionary_version)
+        .bind(item.original_size)
+        .bind(compressed_size)
+        .bind(item.original_sha256)
+        .bind(item.compressed_sha256)
+        .bind(micros(item.created_at.unwrap_or(now)))
+        .bind(micros(item.updated_at.unwrap_or(now)))
+        .bind(item.deleted_at.map(micros))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(item_from_row(&row)?)
+    }
+
+    async fn record_checksums(&self, id: Uuid, original_sha256: &str, compressed_sha256: &str) -> Result<(), DbError> {
+        let result = sqlx::query(
+            "UPDATE compressed_items SET original_sha256 = ?2, compressed_sha256 = ?3 WHERE id = ?1",
+        )
+        .bind(id.hyphenated().to_string())
+        .bind(original_sha256)
+        .bind(compressed_sha256)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn update_item(
+        &self,
+        id: Uuid,
+        name: Option<&str>,
+        payload: Option<ItemPayload>,
+        expected_updated_at: Option<DateTime<Utc>>,
+    ) -> Result<StoredItem, DbError> {
+        let compressed_size = payload.as_ref().map(|payload| payload.data.len() as i64);
+        let (data, algorithm, level, dictionary_version, original_size, checksums) = match payload {
+            Some(payload) => (
+                Some(payload.data),
+                Some(payload.algorithm.as_str()),
+                Some(payload.level),
+                payload.dictionary_version,
+                Some(payload.original_size as i64),
+                Some((payload.original_sha256, payload.compressed_sha256)),
+            ),
+            None => (None, None, None, None, None, None),
+        };
+        let (original_sha256, compressed_sha256) = checksums.unzip();
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE compressed_items
+            SET name = COALESCE(?2, name),
+                data = COALESCE(?3, data),
+                algorithm = COALESCE(?4, algorithm),
+                level = COALESCE(?5, level),
+                original_size = COALESCE(?6, original_size),
+                compressed_size = COALESCE(?7, compressed_size),
+                original_sha256 = COALESCE(?11, original_sha256),
+                compressed_sha256 = COALESCE(?12, compressed_sha256),
+                -- A new payload replaces the dictionary too, including with none
+                dictionary_version = CASE WHEN ?3 IS NULL THEN dictionary_version ELSE ?8 END,
+                -- Strictly later than before, so every write changes the item's ETag
+                updated_at = MAX(?10, updated_at + 1)
+            WHERE id = ?1
+              AND deleted_at IS NULL
+              AND (?9 IS NULL OR updated_at = ?9)
+            RETURNING {ITEM_COLUMNS}
+            "#
+        ))
+        .bind(id.hyphenated().to_string())
+        .bind(name)
+        .bind(data)
+        .bind(algorithm)
+        .bind(level)
+        .bind(original_size)
+        .bind(compressed_size)
+        .bind(dictionary_version)
+        .bind(expected_updated_at.map(micros))
+        .bind(micros(Utc::now()))
+        .bind(original_sha256)
+        .bind(compressed_sha256)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(item_from_row(&row)?),
+            None => Err(self.missing_or_changed(id, expected_updated_at).await),
+        }
+    }
+
+    async fn delete_item(&self, id: Uuid, expected_updated_at: Option<DateTime<Utc>>) -> Result<(), DbError> {
+        let result = sqlx::query(
+            r#"
+            UPDATE compressed_items SET deleted_at = ?3
+            WHERE id = ?1
+              AND deleted_at IS NULL
+              AND (?2 IS NULL OR updated_at = ?2)
+            "#,
+        )
+        .bind(id.hyphenated().to_string())
+        .bind(expected_updated_at.map(micros))
+        .bind(micros(Utc::now()))
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(self.missing_or_changed(id, expected_updated_at).await);
+        }
+
+        Ok(())
+    }
+
+    async fn restore_item(&self, id: Uuid) -> Result<StoredItem, DbError> {
+        let result = sqlx::query("UPDATE compressed_items SET deleted_at = NULL WHERE id = ?1 AND deleted_at IS NOT NULL")
+            .bind(id.hyphenated().to_string())
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DbError::NotFound);
+        }
+
+        self.get_item(id).await
+    }
+
+    async fn purge_trash(&self, deleted_before: Option<DateTime<Utc>>) -> Result<u64, DbError> {
+        let result =
+            sqlx::query("DELETE FROM compressed_items WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)")
+                .bind(deleted_before.map(micros))
+                .execute(&self.pool)
+                .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    async fn clear_items(&self, hard: bool) -> Result<u64, DbError> {
+        let result = if hard {
+            sqlx::query("DELETE FROM compressed_items").execute(&self.pool).await?
+        } else {
+            sqlx::query("UPDATE compressed_items SET deleted_at = ?1 WHERE deleted_at IS NULL")
+                .bind(micros(Utc::now()))
+                .execute(&self.pool)
+                .await?
+        };
+
+        Ok(result.rows_affected())
+    }
+
+    async fn item_stats(&self) -> Result<ItemStats, DbError> {
+        let (total_items, trashed_items, total_data_size) = sqlx::query_as::<_, (i64, i64, i64)>(
+            r#"
+            SELECT
+                COUNT(*) FILTER (WHERE deleted_at IS NULL),
+                COUNT(*) FILTER (WHERE deleted_at IS NOT NULL),
+                COALESCE(SUM(length(data)), 0)
+            FROM compressed_items
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ItemStats {
+            total_items,
+            trashed_items,
+            total_data_size,
+        })
+    }
+
+    async fn algorithm_stats(&self) -> Result<Vec<AlgorithmStats>, DbError> {
+        let rows = sqlx::query_as::<_, (String, i64, i64, i64)>(
+            r#"
+            SELECT algorithm, COUNT(*), SUM(original_size), SUM(compressed_size)
+            FROM compressed_items
+            WHERE deleted_at IS NULL
+            GROUP BY algorithm
+            ORDER BY algorithm
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(algorithm, items, original_size, compressed_size)| AlgorithmStats {
+                algorithm,
+                items,
+                original_size,
+                compressed_size,
+            })
+            .collect())
+    }
+
+    async fn sample_items(&self, limit: u32) -> Result<Vec<StoredItem>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {ITEM_COLUMNS} FROM compressed_items WHERE deleted_at IS NULL ORDER BY random() LIMIT ?1"
+        ))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(item_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn list_dictionaries(&self) -> Result<Vec<DictionaryInfo>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {DICTIONARY_INFO_COLUMNS} FROM zstd_dictionaries ORDER BY version DESC"
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(dictionary_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn get_dictionary_data(&self, version: i32) -> Result<Vec<u8>, DbError> {
+        sqlx::query_scalar("SELECT data FROM zstd_dictionaries WHERE version = ?1")
+            .bind(version)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or(DbError::NotFound)
+    }
+
+    async fn active_dictionary_version(&self) -> Result<Option<i32>, DbError> {
+        let version = sqlx::query_scalar("SELECT version FROM zstd_dictionaries WHERE active")
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(version)
+    }
+
+    async fn insert_dictionary(
+        &self,
+        data: &[u8],
+        sample_count: i32,
+        sample_bytes: i64,
+        activate: bool,
+    ) -> Result<DictionaryInfo, DbError> {
+        let mut tx = self.pool.begin().await?;
+        if activate {
+            deactivate(&mut tx).await?;
+        }
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO zstd_dictionaries (data, sample_count, sample_bytes, active, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            RETURNING {DICTIONARY_INFO_COLUMNS}
+            "#
+        ))
+        .bind(data)
+        .bind(sample_count)
+        .bind(sample_bytes)
+        .bind(activate)
+        .bind(micros(Utc::now()))
+        .fetch_one(&mut *tx)
+        .await?;
+        let dictionary = dictionary_from_row(&row)?;
+        tx.commit().await?;
+
+        Ok(dictionary)
+    }
+
+    async fn activate_dictionary(&self, version: i32) -> Result<DictionaryInfo, DbError> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE zstd_dictionaries SET active = FALSE WHERE active AND version <> ?1")
+            .bind(version)
+            .execute(&mut *tx)
+            .await?;
+        let row = sqlx::query(&format!(
+            "UPDATE zstd_dictionaries SET active = TRUE WHERE version = ?1 RETURNING {DICTIONARY_INFO_COLUMNS}"
+        ))
+        .bind(version)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(DbError::NotFound)?;
+        let dictionary = dictionary_from_row(&row)?;
+        tx.commit().await?;
+
+        Ok(dictionary)
+    }
+
+    async fn deactivate_dictionaries(&self) -> Result<(), DbError> {
+        let mut conn = self.pool.acquire().await?;
+        deactivate(&mut conn).await?;
+
+        Ok(())
+    }
+}
+
+async fn deactivate(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE zstd_dictionaries SET active = FALSE WHERE active")
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Compressed images in SQLite
+#[derive(Debug, Clone)]
+pub struct SqliteImageStore {
+    pool: SqlitePool,
+}
+
+impl SqliteImageStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl ImageStore for SqliteImageStore {
+    async fn save_image(&self, image: NewStoredImage) -> Result<StoredImage, DbError> {
+        let id = Uuid::now_v7();
+        let mut tx = self.pool.begin().await?;
+        let compressed_sha256 = acquire_blob(&mut tx, &image.data).await?;
+        let thumbnail_sha256 = match &image.thumbnail {
+            Some(thumbnail) => Some(acquire_blob(&mut tx, thumbnail).await?),
+            None => None,
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO stored_images
+                (id, format, data, original_size, compressed_size, original_sha256, compressed_sha256,
+                 thumbnail_sha256, options, created_at)
+            VALUES (?1, ?2, X'', ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            "#,
+        )
+        .bind(id.hyphenated().to_string())
+        .bind(image.format)
+        .bind(image.original_size as i64)
+        .bind(image.data.len() as i64)
+        .bind(image.original_sha256)
+        .bind(compressed_sha256)
+        .bind(thumbnail_sha256)
+        .bind(image.options)
+        .bind(micros(Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+        let stored = fetch_image(&mut tx, id).await?.ok_or(DbError::NotFound)?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn get_image(&self, id: Uuid) -> Result<StoredImage, DbError> {
+        let mut conn = self.pool.acquire().await?;
+        fetch_image(&mut conn, id).await?.ok_or(DbError::NotFound)
+    }
+
+    async fn find_derivative(&self, original_sha256: &str, options: &str) -> Result<Option<StoredImage>, DbError> {
+        let row = sqlx::query(&format!(
+            r#"
+            SELECT {IMAGE_COLUMNS} FROM {IMAGES}
+            WHERE i.original_sha256 = ?1 AND i.options = ?2
+            ORDER BY i.created_at DESC, i.id DESC
+            LIMIT 1
+            "#
+        ))
+        .bind(original_sha256)
+        .bind(options)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(image_from_row).transpose()?)
+    }
+
+    async fn list_images(&self, after: Option<Uuid>, limit: u32) -> Result<Vec<StoredImage>, DbError> {
+        let rows = sqlx::query(&format!(
+            "SELECT {IMAGE_COLUMNS} FROM {IMAGES} WHERE (?1 IS NULL OR i.id > ?1) ORDER BY i.id LIMIT ?2"
+        ))
+        .bind(after.map(|id| id.hyphenated().to_string()))
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows.iter().map(image_from_row).collect::<Result<_, _>>()?)
+    }
+
+    async fn put_image(&self, image: StoredImage) -> Result<StoredImage, DbError> {
+        let id = image.id.hyphenated().to_string();
+        let mut tx = self.pool.begin().await?;
+        // Take the new references before dropping the old ones, which may be the same blobs
+        let compressed_sha256 = acquire_blob(&mut tx, &image.data).await?;
+        let thumbnail_sha256 = match &image.thumbnail {
+            Some(thumbnail) => Some(acquire_blob(&mut tx, thumbnail).await?),
+            None => None,
+        };
+        let replaced: Option<(Option<String>, Option<String>)> =
+            sqlx::query_as("SELECT compressed_sha256, thumbnail_sha256 FROM stored_images WHERE id = ?1")
+                .bind(&id)
+                .fetch_optional(&mut *tx)
+                .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO stored_images
+                (id, format, data, original_size, compressed_size, original_sha256, compressed_sha256,
+                 thumbnail_sha256, options, created_at)
+            VALUES (?1, ?2, X'', ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+            ON CONFLICT (id) DO UPDATE SET
+                format = excluded.format,
+                data = excluded.data,
+                original_size = excluded.original_size,
+                compressed_size = excluded.compressed_size,
+                original_sha256 = excluded.original_sha256,
+                compressed_sha256 = excluded.compressed_sha256,
+                thumbnail_sha256 = excluded.thumbnail_sha256,
+                options = excluded.options,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(&id)
+        .bind(image.format)
+        .bind(image.original_size)
+        .bind(image.data.len() as i64)
+        .bind(image.original_sha256)
+        .bind(compressed_sha256)
+        .bind(thumbnail_sha256)
+        .bind(image.options)
+        .bind(micros(image.created_at))
+        .execute(&mut *tx)
+        .await?;
+        if let Some((compressed_sha256, thumbnail_sha256)) = replaced {
+            release_blobs(&mut tx, [compressed_sha256, thumbnail_sha256]).await?;
+        }
+        let stored = fetch_image(&mut tx, image.id).await?.ok_or(DbError::NotFound)?;
+        tx.commit().await?;
+
+        Ok(stored)
+    }
+
+    async fn record_image_checksum(&self, id: Uuid, compressed_sha256: &str) -> Result<(), DbError> {
+        let id = id.hyphenated().to_string();
+        let mut tx = self.pool.begin().await?;
+        let recorded: Option<String> = sqlx::query_scalar("SELECT compressed_sha256 FROM stored_images WHERE id = ?1")
+            .bind(&id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(DbError::NotFound)?;
+        if recorded.is_some() {
+            return Ok(());
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO blobs (sha256, data, size, ref_count, created_at)
+            SELECT ?2, data, length(data), 1, ?3 FROM stored_images WHERE id = ?1
+            ON CONFLICT (sha256) DO UPDATE SET ref_count = ref_count + 1
+            "#,
+        )
+        .bind(&id)
+        .bind(compressed_sha256)
+        .bind(micros(Utc::now()))
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("UPDATE stored_images SET compressed_sha256 = ?2, data = X'' WHERE id = ?1")
+            .bind(&id)
+            .bind(compressed_sha256)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn delete_image(&self, id: Uuid) -> Result<(), DbError> {
+        let mut tx = self.pool.begin().await?;
+        let (compressed_sha256, thumbnail_sha256): (Option<String>, Option<String>) =
+            sqlx::query_as("DELETE FROM stored_images WHERE id = ?1 RETURNING compressed_sha256, thumbnail_sha256")
+                .bind(id.hyphenated().to_string())
+                .fetch_optional(&mut *tx)
+                .await?
+                .ok_or(DbError::NotFound)?;
+        release_blobs(&mut tx, [compressed_sha256, thumbnail_sha256]).await?;
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    async fn blob_stats(&self) -> Result<BlobStats, DbError> {
+        let (blobs, references, stored_bytes, bytes_saved): (i64, i64, i64, i64) = sqlx::query_as(
+            r#"
+            SELECT
+                COUNT(*),
+                COALESCE(SUM(ref_count), 0),
+                COALESCE(SUM(size), 0),
+                COALESCE(SUM(size * (ref_count - 1)), 0)
+            FROM blobs
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(BlobStats {
+            blobs,
+            references,
+            stored_bytes,
+            bytes_saved,
+        })
+    }
+}
+
+async fn fetch_image(conn: &mut SqliteConnection, id: Uuid) -> Result<Option<StoredImage>, sqlx::Error> {
+    let row = sqlx::query(&format!("SELECT {IMAGE_COLUMNS} FROM {IMAGES} WHERE i.id = ?1"))
+        .bind(id.hyphenated().to_string())
+        .fetch_optional(conn)
+        .await?;
+    row.as_ref().map(image_from_row).transpose()
+}
+
+/// Adds a reference to the blob holding `data`, storing it if it is new, and returns
+/// its name
+async fn acquire_blob(conn: &mut SqliteConnection, data: &[u8]) -> Result<String, sqlx::Error> {
+    let sha256 = sha256_hex(data);
+    sqlx::query(
+        r#"
+        INSERT INTO blobs (sha256, data, size, ref_count, created_at) VALUES (?1, ?2, ?3, 1, ?4)
+        ON CONFLICT (sha256) DO UPDATE SET ref_count = ref_count + 1
+        "#,
+    )
+    .bind(&sha256)
+    .bind(data)
+    .bind(data.len() as i64)
+    .bind(micros(Utc::now()))
+    .execute(conn)
+    .await?;
+    Ok(sha256)
+}
+
+/// Drops a reference to each named blob, deleting those no image refers to anymore
+async fn release_blobs(conn: &mut SqliteConnection, blobs: [Option<String>; 2]) -> Result<(), sqlx::Error> {
+    for sha256 in blobs.into_iter().flatten() {
+        sqlx::query("UPDATE blobs SET ref_count = ref_count - 1 WHERE sha256 = ?1")
+            .bind(&sha256)
+            .execute(&mut *conn)
+            .await?;
+        sqlx::query("DELETE FROM blobs WHERE sha256 = ?1 AND ref_count <= 0")
+            .bind(&sha256)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Timestamps are stored as microseconds since the Unix epoch, the precision
+/// PostgreSQL keeps
+fn micros(timestamp: DateTime<Utc>) -> i64 {
+    timestamp.timestamp_micros()
+}
+
+fn timestamp(row: &SqliteRow, column: &str) -> Result<DateTime<Utc>, sqlx::Error> {
+    let value: i64 = row.try_get(column)?;
+    DateTime::from_timestamp_micros(value).ok_or_else(|| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: format!("timestamp {value} is out of range").into(),
+    })
+}
+
+fn uuid(row: &SqliteRow, column: &str) -> Result<Uuid, sqlx::Error> {
+    let value: String = row.try_get(column)?;
+    Uuid::parse_str(&value).map_err(|e| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: Box::new(e),
+    })
+}
+
+fn item_from_row(row: &SqliteRow) -> Result<StoredItem, sqlx::Error> {
+    let deleted_at: Option<i64> = row.try_get("deleted_at")?;
+    Ok(StoredItem {
+        id: uuid(row, "id")?,
+        name: row.try_get("name")?,
+        data: row.try_get("data")?,
+        algorithm: row.try_get("algorithm")?,
+        level: row.try_get("level")?,
+        dictionary_version: row.try_get("dictionary_version")?,
+        original_size: row.try_get("original_size")?,
+        compressed_size: row.try_get("compressed_size")?,
+        original_sha256: row.try_get("original_sha256")?,
+        compressed_sha256: row.try_get("compressed_sha256")?,
+        created_at: Some(timestamp(row, "created_at")?),
+        updated_at: Some(timestamp(row, "updated_at")?),
+        deleted_at: match deleted_at {
+            Some(_) => Some(timestamp(row, "deleted_at")?),
+            None => None,
+        },
+    })
+}
+
+fn dictionary_from_row(row: &SqliteRow) -> Result<DictionaryInfo, sqlx::Error> {
+    Ok(DictionaryInfo {
+        version: row.try_get("version")?,
+        size: row.try_get("size")?,
+        sample_count: row.try_get("sample_count")?,
+        sample_bytes: row.try_get("sample_bytes")?,
+        active: row.try_get("active")?,
+        created_at: timestamp(row, "created_at")?,
+    })
+}
+
+fn image_from_row(row: &SqliteRow) -> Result<StoredImage, sqlx::Error> {
+    Ok(StoredImage {
+        id: uuid(row, "id")?,
+        format: row.try_get("format")?,
+        data: row.try_get("data")?,
+        original_size: row.try_get("original_size")?,
+        compressed_size: row.try_get("compressed_size")?,
+        original_sha256: row.try_get("original_sha256")?,
+        compressed_sha256: row.try_get("compressed_sha256")?,
+        thumbnail: row.try_get("thumbnail")?,
+        thumbnail_sha256: row.try_get("thumbnail_sha256")?,
+        options: row.try_get("options")?,
+        created_at: timestamp(row, "created_at")?,
+    })
+}
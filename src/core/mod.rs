@@ -1,3 +1,4 @@
 pub mod config;
-// pub mod database;
+#[cfg(feature = "database")]
+pub mod database;
 pub mod models;
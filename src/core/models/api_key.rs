@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// Permission carried by an API key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub enum Scope {
+    /// `POST /compress`
+    #[serde(rename = "compress")]
+    Compress,
+    /// `POST /compress/batch`
+    #[serde(rename = "batch")]
+    Batch,
+    /// Reading stored images and items
+    #[serde(rename = "files:read")]
+    FilesRead,
+    /// Creating, updating and deleting stored files and items
+    #[serde(rename = "files:write")]
+    FilesWrite,
+    /// Key management and statistics; implies every other scope
+    #[serde(rename = "admin")]
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 5] = [
+        Scope::Compress,
+        Scope::Batch,
+        Scope::FilesRead,
+        Scope::FilesWrite,
+        Scope::Admin,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Compress => "compress",
+            Scope::Batch => "batch",
+            Scope::FilesRead => "files:read",
+            Scope::FilesWrite => "files:write",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("unknown scope `{s}` (expected compress, batch, files:read, files:write or admin)"))
+    }
+}
+
+/// Where an API key is stored
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    /// Static keys file (read-only)
+    File,
+    /// PostgreSQL `api_keys` table
+    Database,
+}
+
+/// API key metadata; the key itself is never stored or returned after creation
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ApiKeyInfo {
+    /// Key identifier
+    #[schema(example = "0199f1b2-7c1e-7d32-9a51-3c4b8f2e6a10")]
+    pub id: String,
+
+    /// Human-readable name
+    #[schema(example = "CI pipeline")]
+    pub name: String,
+
+    /// First characters of the key, to help recognise it
+    #[schema(example = "rca_3kF9x2")]
+    pub prefix: String,
+
+    pub scopes: Vec<Scope>,
+
+    pub source: KeySource,
+
+    /// Creation time (database keys only)
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+/// Request payload for creating an API key
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiKeyRequest {
+    /// Human-readable name
+    #[schema(example = "CI pipeline")]
+    pub name: String,
+
+    /// Scopes granted to the key
+    pub scopes: Vec<Scope>,
+}
+
+/// A newly created API key. The plaintext `key` is only ever shown here.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreatedApiKey {
+    /// The secret to send as `Authorization: Bearer <key>` or `X-API-Key`
+    #[schema(example = "rca_3kF9x2mQ7vLw0pZsT8bYc1nHd4eJ6gRu")]
+    pub key: String,
+
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+}
@@ -1,13 +1,32 @@
-// use crate::core::database::DbPool;
+#[cfg(feature = "database")]
+use crate::core::database::{ImageStore, ItemRepository};
+#[cfg(feature = "database")]
+use crate::services::dictionary::DictionaryStore;
+use crate::server::Lifecycle;
+use crate::services::{
+    AuthService, ComputePool, DataCompressionService, HealthService, ImageCompressionService, Metrics, RateLimiter, StatsService,
+};
+use std::sync::Arc;
 
 // Application state (no database needed for compress endpoint)
 #[derive(Debug, Clone)]
 pub struct AppState {
-    // pub db_pool: DbPool,
-}
-
-impl AppState {
-    pub fn new(/* db_pool: DbPool */) -> Self {
-        Self { /* db_pool */ }
-    }
+    /// Backs the items API; `None` when `database.enabled` is off
+    #[cfg(feature = "database")]
+    pub items: Option<Arc<dyn ItemRepository>>,
+    /// Stored compressed images, in the same backend as items
+    #[cfg(feature = "database")]
+    pub images: Option<Arc<dyn ImageStore>>,
+    /// zstd dictionaries used by items
+    #[cfg(feature = "database")]
+    pub dictionaries: Arc<DictionaryStore>,
+    pub image_service: Arc<ImageCompressionService>,
+    pub data_service: Arc<DataCompressionService>,
+    pub compute: Arc<ComputePool>,
+    pub lifecycle: Arc<Lifecycle>,
+    pub metrics: Arc<Metrics>,
+    pub stats: Arc<StatsService>,
+    pub health: Arc<HealthService>,
+    pub auth: Arc<AuthService>,
+    pub rate_limiter: Arc<RateLimiter>,
 }
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+/// General-purpose compression algorithm for stored data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    /// Stored as-is; items written before compression was introduced use this
+    Identity,
+    Gzip,
+    /// zlib-wrapped deflate (RFC 1950), as used by the HTTP `deflate` coding
+    Deflate,
+    #[default]
+    Zstd,
+    Brotli,
+    Lz4,
+    Xz,
+    Bzip2,
+}
+
+impl CompressionAlgorithm {
+    pub const ALL: [CompressionAlgorithm; 8] = [
+        CompressionAlgorithm::Identity,
+        CompressionAlgorithm::Gzip,
+        CompressionAlgorithm::Deflate,
+        CompressionAlgorithm::Zstd,
+        CompressionAlgorithm::Brotli,
+        CompressionAlgorithm::Lz4,
+        CompressionAlgorithm::Xz,
+        CompressionAlgorithm::Bzip2,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Identity => "identity",
+            CompressionAlgorithm::Gzip => "gzip",
+            CompressionAlgorithm::Deflate => "deflate",
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Brotli => "brotli",
+            CompressionAlgorithm::Lz4 => "lz4",
+            CompressionAlgorithm::Xz => "xz",
+            CompressionAlgorithm::Bzip2 => "bzip2",
+        }
+    }
+
+    /// Levels the algorithm accepts
+    pub fn levels(self) -> RangeInclusive<i32> {
+        match self {
+            CompressionAlgorithm::Identity => 0..=0,
+            CompressionAlgorithm::Gzip | CompressionAlgorithm::Deflate | CompressionAlgorithm::Xz => 0..=9,
+            CompressionAlgorithm::Zstd => 1..=22,
+            CompressionAlgorithm::Brotli => 0..=11,
+            CompressionAlgorithm::Lz4 => 0..=16,
+            CompressionAlgorithm::Bzip2 => 1..=9,
+        }
+    }
+
+    /// Level used when a request does not pick one
+    pub fn default_level(self) -> i32 {
+        match self {
+            CompressionAlgorithm::Identity => 0,
+            CompressionAlgorithm::Gzip | CompressionAlgorithm::Deflate => 6,
+            CompressionAlgorithm::Zstd => 3,
+            CompressionAlgorithm::Brotli => 6,
+            CompressionAlgorithm::Lz4 => 0,
+            CompressionAlgorithm::Xz | CompressionAlgorithm::Bzip2 => 6,
+        }
+    }
+}
+
+impl fmt::Display for CompressionAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CompressionAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CompressionAlgorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.as_str() == s)
+            .ok_or_else(|| format!("unknown algorithm `{s}` (expected identity, gzip, deflate, zstd, brotli, lz4, xz or bzip2)"))
+    }
+}
@@ -0,0 +1,73 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::CompressionAlgorithm;
+
+/// JSON body for `POST /compress/data`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CompressDataRequest {
+    /// Base64 encoded data to compress
+    #[schema(example = "SGVsbG8gV29ybGQ=")]
+    pub data: String,
+
+    /// Compression algorithm (default: zstd)
+    pub algorithm: Option<CompressionAlgorithm>,
+
+    /// Compression level: gzip, deflate and xz 0-9 (default 6), zstd 1-22 (3),
+    /// brotli 0-11 (6), lz4 0-16 (0), bzip2 1-9 (6)
+    #[schema(example = 3)]
+    pub level: Option<i32>,
+
+    /// Hex SHA-256 of the decoded `data`; the request fails if it does not match
+    #[schema(example = "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e")]
+    pub checksum: Option<String>,
+}
+
+/// JSON body for `POST /decompress/data`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DecompressDataRequest {
+    /// Base64 encoded compressed data
+    #[schema(example = "KLUv/SALWQAASGVsbG8gV29ybGQ=")]
+    pub data: String,
+
+    /// Algorithm the data was compressed with
+    pub algorithm: CompressionAlgorithm,
+
+    /// Hex SHA-256 of the decoded `data`; the request fails if it does not match
+    pub checksum: Option<String>,
+}
+
+/// Response for a JSON compression or decompression request
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DataCompressionResponse {
+    /// Algorithm used
+    pub algorithm: CompressionAlgorithm,
+
+    /// Compression level used (compression only)
+    #[schema(example = 3)]
+    pub level: Option<i32>,
+
+    /// Size of the uncompressed data in bytes
+    #[schema(example = 11)]
+    pub original_size: u64,
+
+    /// Size of the compressed data in bytes
+    #[schema(example = 20)]
+    pub compressed_size: u64,
+
+    /// Compression ratio (compressed_size / original_size)
+    #[schema(example = 1.82)]
+    pub compression_ratio: f64,
+
+    /// Uncompressed bytes processed per second, in MB/s
+    #[schema(example = 412.5)]
+    pub throughput_mb_per_sec: f64,
+
+    /// Processing duration in milliseconds
+    #[schema(example = 0.2)]
+    pub processing_duration_ms: f64,
+
+    /// Base64 encoded result: the compressed data, or the decompressed data
+    #[schema(example = "KLUv/SALWQAASGVsbG8gV29ybGQ=")]
+    pub data: String,
+}
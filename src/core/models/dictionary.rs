@@ -0,0 +1,38 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+/// A trained zstd dictionary; the dictionary bytes themselves are not returned
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct DictionaryInfo {
+    /// Dictionary version, recorded on every item compressed with it
+    #[schema(example = 3)]
+    pub version: i32,
+    /// Dictionary size in bytes
+    #[schema(example = 112640)]
+    pub size: i32,
+    /// Items the dictionary was trained on
+    #[schema(example = 1000)]
+    pub sample_count: i32,
+    /// Total size of the training samples in bytes
+    #[schema(example = 2483211)]
+    pub sample_bytes: i64,
+    /// Whether new zstd items are compressed with this dictionary
+    pub active: bool,
+    #[schema(example = "2023-01-01T00:00:00Z")]
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request body for `POST /admin/dictionaries`
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct TrainDictionaryRequest {
+    /// Items to sample at random (default: 1000, at most 100000)
+    #[schema(example = 1000)]
+    pub sample_size: Option<u32>,
+    /// Maximum dictionary size in bytes (default: 112640)
+    #[schema(example = 112640)]
+    pub max_size: Option<u32>,
+    /// Make the new dictionary the active one (default: true)
+    pub activate: Option<bool>,
+}
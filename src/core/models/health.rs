@@ -0,0 +1,63 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Liveness report: the process is up and serving requests
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LivenessResponse {
+    /// Always `alive`
+    #[schema(example = "alive")]
+    pub status: String,
+
+    /// Build version
+    #[schema(example = "0.1.0")]
+    pub version: String,
+
+    /// Seconds since the process started
+    pub uptime_secs: u64,
+}
+
+/// Overall readiness
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessStatus {
+    Ready,
+    NotReady,
+}
+
+/// Outcome of a single readiness check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Fail,
+}
+
+/// Result of a single readiness check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HealthCheck {
+    pub status: CheckStatus,
+
+    /// Time the check took in milliseconds
+    pub latency_ms: f64,
+
+    /// Failure reason or additional detail
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// Readiness report: whether the service should receive traffic
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReadinessResponse {
+    pub status: ReadinessStatus,
+
+    /// Build version
+    #[schema(example = "0.1.0")]
+    pub version: String,
+
+    /// Seconds since the process started
+    pub uptime_secs: u64,
+
+    /// Individual checks by name (`draining`, `database`, `storage`, `compute`)
+    pub checks: BTreeMap<String, HealthCheck>,
+}
@@ -1,6 +1,8 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 
+use crate::utils::ProblemDetails;
+
 /// Request payload for image compression
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct CompressImageRequest {
@@ -20,15 +22,15 @@ pub struct CompressImageRequest {
     #[schema(example = "image/jpeg")]
     pub content_type: String,
     
-    /// Generate thumbnail (default: true)
+    /// Generate thumbnail (default: true, configurable via DEFAULTS_GENERATE_THUMBNAIL)
     #[schema(example = true)]
     pub generate_thumbnail: Option<bool>,
     
-    /// Thumbnail size in pixels (default: 150)
+    /// Thumbnail size in pixels (default: 150, configurable via DEFAULTS_THUMBNAIL_SIZE)
     #[schema(example = 150, minimum = 50, maximum = 300)]
     pub thumbnail_size: Option<u32>,
     
-    /// JPEG quality (1-100, optional, defaults to 75)
+    /// JPEG quality (1-100, optional, defaults to 75, configurable via DEFAULTS_QUALITY)
     #[schema(example = 75, minimum = 1, maximum = 100)]
     pub quality: Option<u8>,
     
@@ -39,12 +41,17 @@ pub struct CompressImageRequest {
     /// Maximum height for resizing (optional)
     #[schema(example = 1080)]
     pub max_height: Option<u32>,
+
+    /// Hex SHA-256 of the source image (the decoded upload, or the downloaded file);
+    /// the request fails if it does not match
+    pub checksum: Option<String>,
 }
 
 /// Response for successful image compression
 #[derive(Debug, Serialize, ToSchema)]
 pub struct CompressImageResponse {
-    /// Unique identifier for the compressed image
+    /// Unique identifier for the compressed image; the ID it is stored under when the
+    /// database keeps compressed images
     pub file_id: String,
     
     /// Original filename
@@ -58,6 +65,19 @@ pub struct CompressImageResponse {
     
     /// Compression ratio (compressed_size / original_size)
     pub compression_ratio: f64,
+
+    /// Hex SHA-256 of the source image
+    #[schema(example = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08")]
+    pub original_sha256: String,
+
+    /// Hex SHA-256 of the compressed image
+    #[schema(example = "60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752")]
+    pub compressed_sha256: String,
+
+    /// Whether the compressed image was reused from an earlier request with the same
+    /// source image and options rather than processed again
+    #[schema(example = false)]
+    pub deduplicated: bool,
     
     /// Base64 encoded compressed image data
     pub compressed_data: String,
@@ -76,10 +96,88 @@ pub struct CompressImageResponse {
     
     /// Processing duration in milliseconds
     pub processing_duration_ms: u64,
+
+    /// Time spent in each stage of the pipeline
+    pub timings: CompressionTimings,
 }
 
-/// Image compression statistics
+/// Request payload for compressing several images in one call
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CompressBatchRequest {
+    /// Images to compress, each with its own options
+    pub images: Vec<CompressImageRequest>,
+}
+
+/// Response for a batch, with one result per requested image in request order
 #[derive(Debug, Serialize, ToSchema)]
+pub struct CompressBatchResponse {
+    pub results: Vec<BatchImageResult>,
+}
+
+/// Outcome of one image in a batch; a failed image does not fail the others
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BatchImageResult {
+    /// Status code the image would have had on its own through `POST /compress`
+    #[schema(example = 200)]
+    pub status: u16,
+
+    /// The compressed image, when it succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<CompressImageResponse>,
+
+    /// Why it failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ProblemDetails>,
+}
+
+/// Per-stage wall-clock time of one compression, in milliseconds
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct CompressionTimings {
+    /// Downloading the image or decoding the base64 upload
+    #[schema(example = 12.4)]
+    pub fetch_ms: f64,
+
+    /// Decoding the source image
+    #[schema(example = 3.1)]
+    pub decode_ms: f64,
+
+    /// Resizing to the requested maximum dimensions
+    #[schema(example = 0.0)]
+    pub resize_ms: f64,
+
+    /// Encoding the compressed image
+    #[schema(example = 29.7)]
+    pub encode_ms: f64,
+
+    /// Generating the thumbnail (0 when none was requested)
+    #[schema(example = 8.2)]
+    pub thumbnail_ms: f64,
+
+    /// The whole compression, including waiting for a compute worker
+    #[schema(example = 55.0)]
+    pub total_ms: f64,
+}
+
+impl CompressionTimings {
+    /// The timings as a `Server-Timing` header value
+    pub fn server_timing(&self) -> String {
+        [
+            ("fetch", self.fetch_ms),
+            ("decode", self.decode_ms),
+            ("resize", self.resize_ms),
+            ("encode", self.encode_ms),
+            ("thumbnail", self.thumbnail_ms),
+            ("total", self.total_ms),
+        ]
+        .iter()
+        .map(|(name, ms)| format!("{name};dur={ms:.1}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+}
+
+/// Image compression statistics
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
 pub struct ImageCompressionStats {
     /// Total images processed
     pub total_processed: u64,
@@ -90,3 +188,24 @@ pub struct ImageCompressionStats {
     /// Average compression ratio
     pub average_compression_ratio: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn server_timing_lists_every_stage_in_milliseconds() {
+        let timings = CompressionTimings {
+            fetch_ms: 12.44,
+            decode_ms: 3.06,
+            resize_ms: 0.0,
+            encode_ms: 40.25,
+            thumbnail_ms: 1.5,
+            total_ms: 57.3,
+        };
+        assert_eq!(
+            timings.server_timing(),
+            "fetch;dur=12.4, decode;dur=3.1, resize;dur=0.0, encode;dur=40.2, thumbnail;dur=1.5, total;dur=57.3"
+        );
+    }
+}
@@ -1,41 +1,117 @@
+use base64::prelude::*;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use sha2::{Digest, Sha256};
 use sqlx::FromRow;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, Clone, FromRow, ToSchema)]
+use super::CompressionAlgorithm;
+
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[schema(example = json!({
     "id": "550e8400-e29b-41d4-a716-446655440000",
     "name": "Example Item",
     "data": "SGVsbG8gV29ybGQ=",
+    "algorithm": "zstd",
+    "level": 3,
+    "dictionary_version": null,
+    "original_size": 11,
+    "compressed_size": 20,
+    "original_sha256": "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e",
+    "compressed_sha256": "e34115918415980bc3c939bbc9be23a0123e8c06be71719a3fc18d0bfd946cb0",
+    "etag": "\"5d41402abc4b2a76b9719d911017c592\"",
     "created_at": "2023-01-01T00:00:00Z",
-    "updated_at": "2023-01-01T00:00:00Z"
+    "updated_at": "2023-01-01T00:00:00Z",
+    "deleted_at": null
 }))]
 pub struct CompressedItem {
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
     pub id: Uuid,
     #[schema(example = "My Data Item")]
     pub name: String,
+    /// Base64 encoded data; the compressed bytes when read with `raw=true`
     #[schema(example = "SGVsbG8gV29ybGQ=")]
-    pub data: String, // Base64 encoded compressed data
+    pub data: String,
+    /// Algorithm the data is stored with
+    pub algorithm: CompressionAlgorithm,
+    /// Compression level used
+    #[schema(example = 3)]
+    pub level: i32,
+    /// Version of the zstd dictionary the data was compressed with, if any
+    #[schema(example = json!(null))]
+    pub dictionary_version: Option<i32>,
+    /// Size of the data in bytes
+    #[schema(example = 11)]
+    pub original_size: u64,
+    /// Size of the stored, compressed data in bytes
+    #[schema(example = 20)]
+    pub compressed_size: u64,
+    /// Hex SHA-256 of the data; null for items stored before checksums were kept
+    /// that `admin verify --backfill` has not reached yet
+    #[schema(example = "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e")]
+    pub original_sha256: Option<String>,
+    /// Hex SHA-256 of the stored, compressed data
+    #[schema(example = "e34115918415980bc3c939bbc9be23a0123e8c06be71719a3fc18d0bfd946cb0")]
+    pub compressed_sha256: Option<String>,
+    /// Strong entity tag of the item, as sent in the `ETag` header; pass it in `If-Match`
+    /// to update or delete only this version
+    #[schema(example = "\"5d41402abc4b2a76b9719d911017c592\"")]
+    pub etag: String,
     #[schema(example = "2023-01-01T00:00:00Z")]
     pub created_at: Option<DateTime<Utc>>,
     #[schema(example = "2023-01-01T00:00:00Z")]
     pub updated_at: Option<DateTime<Utc>>,
+    /// When the item was moved to the trash; null for live items
+    #[schema(example = json!(null))]
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl CompressedItem {
+    /// The API view of a stored item whose algorithm has been parsed, with `data`
+    /// already encoded
+    pub fn from_stored(algorithm: CompressionAlgorithm, item: StoredItem, data: String) -> Self {
+        CompressedItem {
+            algorithm,
+            etag: item.etag(),
+            id: item.id,
+            name: item.name,
+            data,
+            level: item.level,
+            dictionary_version: item.dictionary_version,
+            original_size: item.original_size as u64,
+            compressed_size: item.compressed_size as u64,
+            original_sha256: item.original_sha256,
+            compressed_sha256: item.compressed_sha256,
+            created_at: item.created_at,
+            updated_at: item.updated_at,
+            deleted_at: item.deleted_at,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 #[schema(example = json!({
     "name": "My New Item",
-    "data": "SGVsbG8gV29ybGQ="
+    "data": "SGVsbG8gV29ybGQ=",
+    "algorithm": "zstd",
+    "level": 3
 }))]
 pub struct CreateCompressedItem {
     #[schema(example = "My New Item")]
     pub name: String,
+    /// Base64 encoded data to compress
     #[schema(example = "SGVsbG8gV29ybGQ=")]
-    pub data: String, // Base64 encoded data to compress
+    pub data: String,
+    /// Compression algorithm (default: zstd, with the active dictionary if there is one)
+    pub algorithm: Option<CompressionAlgorithm>,
+    /// Compression level: gzip, deflate and xz 0-9 (default 6), zstd 1-22 (3),
+    /// brotli 0-11 (6), lz4 0-16 (0), bzip2 1-9 (6)
+    #[schema(example = 3)]
+    pub level: Option<i32>,
+    /// Hex SHA-256 of the decoded `data`; the request fails if it does not match
+    #[schema(example = "a591a6d40bf420404a011733cfb7b190d62c65bf0bcda32b57b277d9ad9f146e")]
+    pub checksum: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -45,6 +121,159 @@ pub struct CreateCompressedItem {
 pub struct UpdateCompressedItem {
     #[schema(example = "Updated Item Name")]
     pub name: Option<String>,
+    /// Base64 encoded data to compress
     #[schema(example = "SGVsbG8gV29ybGQ=")]
-    pub data: Option<String>, // Base64 encoded data to compress
+    pub data: Option<String>,
+    /// Recompress with this algorithm (default: keep the current one)
+    pub algorithm: Option<CompressionAlgorithm>,
+    /// Recompress at this level (default: the current level, or the algorithm's default
+    /// when the algorithm changes)
+    pub level: Option<i32>,
+    /// Hex SHA-256 of the decoded `data`; the request fails if it does not match
+    pub checksum: Option<String>,
+}
+
+/// An item as stored in the `compressed_items` table
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredItem {
+    pub id: Uuid,
+    pub name: String,
+    /// Compressed payload
+    pub data: Vec<u8>,
+    pub algorithm: String,
+    pub level: i32,
+    pub dictionary_version: Option<i32>,
+    pub original_size: i64,
+    pub compressed_size: i64,
+    /// Hex SHA-256 of the uncompressed data
+    pub original_sha256: Option<String>,
+    /// Hex SHA-256 of `data`
+    pub compressed_sha256: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl StoredItem {
+    /// Strong entity tag, quoted. Every write sets a new, later `updated_at`, so the tag
+    /// changes whenever any part of the item does; the payload itself is not hashed, so
+    /// listings that skip `data` get the same tags.
+    pub fn etag(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(self.id.as_bytes());
+        hasher.update(self.name.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.algorithm.as_bytes());
+        hasher.update([0]);
+        hasher.update(self.level.to_be_bytes());
+        hasher.update(self.dictionary_version.unwrap_or(0).to_be_bytes());
+        hasher.update(self.original_size.to_be_bytes());
+        hasher.update(self.compressed_size.to_be_bytes());
+        for at in [self.created_at, self.updated_at, self.deleted_at] {
+            hasher.update(at.map_or(0, |at| at.timestamp_micros()).to_be_bytes());
+        }
+        let digest: String = hasher.finalize()[..16]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        format!("\"{digest}\"")
+    }
+}
+
+/// A compressed payload ready to be written to an item
+#[derive(Debug, Clone)]
+pub struct ItemPayload {
+    pub data: Vec<u8>,
+    pub algorithm: CompressionAlgorithm,
+    pub level: i32,
+    /// zstd dictionary the data was compressed with
+    pub dictionary_version: Option<i32>,
+    pub original_size: u64,
+    /// Hex SHA-256 of the uncompressed data
+    pub original_sha256: String,
+    /// Hex SHA-256 of `data`
+    pub compressed_sha256: String,
+}
+
+/// Direction of the creation-time order items are listed in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Position after the last item of a page: its creation time and, to break ties, its ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl ItemCursor {
+    /// Opaque token handed to clients as `next_cursor`
+    pub fn encode(&self) -> String {
+        BASE64_URL_SAFE_NO_PAD.encode(format!("{}:{}", self.created_at.timestamp_micros(), self.id))
+    }
+
+    pub fn decode(token: &str) -> Option<Self> {
+        let raw = String::from_utf8(BASE64_URL_SAFE_NO_PAD.decode(token).ok()?).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        Some(Self {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+/// Filters, order and page for listing items
+#[derive(Debug, Clone, Default)]
+pub struct ItemQuery {
+    /// Names starting with this, case-sensitive
+    pub name_prefix: Option<String>,
+    /// Names containing this, case-insensitive
+    pub name_contains: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub updated_after: Option<DateTime<Utc>>,
+    pub updated_before: Option<DateTime<Utc>>,
+    pub sort: SortOrder,
+    /// List items in the trash instead of live ones
+    pub trashed: bool,
+    /// Start after this item
+    pub after: Option<ItemCursor>,
+    pub limit: u32,
+    /// Load the stored data; without it `StoredItem::data` is left empty
+    pub with_data: bool,
+}
+
+/// One page of items
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ItemPage {
+    /// Items on this page, with only the requested `fields`
+    #[schema(value_type = Vec<CompressedItem>)]
+    pub items: Vec<serde_json::Value>,
+    /// Pass as `cursor` to get the next page; null on the last page
+    #[schema(example = "MTY3MjUzMTIwMDAwMDAwMDo1NTBlODQwMC1lMjliLTQxZDQtYTcxNi00NDY2NTU0NDAwMDA")]
+    pub next_cursor: Option<String>,
+}
+
+/// Totals across all stored items
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ItemStats {
+    /// Items not in the trash
+    pub total_items: i64,
+    pub trashed_items: i64,
+    /// Compressed bytes stored, trash included
+    pub total_data_size: i64,
+}
+
+/// Totals across the live items stored with one algorithm
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AlgorithmStats {
+    pub algorithm: String,
+    pub items: i64,
+    pub original_size: i64,
+    pub compressed_size: i64,
 }
@@ -1,7 +1,32 @@
-// pub mod item;
+#[cfg(feature = "database")]
+pub mod dictionary;
+#[cfg(feature = "database")]
+pub mod item;
+#[cfg(feature = "database")]
+pub mod stored_image;
+pub mod api_key;
+pub mod codec;
+pub mod data;
+pub mod health;
 pub mod image;
+pub mod transfer;
+pub mod usage;
 pub mod app_state;
 
-// pub use item::{CompressedItem, CreateCompressedItem, UpdateCompressedItem};
+#[cfg(feature = "database")]
+pub use dictionary::{DictionaryInfo, TrainDictionaryRequest};
+#[cfg(feature = "database")]
+pub use item::{
+    AlgorithmStats, CompressedItem, CreateCompressedItem, ItemCursor, ItemPage, ItemPayload, ItemQuery, ItemStats, SortOrder,
+    StoredItem, UpdateCompressedItem,
+};
+#[cfg(feature = "database")]
+pub use stored_image::{BlobStats, NewStoredImage, StoredImage};
+pub use api_key::*;
+pub use codec::*;
+pub use data::*;
+pub use health::*;
 pub use image::*;
+pub use transfer::*;
+pub use usage::*;
 pub use app_state::AppState;
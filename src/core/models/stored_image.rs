@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use crate::utils::checksum::sha256_hex;
+
+/// A compressed image as stored in the `stored_images` table, with its data and
+/// thumbnail read from the blobs they are kept in
+#[derive(Debug, Clone, FromRow)]
+pub struct StoredImage {
+    pub id: Uuid,
+    /// Output format, e.g. `webp`
+    pub format: String,
+    /// Compressed image bytes
+    pub data: Vec<u8>,
+    pub original_size: i64,
+    pub compressed_size: i64,
+    /// Hex SHA-256 of the source image, when it was known
+    pub original_sha256: Option<String>,
+    /// Hex SHA-256 of `data`, which names the blob it is stored in
+    pub compressed_sha256: Option<String>,
+    /// Thumbnail bytes, when one was generated and its blob is there
+    pub thumbnail: Option<Vec<u8>>,
+    /// Hex SHA-256 of the thumbnail
+    pub thumbnail_sha256: Option<String>,
+    /// The settings the image was compressed with; a request for the same source and
+    /// settings reuses it
+    pub options: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl StoredImage {
+    /// Strong entity tag, quoted: the SHA-256 of `data`, so it changes exactly when the
+    /// served bytes do
+    pub fn etag(&self) -> String {
+        match &self.compressed_sha256 {
+            Some(sha256) => format!("\"{sha256}\""),
+            None => format!("\"{}\"", sha256_hex(&self.data)),
+        }
+    }
+}
+
+/// A compressed image ready to be stored. The store records the SHA-256 of `data` and
+/// of the thumbnail, and keeps each in a blob shared by every image with the same bytes.
+#[derive(Debug, Clone)]
+pub struct NewStoredImage {
+    pub format: String,
+    pub data: Vec<u8>,
+    pub original_size: u64,
+    /// Hex SHA-256 of the source image
+    pub original_sha256: Option<String>,
+    pub thumbnail: Option<Vec<u8>>,
+    /// The settings the image was compressed with
+    pub options: Option<String>,
+}
+
+/// Totals across the content-addressed blobs stored image data is kept in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BlobStats {
+    /// Distinct payloads stored
+    pub blobs: i64,
+    /// Stored images and thumbnails referring to them
+    pub references: i64,
+    /// Bytes the blobs take
+    pub stored_bytes: i64,
+    /// Bytes that a copy per reference would have taken on top of `stored_bytes`
+    pub bytes_saved: i64,
+}
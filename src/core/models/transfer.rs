@@ -0,0 +1,114 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::CompressionAlgorithm;
+
+/// Wrapping applied to a whole export
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportCompression {
+    Gzip,
+    Zstd,
+}
+
+impl ExportCompression {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExportCompression::Gzip => "gzip",
+            ExportCompression::Zstd => "zstd",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportCompression::Gzip => "application/gzip",
+            ExportCompression::Zstd => "application/zstd",
+        }
+    }
+
+    /// File name extension, after `.ndjson`
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportCompression::Gzip => "gz",
+            ExportCompression::Zstd => "zst",
+        }
+    }
+
+    /// The wrapping a file name asks for by its extension, if any
+    pub fn from_file_name(name: &str) -> Option<Self> {
+        [ExportCompression::Gzip, ExportCompression::Zstd]
+            .into_iter()
+            .find(|compression| name.ends_with(&format!(".{}", compression.extension())))
+    }
+
+    /// The algorithm the export is wrapped with
+    pub fn algorithm(self) -> CompressionAlgorithm {
+        match self {
+            ExportCompression::Gzip => CompressionAlgorithm::Gzip,
+            ExportCompression::Zstd => CompressionAlgorithm::Zstd,
+        }
+    }
+}
+
+impl fmt::Display for ExportCompression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ExportCompression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(ExportCompression::Gzip),
+            "zstd" => Ok(ExportCompression::Zstd),
+            _ => Err(format!("unknown compression `{s}` (expected gzip or zstd)")),
+        }
+    }
+}
+
+/// What an import does with a record whose ID is already taken by a different
+/// version of the item or image
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep what is stored
+    #[default]
+    Skip,
+    /// Replace what is stored with the record
+    Overwrite,
+    /// Store the record under a new ID derived from its own
+    Rename,
+}
+
+impl ConflictPolicy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Rename => "rename",
+        }
+    }
+}
+
+impl fmt::Display for ConflictPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ConflictPolicy::Skip),
+            "overwrite" => Ok(ConflictPolicy::Overwrite),
+            "rename" => Ok(ConflictPolicy::Rename),
+            _ => Err(format!("unknown conflict policy `{s}` (expected skip, overwrite or rename)")),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Usage against one quota
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaCounter {
+    pub used: u64,
+
+    /// Quota for the period; absent when unlimited
+    pub limit: Option<u64>,
+
+    /// What is left of the quota; absent when unlimited
+    pub remaining: Option<u64>,
+}
+
+/// Requests and processed input bytes in one quota period
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct QuotaPeriodUsage {
+    /// First day of the period (UTC)
+    pub period_start: NaiveDate,
+
+    /// When the counters reset
+    pub resets_at: DateTime<Utc>,
+
+    pub requests: QuotaCounter,
+
+    /// Processed input bytes
+    pub bytes: QuotaCounter,
+}
+
+/// Token bucket state
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct RateUsage {
+    /// Sustained requests per second; absent when unlimited
+    pub requests_per_second: Option<f64>,
+
+    /// Bucket capacity
+    pub burst: Option<u64>,
+
+    /// Requests that can be made right now
+    pub remaining: Option<u64>,
+}
+
+/// Rate-limit and quota usage for the caller
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsageResponse {
+    /// Who the limits apply to: `key:<id>` or `ip:<address>`
+    #[schema(example = "key:ci")]
+    pub subject: String,
+
+    /// Whether limits are enforced (usage is tracked either way)
+    pub enforced: bool,
+
+    pub rate: RateUsage,
+
+    pub daily: QuotaPeriodUsage,
+
+    pub monthly: QuotaPeriodUsage,
+}
@@ -1,30 +1,131 @@
-use utoipa::OpenApi;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
 use utoipa_scalar::Scalar;
 
-use crate::core::models::{CompressImageRequest, CompressImageResponse};
+use crate::core::models::{
+    ApiKeyInfo, CheckStatus, CreateApiKeyRequest, CreatedApiKey, KeySource, Scope, CompressImageRequest, CompressImageResponse, CompressionTimings,
+    CompressBatchRequest, CompressBatchResponse, BatchImageResult,
+    CompressionAlgorithm, CompressDataRequest, DataCompressionResponse, DecompressDataRequest, HealthCheck, ImageCompressionStats, LivenessResponse,
+    QuotaCounter, QuotaPeriodUsage, RateUsage, ReadinessResponse, ReadinessStatus, UsageResponse,
+};
+#[cfg(feature = "database")]
+use crate::core::models::{
+    CompressedItem, CreateCompressedItem, DictionaryInfo, ExportCompression, ItemPage, SortOrder,
+    TrainDictionaryRequest, UpdateCompressedItem,
+};
+use crate::services::stats::{Granularity, StatsBucket, StatsReport};
+use crate::utils::{ErrorCode, ProblemDetails};
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
         crate::api::handlers::root,
         crate::api::handlers::health_check,
-        // crate::api::handlers::get_items,
-        // crate::api::handlers::get_item,
-        // crate::api::handlers::create_item_handler,
-        // crate::api::handlers::update_item_handler,
-        // crate::api::handlers::delete_item_handler,
+        crate::api::handlers::liveness_handler,
+        crate::api::handlers::readiness_handler,
         crate::api::handlers::compress_image_handler,
+        crate::api::handlers::compress_batch_handler,
+        crate::api::handlers::compress_data_handler,
+        crate::api::handlers::decompress_data_handler,
+        crate::api::handlers::metrics_handler,
+        crate::api::handlers::stats_handler,
+        crate::api::handlers::usage_handler,
+        crate::api::handlers::list_api_keys_handler,
+        crate::api::handlers::create_api_key_handler,
+        crate::api::handlers::revoke_api_key_handler,
     ),
     components(
-        schemas(CompressImageRequest, CompressImageResponse)
+        schemas(
+            CompressImageRequest,
+            CompressImageResponse,
+            CompressionTimings,
+            CompressBatchRequest,
+            CompressBatchResponse,
+            BatchImageResult,
+            CompressionAlgorithm,
+            CompressDataRequest,
+            DecompressDataRequest,
+            DataCompressionResponse,
+            ImageCompressionStats,
+            StatsReport,
+            StatsBucket,
+            Granularity,
+            LivenessResponse,
+            ReadinessResponse,
+            ReadinessStatus,
+            HealthCheck,
+            CheckStatus,
+            Scope,
+            KeySource,
+            ApiKeyInfo,
+            CreateApiKeyRequest,
+            CreatedApiKey,
+            UsageResponse,
+            RateUsage,
+            QuotaPeriodUsage,
+            QuotaCounter,
+            ProblemDetails,
+            ErrorCode
+        )
     ),
+    modifiers(&SecurityAddon),
     tags(
         (name = "rust-compress-api", description = "API for compressing and managing data")
     )
 )]
 pub struct ApiDoc;
 
+/// Registers the `api_key` bearer scheme used by protected endpoints
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+    }
+}
+
+/// Items and dictionaries API, documented only in builds with the `database` feature
+#[cfg(feature = "database")]
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::api::handlers::get_items,
+        crate::api::handlers::get_item,
+        crate::api::handlers::create_item_handler,
+        crate::api::handlers::update_item_handler,
+        crate::api::handlers::delete_item_handler,
+        crate::api::handlers::get_trash,
+        crate::api::handlers::restore_item_handler,
+        crate::api::handlers::export_items_handler,
+        crate::api::handlers::get_stored_image,
+        crate::api::handlers::list_dictionaries_handler,
+        crate::api::handlers::train_dictionary_handler,
+        crate::api::handlers::activate_dictionary_handler,
+    ),
+    components(schemas(
+        CompressedItem,
+        CreateCompressedItem,
+        UpdateCompressedItem,
+        ItemPage,
+        SortOrder,
+        DictionaryInfo,
+        TrainDictionaryRequest,
+        ExportCompression
+    ))
+)]
+struct ItemsApiDoc;
+
+/// The OpenAPI document for the endpoints compiled into this build
+pub fn api_doc() -> utoipa::openapi::OpenApi {
+    #[allow(unused_mut)] // only extended when the database feature is enabled
+    let mut doc = ApiDoc::openapi();
+    #[cfg(feature = "database")]
+    doc.merge(ItemsApiDoc::openapi());
+    doc
+}
+
 /// Scalar API documentation handler
 pub async fn scalar_handler() -> axum::response::Html<String> {
-    axum::response::Html(Scalar::new(ApiDoc::openapi()).to_html())
+    axum::response::Html(Scalar::new(api_doc()).to_html())
 }
@@ -5,8 +5,9 @@
 
 pub mod api;
 pub mod core;
+pub mod server;
 pub mod services;
-// pub mod utils;
+pub mod utils;
 pub mod docs;
 
 // Re-export commonly used types for convenience
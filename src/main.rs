@@ -1,56 +1,217 @@
-use std::net::SocketAddr;
-use tokio;
-use tracing::info;
-use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use clap::Parser;
+use std::sync::Arc;
+use tracing::{info, warn};
+use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt};
 
 use rust_compress_api::{
     AppConfig, AppState,
     api::create_router,
-    // core::database::{create_pool, init_database},
+    core::config::{ConfigArgs, LogFormat},
+    server,
+    server::{Lifecycle, Telemetry},
+    services::{
+        AuthService, ComputePool, DataCompressionService, HealthService, ImageCompressionService, Metrics,
+        RateLimiter, StatsService,
+    },
 };
 
+#[cfg(feature = "database")]
+use rust_compress_api::core::{config::RateLimitBackend, database};
+#[cfg(feature = "database")]
+use rust_compress_api::services::{admin::spawn_trash_purge_task, dictionary::DictionaryStore};
+#[cfg(feature = "database")]
+use std::time::Duration;
+
+/// Rust Compress API server
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+
+    /// Print the effective configuration (secrets redacted) and exit
+    #[arg(long)]
+    print_config: bool,
+}
+
 #[tokio::main]
 async fn main() {
     // Load .env file first
     dotenvy::dotenv().ok();
 
+    let cli = Cli::parse();
+
+    // Load configuration: defaults, config file, environment, command line
+    let config = match AppConfig::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+
+    if cli.print_config {
+        print!(
+            "{}",
+            toml::to_string_pretty(&config.redacted()).expect("configuration serializes to TOML")
+        );
+        return;
+    }
+
     // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "rust_compress_api=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let telemetry = match Telemetry::init(&config.telemetry) {
+        Ok(telemetry) => telemetry,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(2);
+        }
+    };
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| "rust_compress_api=debug,tower_http=debug".into());
+    let registry = tracing_subscriber::registry()
+        .with(filter)
+        .with(telemetry.as_ref().map(Telemetry::layer));
+    // Closing a span logs its fields and time, which is how stage spans show up in logs
+    let fmt = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+    match config.log.format {
+        LogFormat::Text => registry.with(fmt).init(),
+        LogFormat::Json => registry
+            .with(fmt.json().flatten_event(true).with_span_list(true))
+            .init(),
+    }
+    if telemetry.is_some() {
+        info!("Exporting traces and metrics to {}", config.telemetry.endpoint);
+    }
+
+    info!("Starting server with config: {:?}", config.redacted());
+
+    // Open item storage when enabled; the scheme of database.url picks the backend
+    #[cfg(feature = "database")]
+    let storage = if config.database.enabled {
+        if !config.database.run_migrations {
+            info!("Skipping migrations (database.run_migrations is off)");
+        }
+        let storage = match database::connect(&config.database.url, config.database.run_migrations).await {
+            Ok(storage) => storage,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        info!("Database connected ({:?} backend)", storage.backend);
+        if storage.pool.is_none() {
+            info!("Statistics, database API keys and shared rate limits need PostgreSQL; keeping them in memory");
+        }
+        spawn_trash_purge_task(
+            storage.items.clone(),
+            config.database.trash_retention_days,
+            Duration::from_secs(config.database.trash_purge_interval_secs),
+        );
+        Some(storage)
+    } else {
+        None
+    };
+    #[cfg(feature = "database")]
+    let db_pool = storage.as_ref().and_then(|storage| storage.pool.clone());
+    #[cfg(not(feature = "database"))]
+    if config.database.enabled {
+        warn!("database.enabled is set but this build lacks the `database` feature; running without a database");
+    }
 
-    // Load configuration
-    let config = AppConfig::from_env().expect("Failed to load configuration");
+    // Build the shared compute pool and image compression service
+    let compute = Arc::new(ComputePool::new(config.limits.compute_workers));
+    let metrics = Metrics::new();
+    let metrics = Arc::new(if telemetry.is_some() { metrics.with_otel() } else { metrics });
+    let image_service = ImageCompressionService::from_config(&config, compute.clone(), metrics.clone())
+        .expect("Failed to build image compression service");
+    #[cfg(feature = "database")]
+    let image_service = match &storage {
+        Some(storage) if config.database.dedupe_images => image_service.with_store(storage.images.clone()),
+        _ => image_service,
+    };
+    let lifecycle = Arc::new(Lifecycle::new());
 
-    info!("Starting server with config: {:?}", config);
-    // info!("Database URL: {}", config.database.url);
+    // Statistics persist to the database when there is one, otherwise they stay in memory
+    #[cfg(feature = "database")]
+    let stats = match &db_pool {
+        Some(pool) => {
+            let stats = Arc::new(StatsService::with_database(pool.clone()).with_max_buckets(config.stats.max_buckets));
+            stats.spawn_flush_task(Duration::from_secs(config.stats.flush_interval_secs));
+            stats
+        }
+        None => Arc::new(StatsService::in_memory().with_max_buckets(config.stats.max_buckets)),
+    };
+    #[cfg(not(feature = "database"))]
+    let stats = Arc::new(StatsService::in_memory().with_max_buckets(config.stats.max_buckets));
 
-    // Create database connection pool
-    // let db_pool = create_pool(&config.database.url)
-    //     .await
-    //     .expect("Failed to create database pool");
+    // API keys come from the static keys file and, with a database, the `api_keys` table
+    let auth = AuthService::from_config(&config.auth).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    #[cfg(feature = "database")]
+    let auth = match &db_pool {
+        Some(pool) => auth.with_database(pool.clone()),
+        None => auth,
+    };
+    if !auth.enabled() {
+        warn!("API key authentication is disabled; set auth.enabled to protect the API");
+    }
 
-    // Initialize database
-    // init_database(&db_pool)
-    //     .await
-    //     .expect("Failed to initialize database");
+    // Rate-limit state is shared through PostgreSQL only with the `postgres` backend
+    let rate_limiter = RateLimiter::from_config(&config.rate_limit);
+    #[cfg(feature = "database")]
+    let rate_limiter = match &db_pool {
+        Some(pool) if config.rate_limit.backend == RateLimitBackend::Postgres => {
+            rate_limiter.with_database(pool.clone())
+        }
+        _ => rate_limiter,
+    };
 
-    // info!("Database connected and initialized");
+    let health = HealthService::new(&config, compute.clone(), lifecycle.clone());
+    #[cfg(feature = "database")]
+    let health = match &db_pool {
+        Some(pool) => health.with_database(pool.clone()),
+        None => health,
+    };
 
     // Create application state
-    let state = AppState::new(/* db_pool */);
+    let state = AppState {
+        #[cfg(feature = "database")]
+        items: storage.as_ref().map(|storage| storage.items.clone()),
+        #[cfg(feature = "database")]
+        images: storage.as_ref().map(|storage| storage.images.clone()),
+        #[cfg(feature = "database")]
+        dictionaries: Arc::new(DictionaryStore::new()),
+        image_service: Arc::new(image_service),
+        data_service: Arc::new(DataCompressionService::from_config(&config, compute.clone())),
+        compute: compute.clone(),
+        lifecycle: lifecycle.clone(),
+        metrics,
+        stats: stats.clone(),
+        health: Arc::new(health),
+        auth: Arc::new(auth),
+        rate_limiter: Arc::new(rate_limiter),
+    };
 
     // Build our application with routes
-    let app = create_router().with_state(state);
+    let app = create_router(state);
 
-    // Run our app with hyper, listening on the configured host and port
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
-    info!("Listening on {}", addr);
+    // Bind every configured listener (TCP, Unix socket, TLS) before serving any of them
+    let listeners = server::bind_listeners(&config.server)
+        .await
+        .expect("Failed to bind listeners");
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    server::serve(listeners, app, lifecycle, compute, &config.shutdown).await;
+    if let Err(e) = stats.flush().await {
+        warn!("Failed to flush compression statistics: {}", e);
+    }
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown();
+    }
+    info!("Shutdown complete");
+
+    // Exit right away: returning would make the runtime wait for abandoned blocking jobs
+    std::process::exit(0);
 }
+
@@ -0,0 +1,196 @@
+use std::io;
+use std::net::IpAddr;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use axum::extract::connect_info::Connected;
+use axum::serve::IncomingStream;
+use tokio::net::{TcpListener, UnixListener};
+
+use super::tls::TlsListener;
+use crate::core::config::{ListenerConfig, ServerConfig, parse_socket_mode};
+
+/// A listener bound to its socket and ready to serve
+pub enum BoundListener {
+    Tcp(TcpListener),
+    Unix(UnixListener, PathBuf),
+    Tls(TlsListener),
+}
+
+impl BoundListener {
+    /// Human-readable description for logs
+    pub fn describe(&self) -> String {
+        match self {
+            BoundListener::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => format!("http://{}", addr),
+                Err(_) => "http://<unknown>".to_string(),
+            },
+            BoundListener::Unix(_, path) => format!("unix:{}", path.display()),
+            BoundListener::Tls(listener) => format!("https://{}", listener.local_addr()),
+        }
+    }
+}
+
+/// Peer address of a connection, available to handlers as `ConnectInfo<ClientAddr>`.
+///
+/// Unix socket peers have no IP address.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientAddr(pub Option<IpAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        ClientAddr(Some(stream.remote_addr().ip()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        ClientAddr(Some(stream.remote_addr().ip()))
+    }
+}
+
+impl Connected<IncomingStream<'_, UnixListener>> for ClientAddr {
+    fn connect_info(_stream: IncomingStream<'_, UnixListener>) -> Self {
+        ClientAddr(None)
+    }
+}
+
+/// Binds every listener from the server configuration.
+///
+/// All sockets are bound before any of them starts serving, so a bad listener
+/// fails startup instead of leaving the server half up.
+pub async fn bind_listeners(config: &ServerConfig) -> io::Result<Vec<BoundListener>> {
+    let mut bound = Vec::new();
+
+    for listener in config.effective_listeners() {
+        match bind_listener(config, listener).await {
+            Ok(listener) => bound.push(listener),
+            Err(e) => {
+                // Startup fails, so remove the sockets already created for this run
+                for listener in bound {
+                    if let BoundListener::Unix(_, path) = listener {
+                        let _ = std::fs::remove_file(path);
+                    }
+                }
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(bound)
+}
+
+async fn bind_listener(config: &ServerConfig, listener: ListenerConfig) -> io::Result<BoundListener> {
+    Ok(match listener {
+        ListenerConfig::Tcp { address } => BoundListener::Tcp(bind_tcp(&address).await?),
+        ListenerConfig::Unix { path, mode } => {
+            let listener = bind_unix(&path, &mode)?;
+            BoundListener::Unix(listener, path)
+        }
+        ListenerConfig::Tls { address, cert, key } => {
+            let tcp = bind_tcp(&address).await?;
+            let reload_interval = Duration::from_secs(config.tls_reload_interval_secs);
+            BoundListener::Tls(TlsListener::new(tcp, cert, key, reload_interval)?)
+        }
+    })
+}
+
+async fn bind_tcp(address: &str) -> io::Result<TcpListener> {
+    TcpListener::bind(address)
+        .await
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to bind {}: {}", address, e)))
+}
+
+fn bind_unix(path: &Path, mode: &str) -> io::Result<UnixListener> {
+    // Checked before anything touches the path, so a bad mode leaves no socket behind
+    let mode = parse_socket_mode(mode)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid socket mode {}", mode)))?;
+
+    // A socket file left behind by a previous run would make bind fail. Anything
+    // else at the path is left alone.
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("failed to bind {}: the path exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let listener = UnixListener::bind(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("failed to bind {}: {}", path.display(), e)))?;
+
+    if let Err(e) = std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)) {
+        // Do not leave a socket with the umask's permissions for the next run to reuse
+        let _ = std::fs::remove_file(path);
+        return Err(e);
+    }
+
+    Ok(listener)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn unix_socket_replaces_a_stale_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        drop(bind_unix(&path, "660").unwrap());
+        assert!(path.exists());
+
+        let listener = bind_unix(&path, "600").unwrap();
+        assert_eq!(listener.local_addr().unwrap().as_pathname(), Some(path.as_path()));
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[tokio::test]
+    async fn unix_socket_leaves_other_files_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        std::fs::write(&path, "keep me").unwrap();
+
+        let error = bind_unix(&path, "660").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "keep me");
+    }
+
+    #[tokio::test]
+    async fn unix_socket_rejects_a_bad_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        for mode in ["9z", "7777"] {
+            let error = bind_unix(&path, mode).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(!path.exists(), "{mode} left a socket behind");
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_startup_removes_the_sockets_it_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        let config = ServerConfig {
+            listeners: vec![
+                ListenerConfig::Unix {
+                    path: path.clone(),
+                    mode: "660".to_string(),
+                },
+                ListenerConfig::Tcp {
+                    address: "not-an-address".to_string(),
+                },
+            ],
+            ..ServerConfig::default()
+        };
+
+        assert!(bind_listeners(&config).await.is_err());
+        assert!(!path.exists());
+    }
+
+}
@@ -0,0 +1,131 @@
+//! HTTP server runtime: binding the configured listeners, serving the router on
+//! each, and draining them on shutdown
+
+pub mod listener;
+pub mod shutdown;
+pub mod telemetry;
+pub mod tls;
+
+pub use listener::{BoundListener, ClientAddr, bind_listeners};
+pub use shutdown::{Lifecycle, shutdown_signal, track_in_flight};
+pub use telemetry::Telemetry;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+use crate::core::config::ShutdownConfig;
+use crate::services::ComputePool;
+
+/// Serves `app` on every listener until a shutdown signal arrives, then drains.
+///
+/// On SIGTERM/SIGINT the service reports not-ready, keeps accepting for the
+/// configured grace period, closes its listeners and waits up to the drain
+/// timeout for in-flight requests and queued compute jobs. Anything still
+/// running at the deadline is abandoned and logged.
+pub async fn serve(
+    listeners: Vec<BoundListener>,
+    app: Router,
+    lifecycle: Arc<Lifecycle>,
+    compute: Arc<ComputePool>,
+    config: &ShutdownConfig,
+) {
+    let (stop, stopped) = watch::channel(false);
+    let mut servers = JoinSet::new();
+    let mut socket_paths = Vec::new();
+
+    for listener in listeners {
+        info!("Listening on {}", listener.describe());
+        match listener {
+            BoundListener::Tcp(listener) => {
+                servers.spawn(run(listener, app.clone(), stopped.clone()));
+            }
+            BoundListener::Unix(listener, path) => {
+                socket_paths.push(path);
+                servers.spawn(run(listener, app.clone(), stopped.clone()));
+            }
+            BoundListener::Tls(listener) => {
+                servers.spawn(run(listener, app.clone(), stopped.clone()));
+            }
+        }
+    }
+
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        _ = join_all(&mut servers) => {
+            warn!("All listeners stopped unexpectedly");
+            return;
+        }
+    }
+
+    lifecycle.begin_drain();
+    info!(
+        "Draining: reporting not-ready with {} requests in flight",
+        lifecycle.in_flight()
+    );
+
+    if config.grace_period_secs > 0 {
+        tokio::time::sleep(Duration::from_secs(config.grace_period_secs)).await;
+    }
+
+    // Listeners stop accepting; open connections finish their current request
+    let _ = stop.send(true);
+
+    let deadline = Duration::from_secs(config.drain_timeout_secs);
+    let drained = tokio::time::timeout(deadline, async {
+        join_all(&mut servers).await;
+        while compute.queued() + compute.active() > 0 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await;
+
+    match drained {
+        Ok(()) => info!("Drained all in-flight requests"),
+        Err(_) => {
+            warn!(
+                "Drain deadline of {}s reached; abandoning {} in-flight requests, {} running and {} queued compute jobs",
+                config.drain_timeout_secs,
+                lifecycle.in_flight(),
+                compute.active(),
+                compute.queued()
+            );
+            compute.close();
+            servers.abort_all();
+        }
+    }
+
+    for path in socket_paths {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+async fn join_all(servers: &mut JoinSet<()>) {
+    while let Some(result) = servers.join_next().await {
+        if let Err(e) = result {
+            error!("Server task failed: {}", e);
+        }
+    }
+}
+
+async fn run<L>(listener: L, app: Router, mut stopped: watch::Receiver<bool>)
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+    ClientAddr: for<'a> Connected<IncomingStream<'a, L>>,
+{
+    let shutdown = async move {
+        let _ = stopped.wait_for(|stopped| *stopped).await;
+    };
+
+    let app = app.into_make_service_with_connect_info::<ClientAddr>();
+    if let Err(e) = axum::serve(listener, app).with_graceful_shutdown(shutdown).await {
+        error!("Server error: {}", e);
+    }
+}
@@ -0,0 +1,77 @@
+//! Shutdown coordination: signal handling, readiness while draining and in-flight tracking
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use axum::{extract::Request, extract::State, middleware::Next, response::Response};
+use tracing::info;
+
+use crate::core::models::AppState;
+
+/// Process-wide lifecycle state shared by the server loop, middleware and health checks
+#[derive(Debug, Default)]
+pub struct Lifecycle {
+    draining: AtomicBool,
+    in_flight: AtomicUsize,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True once shutdown has started; the service must report not-ready
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    /// Requests currently being handled
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    fn enter(self: &Arc<Self>) -> InFlightGuard {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+}
+
+/// Decrements the in-flight count when the request finishes, even if its future is dropped
+struct InFlightGuard(Arc<Lifecycle>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware counting requests in flight
+pub async fn track_in_flight(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let _guard = state.lifecycle.enter();
+    next.run(request).await
+}
+
+/// Resolves on the first SIGINT (Ctrl+C) or SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl+C handler");
+    };
+
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT, shutting down"),
+        _ = terminate => info!("Received SIGTERM, shutting down"),
+    }
+}
@@ -0,0 +1,156 @@
+//! Optional OpenTelemetry export of spans and metrics over OTLP/HTTP.
+//!
+//! Nothing here is installed unless `telemetry.enabled` is set: no exporter threads,
+//! no tracing layer, and W3C trace context is neither read from incoming requests
+//! nor injected into outgoing ones.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::{global, trace::TracerProvider as _};
+use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    metrics::{PeriodicReader, SdkMeterProvider},
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider},
+};
+use thiserror::Error;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::core::config::TelemetryConfig;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Error, Debug)]
+pub enum TelemetryError {
+    #[error("Failed to build OTLP exporter: {0}")]
+    Exporter(#[from] opentelemetry_otlp::ExporterBuildError),
+}
+
+/// Installed trace and metric pipelines, flushed by [`Telemetry::shutdown`]
+#[derive(Debug)]
+pub struct Telemetry {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+impl Telemetry {
+    /// Builds the OTLP pipelines when telemetry is enabled, and installs the global
+    /// meter provider and `traceparent` propagator
+    pub fn init(config: &TelemetryConfig) -> Result<Option<Self>, TelemetryError> {
+        if !config.enabled {
+            return Ok(None);
+        }
+
+        let endpoint = config.endpoint.trim_end_matches('/');
+        let resource = Resource::builder()
+            .with_service_name(config.service_name.clone())
+            .build();
+
+        let span_exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{endpoint}/v1/traces"))
+            .build()?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(span_exporter)
+            // Follow the caller's sampling decision, sample new traces by ratio
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                config.sampling_ratio,
+            ))))
+            .with_resource(resource.clone())
+            .build();
+
+        let metric_exporter = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{endpoint}/v1/metrics"))
+            .build()?;
+        let reader = PeriodicReader::builder(metric_exporter)
+            .with_interval(Duration::from_secs(config.metrics_interval_secs))
+            .build();
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(reader)
+            .with_resource(resource)
+            .build();
+
+        global::set_meter_provider(meter_provider.clone());
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        ENABLED.store(true, Ordering::Relaxed);
+
+        Ok(Some(Self {
+            tracer_provider,
+            meter_provider,
+        }))
+    }
+
+    /// A `tracing` layer that exports spans through this pipeline
+    pub fn layer<S>(&self) -> tracing_opentelemetry::OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+    where
+        S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let tracer = self.tracer_provider.tracer(env!("CARGO_PKG_NAME"));
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    }
+
+    /// Flushes pending spans and metrics
+    pub fn shutdown(self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            tracing::warn!("Failed to flush traces: {}", e);
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            tracing::warn!("Failed to flush metrics: {}", e);
+        }
+    }
+}
+
+/// Whether OTLP export is active
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Continues the trace from an incoming `traceparent` header in `span`
+pub fn set_remote_parent(span: &Span, headers: &HeaderMap) {
+    if !enabled() {
+        return;
+    }
+    let parent = global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    // Fails only when the span is disabled, in which case there is nothing to link
+    let _ = span.set_parent(parent);
+}
+
+/// Adds the current span's trace context to an outgoing request's headers
+pub fn inject_context(headers: &mut HeaderMap) {
+    if !enabled() {
+        return;
+    }
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(headers));
+    });
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(key.as_bytes()), HeaderValue::from_str(&value)) {
+            self.0.insert(name, value);
+        }
+    }
+}
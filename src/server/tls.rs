@@ -0,0 +1,161 @@
+//! HTTPS listener backed by rustls, with certificate reload on file change
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use axum::serve::Listener;
+use rustls::ServerConfig;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+/// How long a client may take to complete the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshaken connections waiting for the server to accept them
+const ACCEPT_BACKLOG: usize = 128;
+
+/// A TCP listener that terminates TLS before handing connections to axum.
+///
+/// Handshakes run on their own tasks so a slow client cannot stall other
+/// connections. The certificate and key are re-read whenever either file's
+/// modification time changes; a failed reload keeps the previous certificate.
+pub struct TlsListener {
+    incoming: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    local_addr: SocketAddr,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, cert: PathBuf, key: PathBuf, reload_interval: Duration) -> io::Result<Self> {
+        let local_addr = tcp.local_addr()?;
+        let acceptor = Arc::new(RwLock::new(load_acceptor(&cert, &key)?));
+        let (sender, incoming) = mpsc::channel(ACCEPT_BACKLOG);
+
+        tokio::spawn(watch_certificates(
+            cert,
+            key,
+            reload_interval,
+            acceptor.clone(),
+            sender.clone(),
+        ));
+        tokio::spawn(accept_loop(tcp, acceptor, sender));
+
+        Ok(Self { incoming, local_addr })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.incoming.recv().await {
+            Some(connection) => connection,
+            // The accept loop only exits once this listener is dropped
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+type Connections = mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>;
+
+async fn accept_loop(tcp: TcpListener, acceptor: Arc<RwLock<TlsAcceptor>>, sender: Connections) {
+    loop {
+        let (stream, addr) = tokio::select! {
+            _ = sender.closed() => return,
+            accepted = tcp.accept() => match accepted {
+                Ok(connection) => connection,
+                Err(e) => {
+                    warn!("Failed to accept TLS connection: {}", e);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+            },
+        };
+
+        let acceptor = acceptor.read().expect("TLS acceptor lock poisoned").clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => {
+                    let _ = sender.send((stream, addr)).await;
+                }
+                Ok(Err(e)) => debug!("TLS handshake with {} failed: {}", addr, e),
+                Err(_) => debug!("TLS handshake with {} timed out", addr),
+            }
+        });
+    }
+}
+
+async fn watch_certificates(
+    cert: PathBuf,
+    key: PathBuf,
+    interval: Duration,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+    sender: Connections,
+) {
+    let mut last_modified = modified(&cert, &key);
+
+    loop {
+        tokio::select! {
+            _ = sender.closed() => return,
+            _ = tokio::time::sleep(interval) => {}
+        }
+
+        let current = modified(&cert, &key);
+        if current == last_modified {
+            continue;
+        }
+        last_modified = current;
+
+        match load_acceptor(&cert, &key) {
+            Ok(reloaded) => {
+                *acceptor.write().expect("TLS acceptor lock poisoned") = reloaded;
+                info!("Reloaded TLS certificate from {}", cert.display());
+            }
+            Err(e) => warn!("Failed to reload TLS certificate, keeping the previous one: {}", e),
+        }
+    }
+}
+
+fn modified(cert: &Path, key: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let mtime = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (mtime(cert), mtime(key))
+}
+
+fn load_acceptor(cert: &Path, key: &Path) -> io::Result<TlsAcceptor> {
+    let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e))
+    };
+
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(cert, &e))?;
+    if certs.is_empty() {
+        return Err(invalid(cert, &"no certificates found"));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, &e))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, private_key))
+        .map_err(|e| invalid(cert, &e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
@@ -1,42 +1,39 @@
-use crate::core::database::DbPool;
+use crate::core::database::{DbPool, ItemRepository};
+use chrono::{TimeDelta, Utc};
 use sqlx;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
 
-#[derive(Debug)]
-pub struct DatabaseStats {
-    pub total_items: i64,
-    pub total_data_size: i64,
+/// Purges items that have been in the trash for `retention_days` every `interval`
+/// until the process exits; does nothing when `retention_days` is 0
+pub fn spawn_trash_purge_task(items: Arc<dyn ItemRepository>, retention_days: u32, interval: Duration) {
+    if retention_days == 0 {
+        return;
+    }
+    tokio::spawn(async move {
+        loop {
+            let cutoff = Utc::now() - TimeDelta::days(i64::from(retention_days));
+            match items.purge_trash(Some(cutoff)).await {
+                Ok(0) => {}
+                Ok(purged) => info!("Purged {} items from the trash", purged),
+                Err(e) => warn!("Failed to purge the trash: {}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
 }
 
-pub async fn count_items(pool: &DbPool) -> Result<i64, sqlx::Error> {
-    let result = sqlx::query!("SELECT COUNT(*) as count FROM compressed_items")
+/// Versions of the migrations recorded as applied, empty before the first run
+pub async fn applied_migrations(pool: &DbPool) -> Result<Vec<i64>, sqlx::Error> {
+    let exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
         .fetch_one(pool)
         .await?;
-    
-    Ok(result.count.unwrap_or(0))
-}
-
-pub async fn clear_all_items(pool: &DbPool) -> Result<u64, sqlx::Error> {
-    let result = sqlx::query!("DELETE FROM compressed_items")
-        .execute(pool)
-        .await?;
-    
-    Ok(result.rows_affected())
-}
+    if !exists {
+        return Ok(Vec::new());
+    }
 
-pub async fn get_database_stats(pool: &DbPool) -> Result<DatabaseStats, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"
-        SELECT 
-            COUNT(*) as total_items,
-            COALESCE(SUM(LENGTH(data)), 0) as total_data_size
-        FROM compressed_items
-        "#
-    )
-    .fetch_one(pool)
-    .await?;
-    
-    Ok(DatabaseStats {
-        total_items: result.total_items.unwrap_or(0),
-        total_data_size: result.total_data_size.unwrap_or(0),
-    })
+    sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+        .fetch_all(pool)
+        .await
 }
@@ -0,0 +1,407 @@
+//! API key authentication
+//!
+//! Keys look like `rca_<32 random characters>`. Only their SHA-256 hash is stored,
+//! either in a static TOML keys file (read-only, for running without a database) or
+//! in the PostgreSQL `api_keys` table, which also supports creating and revoking keys.
+//!
+//! A keys file lists entries produced by `admin keys create --static`:
+//!
+//! ```toml
+//! [[keys]]
+//! id = "ci"
+//! name = "CI pipeline"
+//! prefix = "rca_3kF9x2"
+//! hash = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+//! scopes = ["compress", "batch"]
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::core::config::AuthConfig;
+use crate::core::models::{ApiKeyInfo, CreatedApiKey, KeySource, Scope};
+
+#[cfg(feature = "database")]
+use crate::core::database::DbPool;
+
+/// Prefix of every generated key, so leaked keys are easy to recognise
+const KEY_PREFIX: &str = "rca_";
+
+/// Characters of the key kept as its visible prefix
+const VISIBLE_PREFIX_LEN: usize = 10;
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("Failed to load keys file {}: {message}", path.display())]
+    KeysFile { path: PathBuf, message: String },
+
+    #[error("API key management requires the database; static keys are read-only")]
+    ReadOnly,
+
+    #[error("Invalid API key request: {0}")]
+    InvalidInput(String),
+
+    #[cfg(feature = "database")]
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// The authenticated caller, attached to the request by the auth middleware
+#[derive(Debug, Clone)]
+pub struct ApiKeyIdentity {
+    pub id: String,
+    pub name: String,
+    pub scopes: Vec<Scope>,
+}
+
+impl ApiKeyIdentity {
+    /// `admin` grants every scope
+    pub fn has_scope(&self, scope: Scope) -> bool {
+        self.scopes.iter().any(|granted| *granted == scope || *granted == Scope::Admin)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeysFile {
+    #[serde(default)]
+    keys: Vec<StaticKey>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StaticKey {
+    id: String,
+    name: String,
+    #[serde(default)]
+    prefix: String,
+    hash: String,
+    scopes: Vec<Scope>,
+}
+
+#[derive(Debug)]
+pub struct AuthService {
+    enabled: bool,
+    static_keys: Vec<StaticKey>,
+    #[cfg(feature = "database")]
+    pool: Option<DbPool>,
+}
+
+impl AuthService {
+    /// Loads static keys from `auth.keys_file`, if set
+    pub fn from_config(config: &AuthConfig) -> Result<Self, AuthError> {
+        let static_keys = if config.keys_file.is_empty() {
+            Vec::new()
+        } else {
+            Self::load_keys_file(Path::new(&config.keys_file))?
+        };
+
+        Ok(Self {
+            enabled: config.enabled,
+            static_keys,
+            #[cfg(feature = "database")]
+            pool: None,
+        })
+    }
+
+    /// Adds the `api_keys` table as a key source
+    #[cfg(feature = "database")]
+    pub fn with_database(mut self, pool: DbPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    fn load_keys_file(path: &Path) -> Result<Vec<StaticKey>, AuthError> {
+        let error = |message: String| AuthError::KeysFile {
+            path: path.to_path_buf(),
+            message,
+        };
+        let contents = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let mut file: KeysFile = toml::from_str(&contents).map_err(|e| error(e.message().to_string()))?;
+        for key in &mut file.keys {
+            if !is_sha256_hex(&key.hash) {
+                return Err(error(format!("key `{}`: hash must be 64 hex characters", key.id)));
+            }
+            key.hash.make_ascii_lowercase();
+        }
+        Ok(file.keys)
+    }
+
+    /// Whether protected routes require a key
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Generates a new random key
+    pub fn generate_key() -> String {
+        let mut bytes = [0u8; 24];
+        rand::rng().fill_bytes(&mut bytes);
+        format!(
+            "{KEY_PREFIX}{}",
+            base64::prelude::BASE64_URL_SAFE_NO_PAD.encode(bytes)
+        )
+    }
+
+    /// SHA-256 of the key, hex encoded; this is what gets stored
+    pub fn hash_key(key: &str) -> String {
+        Sha256::digest(key.as_bytes())
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    /// Visible part of a key, shown in listings
+    pub fn key_prefix(key: &str) -> String {
+        key.chars().take(VISIBLE_PREFIX_LEN).collect()
+    }
+
+    /// Resolves a presented key to its identity, or `None` if it is unknown or revoked
+    pub async fn authenticate(&self, key: &str) -> Result<Option<ApiKeyIdentity>, AuthError> {
+        let hash = Self::hash_key(key);
+        if let Some(key) = self.static_keys.iter().find(|static_key| static_key.hash == hash) {
+            return Ok(Some(ApiKeyIdentity {
+                id: key.id.clone(),
+                name: key.name.clone(),
+                scopes: key.scopes.clone(),
+            }));
+        }
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            let row = sqlx::query_as::<_, (String, String, Vec<String>)>(
+                "SELECT id, name, scopes FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+            )
+            .bind(&hash)
+            .fetch_optional(pool)
+            .await?;
+            return Ok(row.map(|(id, name, scopes)| ApiKeyIdentity {
+                id,
+                name,
+                scopes: parse_scopes(&scopes),
+            }));
+        }
+
+        Ok(None)
+    }
+
+    /// Every active key, static ones first
+    pub async fn list_keys(&self) -> Result<Vec<ApiKeyInfo>, AuthError> {
+        #[allow(unused_mut)] // only extended when the `database` feature is enabled
+        let mut keys: Vec<ApiKeyInfo> = self
+            .static_keys
+            .iter()
+            .map(|key| ApiKeyInfo {
+                id: key.id.clone(),
+                name: key.name.clone(),
+                prefix: key.prefix.clone(),
+                scopes: key.scopes.clone(),
+                source: KeySource::File,
+                created_at: None,
+            })
+            .collect();
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            let rows = sqlx::query_as::<_, (String, String, String, Vec<String>, chrono::DateTime<chrono::Utc>)>(
+                "SELECT id, name, prefix, scopes, created_at FROM api_keys WHERE revoked_at IS NULL ORDER BY created_at",
+            )
+            .fetch_all(pool)
+            .await?;
+            keys.extend(rows.into_iter().map(|(id, name, prefix, scopes, created_at)| ApiKeyInfo {
+                id,
+                name,
+                prefix,
+                scopes: parse_scopes(&scopes),
+                source: KeySource::Database,
+                created_at: Some(created_at),
+            }));
+        }
+
+        Ok(keys)
+    }
+
+    /// Creates a key in the database and returns it with its plaintext secret
+    pub async fn create_key(&self, name: &str, scopes: &[Scope]) -> Result<CreatedApiKey, AuthError> {
+        validate_new_key(name, scopes)?;
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            let key = Self::generate_key();
+            let info = ApiKeyInfo {
+                id: uuid::Uuid::now_v7().to_string(),
+                name: name.trim().to_string(),
+                prefix: Self::key_prefix(&key),
+                scopes: scopes.to_vec(),
+                source: KeySource::Database,
+                created_at: Some(chrono::Utc::now()),
+            };
+            let scope_names: Vec<&str> = scopes.iter().map(|scope| scope.as_str()).collect();
+            sqlx::query(
+                "INSERT INTO api_keys (id, name, prefix, key_hash, scopes, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+            )
+            .bind(&info.id)
+            .bind(&info.name)
+            .bind(&info.prefix)
+            .bind(Self::hash_key(&key))
+            .bind(&scope_names)
+            .bind(info.created_at)
+            .execute(pool)
+            .await?;
+            return Ok(CreatedApiKey { key, info });
+        }
+
+        Err(AuthError::ReadOnly)
+    }
+
+    /// Revokes a database key; returns false if no active key has this id
+    pub async fn revoke_key(&self, id: &str) -> Result<bool, AuthError> {
+        if self.static_keys.iter().any(|key| key.id == id) {
+            return Err(AuthError::ReadOnly);
+        }
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            let result = sqlx::query("UPDATE api_keys SET revoked_at = NOW() WHERE id = $1 AND revoked_at IS NULL")
+                .bind(id)
+                .execute(pool)
+                .await?;
+            return Ok(result.rows_affected() > 0);
+        }
+
+        Err(AuthError::ReadOnly)
+    }
+
+    /// A new key and the keys-file entry that grants it, for DB-less deployments
+    pub fn static_key_entry(id: &str, name: &str, scopes: &[Scope]) -> Result<(String, String), AuthError> {
+        validate_new_key(name, scopes)?;
+        if id.trim().is_empty() {
+            return Err(AuthError::InvalidInput("id must not be empty".to_string()));
+        }
+
+        let key = Self::generate_key();
+        let file = KeysFile {
+            keys: vec![StaticKey {
+                id: id.trim().to_string(),
+                name: name.trim().to_string(),
+                prefix: Self::key_prefix(&key),
+                hash: Self::hash_key(&key),
+                scopes: scopes.to_vec(),
+            }],
+        };
+        let entry = toml::to_string(&file).map_err(|e| AuthError::InvalidInput(e.to_string()))?;
+        Ok((key, entry))
+    }
+}
+
+fn validate_new_key(name: &str, scopes: &[Scope]) -> Result<(), AuthError> {
+    if name.trim().is_empty() {
+        return Err(AuthError::InvalidInput("name must not be empty".to_string()));
+    }
+    if scopes.is_empty() {
+        return Err(AuthError::InvalidInput("at least one scope is required".to_string()));
+    }
+    Ok(())
+}
+
+/// Scopes read back from the database; unknown names are ignored
+#[cfg(feature = "database")]
+fn parse_scopes(names: &[String]) -> Vec<Scope> {
+    names.iter().filter_map(|name| name.parse().ok()).collect()
+}
+
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity(scopes: &[Scope]) -> ApiKeyIdentity {
+        ApiKeyIdentity {
+            id: "test".to_string(),
+            name: "Test".to_string(),
+            scopes: scopes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn scopes_round_trip_through_their_names() {
+        for scope in Scope::ALL {
+            assert_eq!(scope.as_str().parse::<Scope>(), Ok(scope));
+        }
+        assert!("deploy".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn keys_only_carry_the_scopes_they_were_given() {
+        let reader = identity(&[Scope::FilesRead]);
+        assert!(reader.has_scope(Scope::FilesRead));
+        assert!(!reader.has_scope(Scope::FilesWrite));
+        assert!(!reader.has_scope(Scope::Compress));
+        assert!(!reader.has_scope(Scope::Admin));
+    }
+
+    #[test]
+    fn admin_implies_every_scope() {
+        let admin = identity(&[Scope::Admin]);
+        assert!(Scope::ALL.into_iter().all(|scope| admin.has_scope(scope)));
+    }
+
+    #[tokio::test]
+    async fn static_keys_authenticate_with_their_scopes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.toml");
+        let (key, entry) = AuthService::static_key_entry("ci", "CI pipeline", &[Scope::Compress]).unwrap();
+        std::fs::write(&path, entry).unwrap();
+
+        let auth = AuthService::from_config(&AuthConfig {
+            enabled: true,
+            keys_file: path.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+
+        let found = auth.authenticate(&key).await.unwrap().expect("key is known");
+        assert_eq!(found.id, "ci");
+        assert_eq!(found.scopes, vec![Scope::Compress]);
+        assert!(auth.authenticate("rca_unknown").await.unwrap().is_none());
+    }
+
+    #[test]
+    fn static_entries_escape_names() {
+        let name = "Quote \" and \\ backslash\nnewline ünïcode";
+        let (_, entry) = AuthService::static_key_entry("ci", name, &[Scope::Compress, Scope::FilesRead]).unwrap();
+
+        let file: KeysFile = toml::from_str(&entry).unwrap();
+        assert_eq!(file.keys.len(), 1);
+        assert_eq!(file.keys[0].name, name);
+        assert_eq!(file.keys[0].scopes, vec![Scope::Compress, Scope::FilesRead]);
+        assert!(entry.starts_with("[[keys]]\n"));
+    }
+
+    #[test]
+    fn static_entries_need_an_id_name_and_scope() {
+        assert!(AuthService::static_key_entry(" ", "CI", &[Scope::Compress]).is_err());
+        assert!(AuthService::static_key_entry("ci", "", &[Scope::Compress]).is_err());
+        assert!(AuthService::static_key_entry("ci", "CI", &[]).is_err());
+    }
+
+    #[test]
+    fn keys_files_with_unknown_scopes_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.toml");
+        let hash = AuthService::hash_key("rca_test");
+        std::fs::write(&path, format!("[[keys]]\nid = \"old\"\nname = \"Old\"\nhash = \"{hash}\"\nscopes = [\"deploy\"]\n")).unwrap();
+
+        let result = AuthService::from_config(&AuthConfig {
+            enabled: true,
+            keys_file: path.to_string_lossy().into_owned(),
+        });
+        assert!(matches!(result, Err(AuthError::KeysFile { .. })));
+    }
+}
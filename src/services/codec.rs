@@ -0,0 +1,297 @@
+//! General-purpose byte compression for stored items and the data endpoints
+
+use std::io::{self, Read, Write};
+
+use flate2::{
+    Compression,
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+};
+use thiserror::Error;
+
+use crate::core::models::CompressionAlgorithm;
+
+/// Buffer size used by the brotli reader and writer
+const BROTLI_BUFFER_SIZE: usize = 64 * 1024;
+/// Buffer size used when copying decompressed output
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+/// Brotli window size (log2); 22 is the format's default
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+#[derive(Error, Debug)]
+pub enum CodecError {
+    #[error("{algorithm} does not support level {level} (expected {min}..={max})")]
+    InvalidLevel {
+        algorithm: CompressionAlgorithm,
+        level: i32,
+        min: i32,
+        max: i32,
+    },
+
+    #[error("Data is not valid {0}: {1}")]
+    Corrupt(CompressionAlgorithm, io::Error),
+
+    #[error("Decompressed data exceeds {0} bytes")]
+    TooLarge(usize),
+
+    #[error("Compression failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// Checks `level` against the algorithm, falling back to its default when `None`
+pub fn resolve_level(algorithm: CompressionAlgorithm, level: Option<i32>) -> Result<i32, CodecError> {
+    let level = level.unwrap_or_else(|| algorithm.default_level());
+    let levels = algorithm.levels();
+    if !levels.contains(&level) {
+        return Err(CodecError::InvalidLevel {
+            algorithm,
+            level,
+            min: *levels.start(),
+            max: *levels.end(),
+        });
+    }
+    Ok(level)
+}
+
+/// Streaming compressor writing to `W`; call [`Encoder::finish`] to flush the trailer
+pub struct Encoder<W: Write> {
+    inner: EncoderInner<W>,
+}
+
+enum EncoderInner<W: Write> {
+    Identity(W),
+    Gzip(GzEncoder<W>),
+    Deflate(ZlibEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+    Brotli(Box<brotli::CompressorWriter<W>>),
+    Lz4(lz4::Encoder<W>),
+    Xz(liblzma::write::XzEncoder<W>),
+    Bzip2(bzip2::write::BzEncoder<W>),
+}
+
+impl<W: Write> Encoder<W> {
+    /// Starts a stream; `level` must already be valid for the algorithm
+    pub fn new(algorithm: CompressionAlgorithm, level: i32, output: W) -> Result<Self, CodecError> {
+        let inner = match algorithm {
+            CompressionAlgorithm::Identity => EncoderInner::Identity(output),
+            CompressionAlgorithm::Gzip => EncoderInner::Gzip(GzEncoder::new(output, Compression::new(level as u32))),
+            CompressionAlgorithm::Deflate => {
+                EncoderInner::Deflate(ZlibEncoder::new(output, Compression::new(level as u32)))
+            }
+            CompressionAlgorithm::Zstd => EncoderInner::Zstd(zstd::Encoder::new(output, level)?),
+            CompressionAlgorithm::Brotli => EncoderInner::Brotli(Box::new(brotli::CompressorWriter::new(
+                output,
+                BROTLI_BUFFER_SIZE,
+                level as u32,
+                BROTLI_WINDOW_BITS,
+            ))),
+            CompressionAlgorithm::Lz4 => {
+                EncoderInner::Lz4(lz4::EncoderBuilder::new().level(level as u32).build(output)?)
+            }
+            CompressionAlgorithm::Xz => EncoderInner::Xz(liblzma::write::XzEncoder::new(output, level as u32)),
+            CompressionAlgorithm::Bzip2 => EncoderInner::Bzip2(bzip2::write::BzEncoder::new(
+                output,
+                bzip2::Compression::new(level as u32),
+            )),
+        };
+        Ok(Self { inner })
+    }
+
+    /// Ends the stream and returns the underlying writer
+    pub fn finish(self) -> Result<W, CodecError> {
+        let output = match self.inner {
+            EncoderInner::Identity(output) => output,
+            EncoderInner::Gzip(encoder) => encoder.finish()?,
+            EncoderInner::Deflate(encoder) => encoder.finish()?,
+            EncoderInner::Zstd(encoder) => encoder.finish()?,
+            // Closing the brotli stream happens in `into_inner`
+            EncoderInner::Brotli(encoder) => encoder.into_inner(),
+            EncoderInner::Lz4(encoder) => {
+                let (output, result) = encoder.finish();
+                result?;
+                output
+            }
+            EncoderInner::Xz(encoder) => encoder.finish()?,
+            EncoderInner::Bzip2(encoder) => encoder.finish()?,
+        };
+        Ok(output)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.inner {
+            EncoderInner::Identity(w) => w.write(buf),
+            EncoderInner::Gzip(w) => w.write(buf),
+            EncoderInner::Deflate(w) => w.write(buf),
+            EncoderInner::Zstd(w) => w.write(buf),
+            EncoderInner::Brotli(w) => w.write(buf),
+            EncoderInner::Lz4(w) => w.write(buf),
+            EncoderInner::Xz(w) => w.write(buf),
+            EncoderInner::Bzip2(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.inner {
+            EncoderInner::Identity(w) => w.flush(),
+            EncoderInner::Gzip(w) => w.flush(),
+            EncoderInner::Deflate(w) => w.flush(),
+            EncoderInner::Zstd(w) => w.flush(),
+            EncoderInner::Brotli(w) => w.flush(),
+            EncoderInner::Lz4(w) => w.flush(),
+            EncoderInner::Xz(w) => w.flush(),
+            EncoderInner::Bzip2(w) => w.flush(),
+        }
+    }
+}
+
+/// Compresses `data`; `level` must already be valid for the algorithm
+pub fn compress(algorithm: CompressionAlgorithm, level: i32, data: &[u8]) -> Result<Vec<u8>, CodecError> {
+    let mut encoder = Encoder::new(algorithm, level, Vec::new())?;
+    encoder.write_all(data)?;
+    encoder.finish()
+}
+
+/// Compresses `data` with zstd and a trained dictionary
+pub fn compress_with_dictionary(level: i32, dictionary: &[u8], data: &[u8]) -> Result<Vec<u8>, CodecError> {
+    Ok(zstd::bulk::Compressor::with_dictionary(level, dictionary)?.compress(data)?)
+}
+
+/// Decompresses zstd `data` made with `dictionary`, refusing to produce more than
+/// `max_size` bytes
+pub fn decompress_with_dictionary(dictionary: &[u8], data: &[u8], max_size: usize) -> Result<Vec<u8>, CodecError> {
+    let corrupt = |e| CodecError::Corrupt(CompressionAlgorithm::Zstd, e);
+    let decoder = zstd::stream::Decoder::with_dictionary(data, dictionary).map_err(corrupt)?;
+    read_limited(decoder, max_size).map_err(|e| match e {
+        CodecError::Io(e) => corrupt(e),
+        e => e,
+    })
+}
+
+/// Trains a zstd dictionary of at most `max_size` bytes from sample payloads
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>, CodecError> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// Decompresses `data`, refusing to produce more than `max_size` bytes
+pub fn decompress(algorithm: CompressionAlgorithm, data: &[u8], max_size: usize) -> Result<Vec<u8>, CodecError> {
+    let mut output = Vec::new();
+    decompress_into(algorithm, data, &mut output, max_size)?;
+    Ok(output)
+}
+
+/// Decompresses everything `input` yields into `output`, refusing to produce more than
+/// `max_size` bytes; returns the decompressed size
+pub fn decompress_into<'a, R: Read + 'a, W: Write>(
+    algorithm: CompressionAlgorithm,
+    input: R,
+    mut output: W,
+    max_size: usize,
+) -> Result<u64, CodecError> {
+    let corrupt = |e| CodecError::Corrupt(algorithm, e);
+    let mut reader: Box<dyn Read + 'a> = match algorithm {
+        CompressionAlgorithm::Identity => Box::new(input),
+        CompressionAlgorithm::Gzip => Box::new(GzDecoder::new(input)),
+        CompressionAlgorithm::Deflate => Box::new(ZlibDecoder::new(input)),
+        CompressionAlgorithm::Zstd => Box::new(zstd::stream::Decoder::new(input).map_err(corrupt)?),
+        CompressionAlgorithm::Brotli => Box::new(brotli::Decompressor::new(input, BROTLI_BUFFER_SIZE)),
+        CompressionAlgorithm::Lz4 => Box::new(lz4::Decoder::new(input).map_err(corrupt)?),
+        CompressionAlgorithm::Xz => Box::new(liblzma::read::XzDecoder::new(input)),
+        CompressionAlgorithm::Bzip2 => Box::new(bzip2::read::BzDecoder::new(input)),
+    };
+
+    // Read errors mean bad input; write errors are the output's own problem
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut total = 0u64;
+    loop {
+        let n = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(total),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(corrupt(e)),
+        };
+        total += n as u64;
+        if total > max_size as u64 {
+            return Err(CodecError::TooLarge(max_size));
+        }
+        output.write_all(&buffer[..n])?;
+    }
+}
+
+fn read_limited(reader: impl Read, max_size: usize) -> Result<Vec<u8>, CodecError> {
+    // Read one byte past the limit to tell "exactly max_size" from "too large"
+    let mut output = Vec::new();
+    reader.take(max_size as u64 + 1).read_to_end(&mut output)?;
+    if output.len() > max_size {
+        return Err(CodecError::TooLarge(max_size));
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Vec<u8> {
+        b"The quick brown fox jumps over the lazy dog. ".repeat(200)
+    }
+
+    #[test]
+    fn every_algorithm_round_trips_at_its_level_bounds() {
+        let data = sample();
+        for algorithm in CompressionAlgorithm::ALL {
+            let levels = algorithm.levels();
+            for level in [*levels.start(), algorithm.default_level(), *levels.end()] {
+                let compressed = compress(algorithm, level, &data).unwrap();
+                // Level 0 means "store" for some algorithms, so only the default must shrink
+                if algorithm != CompressionAlgorithm::Identity && level == algorithm.default_level() {
+                    assert!(compressed.len() < data.len(), "{algorithm} level {level} did not shrink the sample");
+                }
+                assert_eq!(decompress(algorithm, &compressed, data.len()).unwrap(), data, "{algorithm} level {level}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_input_round_trips() {
+        for algorithm in CompressionAlgorithm::ALL {
+            let compressed = compress(algorithm, algorithm.default_level(), b"").unwrap();
+            assert!(decompress(algorithm, &compressed, 0).unwrap().is_empty(), "{algorithm}");
+        }
+    }
+
+    #[test]
+    fn levels_are_checked() {
+        assert_eq!(resolve_level(CompressionAlgorithm::Zstd, None).unwrap(), CompressionAlgorithm::Zstd.default_level());
+        assert_eq!(resolve_level(CompressionAlgorithm::Gzip, Some(9)).unwrap(), 9);
+        assert!(matches!(
+            resolve_level(CompressionAlgorithm::Gzip, Some(10)),
+            Err(CodecError::InvalidLevel { min: 0, max: 9, .. })
+        ));
+    }
+
+    #[test]
+    fn output_is_limited() {
+        let data = sample();
+        for algorithm in CompressionAlgorithm::ALL {
+            let compressed = compress(algorithm, algorithm.default_level(), &data).unwrap();
+            assert!(decompress(algorithm, &compressed, data.len()).is_ok(), "{algorithm}");
+            assert!(
+                matches!(decompress(algorithm, &compressed, data.len() - 1), Err(CodecError::TooLarge(_))),
+                "{algorithm}"
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_input_is_reported_as_such() {
+        for algorithm in CompressionAlgorithm::ALL {
+            if algorithm == CompressionAlgorithm::Identity {
+                continue;
+            }
+            let result = decompress(algorithm, b"definitely not compressed data", 1024);
+            assert!(matches!(result, Err(CodecError::Corrupt(..))), "{algorithm}: {result:?}");
+        }
+    }
+}
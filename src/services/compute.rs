@@ -0,0 +1,167 @@
+//! Bounded pool for CPU-heavy work such as decoding and encoding images
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use thiserror::Error;
+use tokio::sync::Semaphore;
+
+#[derive(Error, Debug)]
+pub enum ComputeError {
+    #[error("Compute pool is shut down")]
+    Closed,
+
+    #[error("Compute task panicked")]
+    Panicked,
+}
+
+/// Runs blocking jobs on tokio's blocking threads, at most `workers` at a time.
+///
+/// Jobs beyond that limit wait in a queue; the queued and active counts are
+/// exposed so shutdown and health checks can report on them.
+#[derive(Debug)]
+pub struct ComputePool {
+    permits: Arc<Semaphore>,
+    workers: usize,
+    queued: Arc<AtomicUsize>,
+    active: Arc<AtomicUsize>,
+}
+
+/// Holds one unit of a counter and gives it back when dropped, so the count
+/// stays right even if the job's future is cancelled
+struct CountGuard(Arc<AtomicUsize>);
+
+impl CountGuard {
+    fn enter(counter: &Arc<AtomicUsize>) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter.clone())
+    }
+}
+
+impl Drop for CountGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl ComputePool {
+    /// Creates a pool running `workers` jobs concurrently; `0` means one per CPU
+    pub fn new(workers: usize) -> Self {
+        let workers = if workers == 0 {
+            std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        } else {
+            workers
+        };
+
+        Self {
+            permits: Arc::new(Semaphore::new(workers)),
+            workers,
+            queued: Arc::new(AtomicUsize::new(0)),
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    pub async fn run<F, T>(&self, job: F) -> Result<T, ComputeError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let queued = CountGuard::enter(&self.queued);
+        let permit = self.permits.clone().acquire_owned().await;
+        drop(queued);
+        let permit = permit.map_err(|_| ComputeError::Closed)?;
+
+        // The guard moves into the job: a dropped caller does not stop the blocking
+        // thread, so the job counts as active until it actually returns
+        let active = CountGuard::enter(&self.active);
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            let _active = active;
+            job()
+        })
+        .await
+        .map_err(|_| ComputeError::Panicked)
+    }
+
+    /// Maximum number of jobs running at once
+    pub fn workers(&self) -> usize {
+        self.workers
+    }
+
+    /// Jobs waiting for a worker
+    pub fn queued(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Jobs currently running
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Stops handing out workers; jobs still queued fail with [`ComputeError::Closed`]
+    pub fn close(&self) {
+        self.permits.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn zero_workers_means_one_per_cpu() {
+        let pool = ComputePool::new(0);
+        assert!(pool.workers() >= 1);
+        assert_eq!(ComputePool::new(3).workers(), 3);
+    }
+
+    #[tokio::test]
+    async fn runs_jobs_and_reports_panics() {
+        let pool = ComputePool::new(1);
+        assert_eq!(pool.run(|| 2 + 2).await.unwrap(), 4);
+        assert!(matches!(pool.run(|| panic!("boom")).await, Err(ComputeError::Panicked)));
+        assert_eq!(pool.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn counts_recover_when_a_queued_job_is_cancelled() {
+        let pool = Arc::new(ComputePool::new(1));
+        let (release, wait) = mpsc::channel::<()>();
+
+        let running = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(move || wait.recv().ok()).await }
+        });
+        while pool.active() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let queued = tokio::spawn({
+            let pool = pool.clone();
+            async move { pool.run(|| ()).await }
+        });
+        while pool.queued() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        queued.abort();
+        let _ = queued.await;
+        assert_eq!(pool.queued(), 0);
+        assert_eq!(pool.active(), 1);
+
+        release.send(()).unwrap();
+        running.await.unwrap().unwrap();
+        assert_eq!(pool.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn closed_pool_rejects_jobs() {
+        let pool = ComputePool::new(1);
+        pool.close();
+        assert!(matches!(pool.run(|| ()).await, Err(ComputeError::Closed)));
+        assert_eq!(pool.queued(), 0);
+    }
+}
@@ -0,0 +1,219 @@
+//! Compression and decompression of arbitrary payloads for `/compress/data` and
+//! `/decompress/data`.
+//!
+//! Request bodies are received into a spool (memory, then a temporary file under
+//! `storage.path`) before a compute worker is taken, so a slow upload never holds one.
+//! Output is spooled the same way and streamed back from there.
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use axum::body::Bytes;
+use futures_util::{Stream, StreamExt};
+use thiserror::Error;
+use tracing::field;
+
+use crate::core::config::AppConfig;
+use crate::core::models::CompressionAlgorithm;
+use crate::services::codec::{self, CodecError, Encoder};
+use crate::services::compute::{ComputeError, ComputePool};
+use crate::utils::spool::{Spool, SpoolWriter};
+
+#[derive(Error, Debug)]
+pub enum DataError {
+    #[error("Request body exceeds {0} bytes")]
+    TooLarge(u64),
+
+    #[error("Failed to read request body: {0}")]
+    Body(String),
+
+    #[error("Failed to buffer data: {0}")]
+    Spool(#[from] io::Error),
+
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+
+    #[error("Data processing failed: {0}")]
+    Compute(#[from] ComputeError),
+}
+
+/// Result of one compression or decompression
+#[derive(Debug)]
+pub struct DataOutcome {
+    pub output: Spool,
+    /// Bytes read from the request
+    pub input_size: u64,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug)]
+pub struct DataCompressionService {
+    compute: Arc<ComputePool>,
+    max_data_size: u64,
+    spool_dir: PathBuf,
+}
+
+impl DataCompressionService {
+    pub fn from_config(config: &AppConfig, compute: Arc<ComputePool>) -> Self {
+        Self {
+            compute,
+            max_data_size: config.limits.max_data_size,
+            spool_dir: config.storage.path.clone(),
+        }
+    }
+
+    /// Maximum accepted body, and maximum decompressed output, in bytes
+    pub fn max_data_size(&self) -> u64 {
+        self.max_data_size
+    }
+
+    /// Reads `body` into a spool, enforcing the size limit
+    pub async fn receive<S, E>(&self, mut body: S) -> Result<Spool, DataError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: std::fmt::Display,
+    {
+        let mut input = SpoolWriter::new(&self.spool_dir);
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| DataError::Body(e.to_string()))?;
+            if input.len() + chunk.len() as u64 > self.max_data_size {
+                return Err(DataError::TooLarge(self.max_data_size));
+            }
+            input.write_chunk(chunk).await?;
+        }
+        Ok(input.finish()?)
+    }
+
+    /// Compresses a received body; `level` must already be valid for the algorithm
+    #[tracing::instrument(
+        name = "compress_data",
+        skip_all,
+        fields(algorithm = %algorithm, level, input_size = field::Empty, output_size = field::Empty)
+    )]
+    pub async fn compress(&self, input: Spool, algorithm: CompressionAlgorithm, level: i32) -> Result<DataOutcome, DataError> {
+        self.process(input, move |mut input, output| {
+            let mut encoder = Encoder::new(algorithm, level, output)?;
+            io::copy(&mut input, &mut encoder)?;
+            encoder.finish()?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Decompresses a received body, up to `max_data_size` bytes of output
+    #[tracing::instrument(
+        name = "decompress_data",
+        skip_all,
+        fields(algorithm = %algorithm, input_size = field::Empty, output_size = field::Empty)
+    )]
+    pub async fn decompress(&self, input: Spool, algorithm: CompressionAlgorithm) -> Result<DataOutcome, DataError> {
+        let max_output = self.max_data_size as usize;
+        self.process(input, move |input, output| {
+            codec::decompress_into(algorithm, input, output, max_output)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Runs `job` over `input` on the compute pool, spooling what it writes
+    async fn process<F>(&self, input: Spool, job: F) -> Result<DataOutcome, DataError>
+    where
+        F: FnOnce(Box<dyn io::Read + Send>, &mut SpoolWriter) -> Result<(), CodecError> + Send + 'static,
+    {
+        let started = Instant::now();
+        let input_size = input.len();
+        let spool_dir = self.spool_dir.clone();
+        let span = tracing::Span::current();
+        let output = self
+            .compute
+            .run(move || {
+                let _entered = span.enter();
+                let mut output = SpoolWriter::new(spool_dir);
+                job(input.into_reader(), &mut output)?;
+                Ok::<_, CodecError>(output.finish()?)
+            })
+            .await??;
+
+        let span = tracing::Span::current();
+        span.record("input_size", input_size);
+        span.record("output_size", output.len());
+        Ok(DataOutcome {
+            output,
+            input_size,
+            elapsed: started.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::utils::spool::MEMORY_LIMIT;
+
+    fn service(dir: &std::path::Path, max_data_size: u64) -> (DataCompressionService, Arc<ComputePool>) {
+        let mut config = AppConfig::default();
+        config.storage.path = dir.to_path_buf();
+        config.limits.max_data_size = max_data_size;
+        let compute = Arc::new(ComputePool::new(1));
+        (DataCompressionService::from_config(&config, compute.clone()), compute)
+    }
+
+    fn chunks(data: &[u8]) -> impl Stream<Item = Result<Bytes, Infallible>> + Unpin + use<> {
+        let chunks: Vec<_> = data.chunks(64 * 1024).map(|chunk| Ok(Bytes::copy_from_slice(chunk))).collect();
+        futures_util::stream::iter(chunks)
+    }
+
+    #[tokio::test]
+    async fn large_payloads_round_trip_through_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = service(dir.path(), 16 * 1024 * 1024);
+        let data: Vec<u8> = (0..MEMORY_LIMIT as u32 * 2).map(|i| (i * 7 % 256) as u8).collect();
+
+        let input = service.receive(chunks(&data)).await.unwrap();
+        assert!(matches!(input, Spool::File { .. }));
+        let compressed = service.compress(input, CompressionAlgorithm::Zstd, 3).await.unwrap();
+        assert_eq!(compressed.input_size, data.len() as u64);
+
+        let input = Spool::from(compressed.output.into_bytes().await.unwrap().to_vec());
+        let restored = service.decompress(input, CompressionAlgorithm::Zstd).await.unwrap();
+        assert!(matches!(restored.output, Spool::File { .. }));
+        assert_eq!(restored.output.into_bytes().await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn bodies_over_the_limit_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = service(dir.path(), 100);
+        let result = service.receive(chunks(&[0; 101])).await;
+        assert!(matches!(result, Err(DataError::TooLarge(100))));
+    }
+
+    #[tokio::test]
+    async fn decompressed_output_is_limited() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, _) = service(dir.path(), 1000);
+        let bomb = codec::compress(CompressionAlgorithm::Gzip, 6, &[0; 10_000]).unwrap();
+        let result = service.decompress(Spool::from(bomb), CompressionAlgorithm::Gzip).await;
+        assert!(matches!(result, Err(DataError::Codec(CodecError::TooLarge(1000)))));
+    }
+
+    #[tokio::test]
+    async fn a_slow_upload_does_not_hold_a_worker() {
+        let dir = tempfile::tempdir().unwrap();
+        let (service, compute) = service(dir.path(), 1000);
+        let stalled = futures_util::stream::iter([Ok::<_, Infallible>(Bytes::from_static(b"partial"))])
+            .chain(futures_util::stream::pending());
+
+        tokio::select! {
+            _ = service.receive(stalled) => panic!("a stalled body finished"),
+            result = tokio::time::timeout(Duration::from_secs(5), compute.run(|| 42)) => {
+                assert_eq!(result.expect("the only worker was taken").unwrap(), 42);
+            }
+        }
+    }
+}
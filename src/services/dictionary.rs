@@ -0,0 +1,241 @@
+//! zstd dictionaries for small items: training from stored items, and a cache of the
+//! dictionary versions needed to read them back
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+
+use thiserror::Error;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::core::database::{DbError, ItemRepository};
+use crate::core::models::{CompressionAlgorithm, ItemPayload, StoredItem};
+use crate::services::codec::{self, CodecError};
+use crate::utils::checksum::sha256_hex;
+
+/// Items sampled when a training request does not say
+pub const DEFAULT_SAMPLE_SIZE: u32 = 1000;
+/// Upper bound on sampled items, to keep training memory bounded
+pub const MAX_SAMPLE_SIZE: u32 = 100_000;
+/// Dictionary size when a training request does not say; zstd's own default
+pub const DEFAULT_DICTIONARY_SIZE: u32 = 112_640;
+
+#[derive(Error, Debug)]
+pub enum DictionaryError {
+    #[error("There are no items to train a dictionary on")]
+    NoSamples,
+
+    #[error("Dictionary training failed: {0}")]
+    Training(CodecError),
+
+    #[error("Item {0} could not be read: {1}")]
+    Sample(Uuid, String),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+}
+
+/// Dictionary bytes by version
+pub type Dictionaries = HashMap<i32, Arc<Vec<u8>>>;
+
+/// Caches dictionaries by version. Versions never change once stored, so nothing is
+/// ever evicted; only which version is active is looked up each time.
+#[derive(Debug, Default)]
+pub struct DictionaryStore {
+    cache: RwLock<Dictionaries>,
+}
+
+/// Sampled items and the dictionaries needed to decompress them
+pub struct TrainingSet {
+    items: Vec<StoredItem>,
+    dictionaries: Dictionaries,
+}
+
+/// A dictionary trained from a [`TrainingSet`], not yet stored
+pub struct TrainedDictionary {
+    pub data: Vec<u8>,
+    pub sample_count: i32,
+    pub sample_bytes: i64,
+}
+
+impl DictionaryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn get(&self, repo: &dyn ItemRepository, version: i32) -> Result<Arc<Vec<u8>>, DbError> {
+        if let Some(dictionary) = self.cache.read().await.get(&version) {
+            return Ok(dictionary.clone());
+        }
+        let dictionary = Arc::new(repo.get_dictionary_data(version).await?);
+        self.cache.write().await.insert(version, dictionary.clone());
+        Ok(dictionary)
+    }
+
+    /// The active dictionary, if any. Looked up on every call so a dictionary trained
+    /// by another instance or the admin CLI takes effect without a restart.
+    pub async fn active(&self, repo: &dyn ItemRepository) -> Result<Option<(i32, Arc<Vec<u8>>)>, DbError> {
+        match repo.active_dictionary_version().await? {
+            Some(version) => Ok(Some((version, self.get(repo, version).await?))),
+            None => Ok(None),
+        }
+    }
+
+    /// The dictionaries `items` were compressed with
+    pub async fn for_items(&self, repo: &dyn ItemRepository, items: &[StoredItem]) -> Result<Dictionaries, DbError> {
+        let mut dictionaries = Dictionaries::new();
+        for version in items.iter().filter_map(|item| item.dictionary_version) {
+            if let Entry::Vacant(entry) = dictionaries.entry(version) {
+                entry.insert(self.get(repo, version).await?);
+            }
+        }
+        Ok(dictionaries)
+    }
+
+    /// Like [`DictionaryStore::for_items`], but leaves out versions that no longer
+    /// exist so [`check_item`](crate::services::integrity::check_item) can report the
+    /// items that need them
+    pub async fn available_for_items(
+        &self,
+        repo: &dyn ItemRepository,
+        items: &[StoredItem],
+    ) -> Result<Dictionaries, DbError> {
+        let mut dictionaries = Dictionaries::new();
+        for version in items.iter().filter_map(|item| item.dictionary_version) {
+            if let Entry::Vacant(entry) = dictionaries.entry(version) {
+                match self.get(repo, version).await {
+                    Ok(dictionary) => {
+                        entry.insert(dictionary);
+                    }
+                    Err(DbError::NotFound) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(dictionaries)
+    }
+
+    /// A random sample of up to `sample_size` items to train on
+    pub async fn training_set(&self, repo: &dyn ItemRepository, sample_size: u32) -> Result<TrainingSet, DictionaryError> {
+        let items = repo.sample_items(sample_size.min(MAX_SAMPLE_SIZE)).await?;
+        if items.is_empty() {
+            return Err(DictionaryError::NoSamples);
+        }
+        let dictionaries = self.for_items(repo, &items).await?;
+        Ok(TrainingSet { items, dictionaries })
+    }
+}
+
+/// Compresses item data, with `dictionary` when given, and hashes both sides;
+/// CPU-bound, so run it off the async runtime
+pub fn compress_item(
+    algorithm: CompressionAlgorithm,
+    level: i32,
+    dictionary: Option<(i32, Arc<Vec<u8>>)>,
+    data: &[u8],
+) -> Result<ItemPayload, CodecError> {
+    let compressed = match &dictionary {
+        Some((_, dictionary)) => codec::compress_with_dictionary(level, dictionary, data)?,
+        None => codec::compress(algorithm, level, data)?,
+    };
+    Ok(ItemPayload {
+        original_sha256: sha256_hex(data),
+        compressed_sha256: sha256_hex(&compressed),
+        data: compressed,
+        algorithm,
+        level,
+        dictionary_version: dictionary.map(|(version, _)| version),
+        original_size: data.len() as u64,
+    })
+}
+
+/// Decompresses a stored item; `dictionaries` must hold its dictionary, if it has one
+pub fn decompress_item(
+    algorithm: CompressionAlgorithm,
+    item: &StoredItem,
+    dictionaries: &Dictionaries,
+) -> Result<Vec<u8>, CodecError> {
+    let max_size = item.original_size as usize;
+    match item.dictionary_version.and_then(|version| dictionaries.get(&version)) {
+        Some(dictionary) => codec::decompress_with_dictionary(dictionary, &item.data, max_size),
+        None => codec::decompress(algorithm, &item.data, max_size),
+    }
+}
+
+/// Trains a dictionary of at most `max_size` bytes; CPU-bound, so run it off the
+/// async runtime
+pub fn train(set: TrainingSet, max_size: u32) -> Result<TrainedDictionary, DictionaryError> {
+    let samples = set
+        .items
+        .iter()
+        .map(|item| {
+            let algorithm: CompressionAlgorithm = item
+                .algorithm
+                .parse()
+                .map_err(|e| DictionaryError::Sample(item.id, e))?;
+            decompress_item(algorithm, item, &set.dictionaries)
+                .map_err(|e| DictionaryError::Sample(item.id, e.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let sample_bytes = samples.iter().map(|sample| sample.len() as i64).sum();
+    let data = codec::train_dictionary(&samples, max_size as usize).map_err(DictionaryError::Training)?;
+    Ok(TrainedDictionary {
+        data,
+        sample_count: samples.len() as i32,
+        sample_bytes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::database;
+
+    fn record(i: usize) -> Vec<u8> {
+        format!(r#"{{"id":{i},"status":"active","region":"eu-west-{}","tags":["a","b"]}}"#, i % 3).into_bytes()
+    }
+
+    #[tokio::test]
+    async fn trains_on_stored_items_and_reads_them_back() {
+        let storage = database::connect("memory:", false).await.unwrap();
+        let repo = storage.items.as_ref();
+        let store = DictionaryStore::new();
+        assert!(matches!(store.training_set(repo, 10).await, Err(DictionaryError::NoSamples)));
+
+        for i in 0..200 {
+            let payload = compress_item(CompressionAlgorithm::Gzip, 6, None, &record(i)).unwrap();
+            repo.create_item(&format!("record-{i}"), payload).await.unwrap();
+        }
+        let trained = train(store.training_set(repo, 200).await.unwrap(), 4096).unwrap();
+        assert_eq!(trained.sample_count, 200);
+        assert!(!trained.data.is_empty() && trained.data.len() <= 4096);
+
+        let info = repo.insert_dictionary(&trained.data, trained.sample_count, trained.sample_bytes, true).await.unwrap();
+        let (version, dictionary) = store.active(repo).await.unwrap().unwrap();
+        assert_eq!(version, info.version);
+
+        let data = record(7);
+        let plain = compress_item(CompressionAlgorithm::Zstd, 3, None, &data).unwrap();
+        let payload = compress_item(CompressionAlgorithm::Zstd, 3, Some((version, dictionary)), &data).unwrap();
+        assert_eq!(payload.dictionary_version, Some(version));
+        assert!(payload.data.len() < plain.data.len());
+        assert_eq!(payload.original_sha256, plain.original_sha256);
+
+        let item = repo.create_item("with-dictionary", payload).await.unwrap();
+        let dictionaries = store.for_items(repo, std::slice::from_ref(&item)).await.unwrap();
+        assert!(dictionaries.contains_key(&version));
+        assert_eq!(decompress_item(CompressionAlgorithm::Zstd, &item, &dictionaries).unwrap(), data);
+        // Without its dictionary the item cannot be read
+        assert!(decompress_item(CompressionAlgorithm::Zstd, &item, &Dictionaries::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn unknown_versions_are_not_found() {
+        let storage = database::connect("memory:", false).await.unwrap();
+        let store = DictionaryStore::new();
+        assert!(store.active(storage.items.as_ref()).await.unwrap().is_none());
+        assert!(matches!(store.get(storage.items.as_ref(), 42).await, Err(DbError::NotFound)));
+    }
+}
@@ -0,0 +1,148 @@
+//! Liveness and readiness checks for `/health/live` and `/health/ready`
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::core::config::AppConfig;
+use crate::core::models::{CheckStatus, HealthCheck, LivenessResponse, ReadinessResponse, ReadinessStatus};
+use crate::server::Lifecycle;
+use crate::services::ComputePool;
+
+#[cfg(feature = "database")]
+use crate::core::database::DbPool;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug)]
+pub struct HealthService {
+    started_at: Instant,
+    storage_path: PathBuf,
+    max_queued_per_worker: usize,
+    check_timeout: Duration,
+    compute: Arc<ComputePool>,
+    lifecycle: Arc<Lifecycle>,
+    #[cfg(feature = "database")]
+    pool: Option<DbPool>,
+}
+
+impl HealthService {
+    pub fn new(config: &AppConfig, compute: Arc<ComputePool>, lifecycle: Arc<Lifecycle>) -> Self {
+        Self {
+            started_at: Instant::now(),
+            storage_path: config.storage.path.clone(),
+            max_queued_per_worker: config.health.max_queued_per_worker,
+            check_timeout: Duration::from_millis(config.health.check_timeout_ms),
+            compute,
+            lifecycle,
+            #[cfg(feature = "database")]
+            pool: None,
+        }
+    }
+
+    /// Adds a database connectivity check to readiness
+    #[cfg(feature = "database")]
+    pub fn with_database(mut self, pool: DbPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn liveness(&self) -> LivenessResponse {
+        LivenessResponse {
+            status: "alive".to_string(),
+            version: VERSION.to_string(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+        }
+    }
+
+    /// Runs every readiness check; the service is ready only if all of them pass
+    pub async fn readiness(&self) -> ReadinessResponse {
+        let mut checks = BTreeMap::new();
+        checks.insert("draining".to_string(), self.timed(async { self.check_draining() }).await);
+        checks.insert("compute".to_string(), self.timed(async { self.check_compute() }).await);
+        checks.insert("storage".to_string(), self.timed(self.check_storage()).await);
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            checks.insert("database".to_string(), self.timed(Self::check_database(pool)).await);
+        }
+
+        let ready = checks.values().all(|check| check.status == CheckStatus::Ok);
+        ReadinessResponse {
+            status: if ready {
+                ReadinessStatus::Ready
+            } else {
+                ReadinessStatus::NotReady
+            },
+            version: VERSION.to_string(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            checks,
+        }
+    }
+
+    /// Runs a check under the configured timeout and records how long it took
+    async fn timed<F>(&self, check: F) -> HealthCheck
+    where
+        F: Future<Output = Result<Option<String>, String>>,
+    {
+        let start = Instant::now();
+        let result = match tokio::time::timeout(self.check_timeout, check).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {} ms", self.check_timeout.as_millis())),
+        };
+        let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+        match result {
+            Ok(message) => HealthCheck {
+                status: CheckStatus::Ok,
+                latency_ms,
+                message,
+            },
+            Err(message) => HealthCheck {
+                status: CheckStatus::Fail,
+                latency_ms,
+                message: Some(message),
+            },
+        }
+    }
+
+    fn check_draining(&self) -> Result<Option<String>, String> {
+        if self.lifecycle.is_draining() {
+            Err("shutting down".to_string())
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn check_compute(&self) -> Result<Option<String>, String> {
+        let workers = self.compute.workers();
+        let queued = self.compute.queued();
+        let detail = format!("{} active, {} queued, {} workers", self.compute.active(), queued, workers);
+        if queued > workers * self.max_queued_per_worker {
+            Err(format!("saturated: {detail}"))
+        } else {
+            Ok(Some(detail))
+        }
+    }
+
+    /// Writes and removes a probe file in the storage directory
+    async fn check_storage(&self) -> Result<Option<String>, String> {
+        let probe = self.storage_path.join(format!(".ready-probe-{}", std::process::id()));
+        let fail = |e: std::io::Error| format!("{} is not writable: {}", self.storage_path.display(), e);
+
+        tokio::fs::create_dir_all(&self.storage_path).await.map_err(fail)?;
+        tokio::fs::write(&probe, b"ok").await.map_err(fail)?;
+        tokio::fs::remove_file(&probe).await.map_err(fail)?;
+        Ok(None)
+    }
+
+    #[cfg(feature = "database")]
+    async fn check_database(pool: &DbPool) -> Result<Option<String>, String> {
+        sqlx::query("SELECT 1")
+            .execute(pool)
+            .await
+            .map(|_| None)
+            .map_err(|e| e.to_string())
+    }
+}
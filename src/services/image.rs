@@ -1,12 +1,23 @@
-use crate::core::models::{CompressImageRequest, CompressImageResponse};
+use crate::core::config::{AppConfig, DefaultsConfig};
+#[cfg(feature = "database")]
+use crate::core::database::ImageStore;
+#[cfg(feature = "database")]
+use crate::core::models::NewStoredImage;
+use crate::core::models::{CompressImageRequest, CompressImageResponse, CompressionTimings};
+use crate::services::compute::{ComputeError, ComputePool};
+use crate::server::telemetry;
+use crate::services::metrics::{Metrics, Stage};
+use crate::utils::checksum::{ChecksumError, sha256_hex, verify_checksum};
 use base64::prelude::*;
 use image::codecs::jpeg::JpegEncoder;
 // use image::codecs::png::{CompressionType, PngEncoder};
 use image::DynamicImage;
 use reqwest;
 // use std::io::Cursor;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tracing::{error, info, warn};
+use tracing::{Instrument, Span, debug, field, info, info_span, warn};
 use uuid::Uuid;
 
 #[derive(Error, Debug)]
@@ -28,21 +39,137 @@ pub enum ImageProcessingError {
 
     #[error("Image too large: {0} bytes. Maximum allowed: {1} bytes")]
     ImageTooLarge(u64, u64),
+
+    #[error("Invalid image URL: {0}")]
+    InvalidUrl(String),
+
+    #[error(transparent)]
+    Checksum(#[from] ChecksumError),
+
+    #[error("Image processing failed: {0}")]
+    Compute(#[from] ComputeError),
 }
 
+#[derive(Debug)]
 pub struct ImageCompressionService {
     client: reqwest::Client,
+    compute: Arc<ComputePool>,
+    metrics: Arc<Metrics>,
     max_image_size: u64,
+    defaults: DefaultsConfig,
+    /// Where compressed images are kept for reuse, when they are
+    #[cfg(feature = "database")]
+    store: Option<Arc<dyn ImageStore>>,
+}
+
+/// Per-request options for the CPU-bound part of the pipeline
+#[derive(Debug, Clone, Copy)]
+struct ProcessOptions {
+    quality: u8,
+    max_dimensions: Option<(u32, u32)>,
+    thumbnail_size: Option<u32>,
+}
+
+#[cfg(feature = "database")]
+impl ProcessOptions {
+    /// Names everything that shapes the output, so an image compressed with the same
+    /// options can be found again
+    fn key(&self) -> String {
+        let fit = self.max_dimensions.map_or("none".to_string(), |(width, height)| format!("{width}x{height}"));
+        let thumbnail = self.thumbnail_size.map_or("none".to_string(), |size| size.to_string());
+        format!("jpeg;quality={};fit={fit};thumbnail={thumbnail}", self.quality)
+    }
+}
+
+struct ProcessedImage {
+    content_type: String,
+    compressed_data: Vec<u8>,
+    compressed_sha256: String,
+    thumbnail: Option<Vec<u8>>,
+    thumbnail_failed: bool,
+    timings: StageTimings,
 }
 
+/// Wall-clock time spent in each stage of one compression
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StageTimings {
+    pub fetch: Duration,
+    pub decode: Duration,
+    pub resize: Duration,
+    pub encode: Duration,
+    pub thumbnail: Duration,
+}
+
+impl StageTimings {
+    fn to_response(self, total: Duration) -> CompressionTimings {
+        // Microsecond precision is plenty and keeps the JSON readable
+        let ms = |duration: Duration| duration.as_micros() as f64 / 1000.0;
+        CompressionTimings {
+            fetch_ms: ms(self.fetch),
+            decode_ms: ms(self.decode),
+            resize_ms: ms(self.resize),
+            encode_ms: ms(self.encode),
+            thumbnail_ms: ms(self.thumbnail),
+            total_ms: ms(total),
+        }
+    }
+}
+
+/// Every compressed image and thumbnail is encoded as JPEG
+const OUTPUT_CONTENT_TYPE: &str = "image/jpeg";
+
 impl ImageCompressionService {
+    /// Creates a service with the built-in default configuration
     pub fn new() -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            max_image_size: 10 * 1024 * 1024, // 10MB limit
-        }
+        let config = AppConfig::default();
+        let compute = Arc::new(ComputePool::new(config.limits.compute_workers));
+        Self::from_config(&config, compute, Arc::new(Metrics::new())).expect("Failed to build HTTP client")
+    }
+
+    /// Creates a service from the fetch, limits and defaults sections of the configuration.
+    ///
+    /// The service owns a single `reqwest::Client`, so it should be built once and shared.
+    pub fn from_config(
+        config: &AppConfig,
+        compute: Arc<ComputePool>,
+        metrics: Arc<Metrics>,
+    ) -> Result<Self, reqwest::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(config.fetch.timeout_secs))
+            .connect_timeout(Duration::from_secs(config.fetch.connect_timeout_secs))
+            .redirect(reqwest::redirect::Policy::limited(config.fetch.max_redirects))
+            .user_agent(config.fetch.user_agent.clone())
+            .build()?;
+
+        Ok(Self {
+            client,
+            compute,
+            metrics,
+            max_image_size: config.limits.max_image_size,
+            defaults: config.defaults.clone(),
+            #[cfg(feature = "database")]
+            store: None,
+        })
     }
 
+    /// Keeps every compressed image in `store`, keyed by the SHA-256 of its source and
+    /// its options, and answers a repeat of the same request from there
+    #[cfg(feature = "database")]
+    pub fn with_store(mut self, store: Arc<dyn ImageStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Maximum accepted source image size in bytes
+    pub fn max_image_size(&self) -> u64 {
+        self.max_image_size
+    }
+
+    #[tracing::instrument(
+        name = "compress_image",
+        skip_all,
+        fields(filename = field::Empty, original_size = field::Empty)
+    )]
     pub async fn compress_image(
         &self,
         request: CompressImageRequest,
@@ -50,115 +177,346 @@ impl ImageCompressionService {
         let start_time = std::time::Instant::now();
         
         // Validate quality if provided
-        let quality = request.quality.unwrap_or(75);
+        let quality = request.quality.unwrap_or(self.defaults.quality);
         if quality == 0 || quality > 100 {
             return Err(ImageProcessingError::InvalidInput(
                 "Quality must be between 1 and 100".to_string(),
             ));
         }
 
-        info!("Starting image compression for file: {}", request.filename);
+        let span = Span::current();
+        span.record("filename", request.filename.as_str());
 
         // Get image data from either base64 or URL
+        let fetch_start = Instant::now();
         let image_data = if let Some(base64_data) = &request.image_data {
-            self.decode_base64_image(base64_data)?
+            let fetch_span = info_span!("fetch", source = "base64", bytes = field::Empty);
+            let data = fetch_span.in_scope(|| self.decode_base64_image(base64_data))?;
+            fetch_span.record("bytes", data.len());
+            data
         } else if let Some(url) = &request.image_url {
-            self.download_image(url).await?
+            let fetch_span = info_span!("fetch", source = "url", url = %url, bytes = field::Empty);
+            let data = self
+                .download_image(url)
+                .instrument(fetch_span.clone())
+                .await
+                .inspect_err(|e| {
+                    self.metrics.record_download_error(download_error_cause(e));
+                })?;
+            fetch_span.record("bytes", data.len());
+            data
         } else {
             return Err(ImageProcessingError::InvalidInput(
                 "Either image_data or image_url must be provided".to_string(),
             ));
         };
         let original_size = image_data.len() as u64;
-
-        info!("Downloaded image, size: {} bytes", original_size);
-
-        // Detect content type
-        let content_type = self.detect_content_type(&image_data);
-        info!("Detected content type: {}", content_type);
-
-        // Decode the image
-        let img = image::load_from_memory(&image_data)?;
-        info!(
-            "Image decoded successfully: {}x{}",
-            img.width(),
-            img.height()
-        );
-
-        // Resize the image if max dimensions specified
-        let resized_img = if let (Some(max_width), Some(max_height)) = (request.max_width, request.max_height) {
-            self.resize_image_to_fit(img, max_width, max_height)
-        } else {
-            img
+        let fetch_duration = fetch_start.elapsed();
+        span.record("original_size", original_size);
+
+        let generate_thumbnail = request.generate_thumbnail.unwrap_or(self.defaults.generate_thumbnail);
+        let options = ProcessOptions {
+            quality,
+            max_dimensions: request.max_width.zip(request.max_height),
+            thumbnail_size: generate_thumbnail
+                .then(|| request.thumbnail_size.unwrap_or(self.defaults.thumbnail_size)),
         };
-        
-        info!(
-            "Image processed to: {}x{}",
-            resized_img.width(),
-            resized_img.height()
-        );
 
-        // Compress the image
-        let compressed_data = self.compress_image_data(resized_img.clone(), &content_type, quality)?;
-        let compressed_size = compressed_data.len() as u64;
+        // Hashing is CPU-bound too. The hash is checked against the client's checksum
+        // before anything is decoded, and names the source in the store.
+        let (image_data, original_sha256) = self
+            .compute
+            .run(move || {
+                let sha256 = sha256_hex(&image_data);
+                (image_data, sha256)
+            })
+            .await?;
+        verify_checksum("checksum", request.checksum.as_deref(), &original_sha256)?;
+
+        #[cfg(feature = "database")]
+        let reused = self.reuse(&original_sha256, options, &image_data).await;
+        #[cfg(not(feature = "database"))]
+        let reused: Option<(Uuid, ProcessedImage)> = None;
+        let (stored_id, processed) = match reused {
+            Some((id, processed)) => (Some(id), processed),
+            // Decoding and encoding are CPU-bound, so they run on the compute pool. The
+            // closure re-enters this span so stage spans stay attached to the request.
+            None => (
+                None,
+                self.compute
+                    .run(move || span.in_scope(|| Self::process(&image_data, options)))
+                    .await??,
+            ),
+        };
+        let deduplicated = stored_id.is_some();
+        // A reused image answers with the stored record's ID and is not stored again. An
+        // output missing its thumbnail is not kept; the next request tries again.
+        #[cfg(feature = "database")]
+        let file_id = match stored_id {
+            Some(id) => id,
+            None if processed.thumbnail_failed => Uuid::now_v7(),
+            None => self
+                .keep(&processed, original_size, &original_sha256, options)
+                .await
+                .unwrap_or_else(Uuid::now_v7),
+        };
+        #[cfg(not(feature = "database"))]
+        let file_id = stored_id.unwrap_or_else(Uuid::now_v7);
 
-        info!("Image compressed, new size: {} bytes", compressed_size);
+        let compressed_size = processed.compressed_data.len() as u64;
 
         // Calculate compression ratio
         let compression_ratio = compressed_size as f64 / original_size as f64;
 
-        // Encode to base64
-        let base64_data = base64::prelude::BASE64_STANDARD.encode(&compressed_data);
-
-        // Generate thumbnail if requested
-        let (thumbnail_data, thumbnail_size) = if request.generate_thumbnail.unwrap_or(true) {
-            let thumbnail_size = request.thumbnail_size.unwrap_or(150);
-            match self.generate_thumbnail(&resized_img, thumbnail_size, quality) {
-                Ok((thumb_data, thumb_size)) => (Some(base64::prelude::BASE64_STANDARD.encode(&thumb_data)), Some(thumb_size)),
-                Err(e) => {
-                    warn!("Failed to generate thumbnail: {:?}", e);
-                    (None, None)
-                }
-            }
-        } else {
-            (None, None)
+        let timings = StageTimings {
+            fetch: fetch_duration,
+            ..processed.timings
         };
+        self.record_metrics(&processed.content_type, original_size, compressed_size, &timings, deduplicated);
+        if processed.thumbnail_failed {
+            self.metrics.record_thumbnail_failure();
+        }
+
+        // Encode to base64
+        let base64_data = base64::prelude::BASE64_STANDARD.encode(&processed.compressed_data);
+        let thumbnail_size = processed.thumbnail.as_ref().map(|thumb| thumb.len() as u64);
+        let thumbnail_data = processed
+            .thumbnail
+            .map(|thumb| base64::prelude::BASE64_STANDARD.encode(&thumb));
+        let content_type = processed.content_type;
 
-        let processing_duration = start_time.elapsed().as_millis() as u64;
+        let total = start_time.elapsed();
+        let processing_duration = total.as_millis() as u64;
 
         let response = CompressImageResponse {
-            file_id: Uuid::now_v7().to_string(),
+            file_id: file_id.to_string(),
             filename: request.filename,
             original_size,
             compressed_size,
             compression_ratio,
+            original_sha256,
+            compressed_sha256: processed.compressed_sha256,
+            deduplicated,
             compressed_data: base64_data,
             thumbnail_data,
             thumbnail_size,
             content_type,
             processed_at: chrono::Utc::now(),
             processing_duration_ms: processing_duration,
+            timings: timings.to_response(total),
         };
 
         info!(
-            "Image compression completed. Compression ratio: {:.2}",
-            compression_ratio
+            original_size,
+            compressed_size,
+            compression_ratio,
+            deduplicated,
+            duration_ms = processing_duration,
+            "Image compression completed"
         );
 
         Ok(response)
     }
 
+    fn record_metrics(
+        &self,
+        content_type: &str,
+        original_size: u64,
+        compressed_size: u64,
+        timings: &StageTimings,
+        deduplicated: bool,
+    ) {
+        let input_format = content_type.trim_start_matches("image/");
+        let output_format = OUTPUT_CONTENT_TYPE.trim_start_matches("image/");
+        self.metrics
+            .record_compression(input_format, output_format, original_size, compressed_size);
+
+        self.metrics.record_stage(Stage::Fetch, timings.fetch);
+        if deduplicated {
+            // Nothing was decoded or encoded, so there are no other stages to time
+            self.metrics.record_dedupe_hit();
+            return;
+        }
+        self.metrics.record_stage(Stage::Decode, timings.decode);
+        self.metrics.record_stage(Stage::Resize, timings.resize);
+        self.metrics.record_stage(Stage::Encode, timings.encode);
+        self.metrics.record_stage(Stage::Thumbnail, timings.thumbnail);
+    }
+
+    /// An earlier compression of the same source with the same options and its stored
+    /// ID, if the store has one. Lookup failures are logged and the image is processed
+    /// instead.
+    #[cfg(feature = "database")]
+    async fn reuse(
+        &self,
+        original_sha256: &str,
+        options: ProcessOptions,
+        image_data: &[u8],
+    ) -> Option<(Uuid, ProcessedImage)> {
+        let store = self.store.as_ref()?;
+        let stored = match store.find_derivative(original_sha256, &options.key()).await {
+            Ok(stored) => stored?,
+            Err(e) => {
+                warn!("Failed to look up an earlier compression: {}", e);
+                return None;
+            }
+        };
+        debug!(id = %stored.id, "Reusing an earlier compression");
+        let processed = ProcessedImage {
+            content_type: Self::detect_content_type(image_data),
+            compressed_sha256: stored.compressed_sha256.unwrap_or_else(|| sha256_hex(&stored.data)),
+            compressed_data: stored.data,
+            thumbnail: stored.thumbnail,
+            thumbnail_failed: false,
+            timings: StageTimings::default(),
+        };
+        Some((stored.id, processed))
+    }
+
+    /// Stores a freshly compressed image for reuse and returns its ID. The store shares
+    /// the bytes with every image that has the same ones. Failures are logged; the
+    /// response goes out regardless.
+    #[cfg(feature = "database")]
+    async fn keep(
+        &self,
+        processed: &ProcessedImage,
+        original_size: u64,
+        original_sha256: &str,
+        options: ProcessOptions,
+    ) -> Option<Uuid> {
+        let store = self.store.as_ref()?;
+        let image = NewStoredImage {
+            format: OUTPUT_CONTENT_TYPE.trim_start_matches("image/").to_string(),
+            data: processed.compressed_data.clone(),
+            original_size,
+            original_sha256: Some(original_sha256.to_string()),
+            thumbnail: processed.thumbnail.clone(),
+            options: Some(options.key()),
+        };
+        match store.save_image(image).await {
+            Ok(stored) => Some(stored.id),
+            Err(e) => {
+                warn!("Failed to store the compressed image: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Decodes, resizes and re-encodes an image, plus the optional thumbnail
+    fn process(image_data: &[u8], options: ProcessOptions) -> Result<ProcessedImage, ImageProcessingError> {
+        let mut timings = StageTimings::default();
+
+        // Detect content type
+        let content_type = Self::detect_content_type(image_data);
+
+        // Decode the image
+        let stage_start = Instant::now();
+        let img = {
+            let span = info_span!(
+                "decode",
+                format = %content_type,
+                bytes = image_data.len(),
+                width = field::Empty,
+                height = field::Empty
+            );
+            let img = span.in_scope(|| image::load_from_memory(image_data))?;
+            span.record("width", img.width()).record("height", img.height());
+            img
+        };
+        timings.decode = stage_start.elapsed();
+
+        // Resize the image if max dimensions specified
+        let stage_start = Instant::now();
+        let resized_img = {
+            let span = info_span!(
+                "resize",
+                from_width = img.width(),
+                from_height = img.height(),
+                width = field::Empty,
+                height = field::Empty
+            );
+            let resized_img = span.in_scope(|| match options.max_dimensions {
+                Some((max_width, max_height)) => Self::resize_image_to_fit(img, max_width, max_height),
+                None => img,
+            });
+            span.record("width", resized_img.width())
+                .record("height", resized_img.height());
+            resized_img
+        };
+        timings.resize = stage_start.elapsed();
+
+        // Compress the image
+        let stage_start = Instant::now();
+        let compressed_data = {
+            let span = info_span!("encode", quality = options.quality, bytes = field::Empty);
+            let compressed_data =
+                span.in_scope(|| Self::compress_image_data(&resized_img, &content_type, options.quality))?;
+            span.record("bytes", compressed_data.len());
+            compressed_data
+        };
+        timings.encode = stage_start.elapsed();
+
+        // Generate thumbnail if requested
+        let stage_start = Instant::now();
+        let mut thumbnail_failed = false;
+        let thumbnail = options.thumbnail_size.and_then(|size| {
+            let span = info_span!("thumbnail", size, width = field::Empty, height = field::Empty, bytes = field::Empty);
+            let _entered = span.enter();
+            match Self::generate_thumbnail(&resized_img, size, options.quality) {
+                Ok(thumb_data) => Some(thumb_data),
+                Err(e) => {
+                    warn!(error = ?e, "Failed to generate thumbnail");
+                    thumbnail_failed = true;
+                    None
+                }
+            }
+        });
+        timings.thumbnail = stage_start.elapsed();
+
+        Ok(ProcessedImage {
+            content_type,
+            compressed_sha256: sha256_hex(&compressed_data),
+            compressed_data,
+            thumbnail,
+            thumbnail_failed,
+            timings,
+        })
+    }
+
     async fn download_image(&self, url: &str) -> Result<Vec<u8>, ImageProcessingError> {
-        let response = self.client.get(url).send().await?;
+        let url = reqwest::Url::parse(url)
+            .map_err(|e| ImageProcessingError::InvalidUrl(e.to_string()))?;
+        // Continue the request's trace at the image host when OTLP export is on
+        let mut headers = reqwest::header::HeaderMap::new();
+        telemetry::inject_context(&mut headers);
+
+        let mut response = self
+            .client
+            .get(url)
+            .headers(headers)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // Reject early when the server announces an oversized body
+        if let Some(length) = response.content_length() {
+            self.check_size(length)?;
+        }
 
-        if !response.status().is_success() {
-            return Err(ImageProcessingError::DownloadError(reqwest::Error::from(
-                response.error_for_status().unwrap_err(),
-            )));
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            bytes.extend_from_slice(&chunk);
+            self.check_size(bytes.len() as u64)?;
         }
 
-        let bytes = response.bytes().await?;
-        Ok(bytes.to_vec())
+        Ok(bytes)
+    }
+
+    fn check_size(&self, size: u64) -> Result<(), ImageProcessingError> {
+        if size > self.max_image_size {
+            return Err(ImageProcessingError::ImageTooLarge(size, self.max_image_size));
+        }
+        Ok(())
     }
 
     fn decode_base64_image(&self, base64_data: &str) -> Result<Vec<u8>, ImageProcessingError> {
@@ -169,12 +527,15 @@ impl ImageCompressionService {
             base64_data
         };
 
+        // Base64 inflates by 4/3, so this bounds the decoded size before allocating
+        self.check_size(data_part.len() as u64 / 4 * 3)?;
+
         base64::prelude::BASE64_STANDARD.decode(data_part).map_err(|_| {
             ImageProcessingError::InvalidInput("Invalid base64 image data".to_string())
         })
     }
 
-    fn resize_image_to_fit(&self, img: DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
+    fn resize_image_to_fit(img: DynamicImage, max_width: u32, max_height: u32) -> DynamicImage {
         let (width, height) = (img.width(), img.height());
         
         // Calculate scaling factor to fit within max dimensions
@@ -186,10 +547,7 @@ impl ImageCompressionService {
             let new_width = (width as f32 * scale) as u32;
             let new_height = (height as f32 * scale) as u32;
             
-            info!(
-                "Resizing image from {}x{} to {}x{} (scale: {:.2})",
-                width, height, new_width, new_height, scale
-            );
+            debug!(scale, "Resizing image");
             
             img.resize(new_width, new_height, image::imageops::FilterType::Lanczos3)
         } else {
@@ -197,7 +555,7 @@ impl ImageCompressionService {
         }
     }
 
-    fn generate_thumbnail(&self, img: &DynamicImage, size: u32, quality: u8) -> Result<(Vec<u8>, u64), ImageProcessingError> {
+    fn generate_thumbnail(img: &DynamicImage, size: u32, quality: u8) -> Result<Vec<u8>, ImageProcessingError> {
         // Create thumbnail maintaining aspect ratio
         let thumbnail = img.resize(size, size, image::imageops::FilterType::Lanczos3);
         
@@ -208,15 +566,16 @@ impl ImageCompressionService {
         let mut encoder = JpegEncoder::new_with_quality(&mut buffer, thumb_quality);
         encoder.encode_image(&thumbnail)?;
         
-        let thumb_size = buffer.len() as u64;
-        info!("Generated thumbnail: {}x{}, size: {} bytes", thumbnail.width(), thumbnail.height(), thumb_size);
+        Span::current()
+            .record("width", thumbnail.width())
+            .record("height", thumbnail.height())
+            .record("bytes", buffer.len());
         
-        Ok((buffer, thumb_size))
+        Ok(buffer)
     }
 
     fn compress_image_data(
-        &self,
-        img: DynamicImage,
+        img: &DynamicImage,
         content_type: &str,
         quality: u8,
     ) -> Result<Vec<u8>, ImageProcessingError> {
@@ -226,14 +585,13 @@ impl ImageCompressionService {
             "image/jpeg" => {
                 // Use more aggressive quality for JPEG compression
                 let effective_quality = std::cmp::max(30, quality.saturating_sub(15));
-                info!("Compressing JPEG with quality {} (reduced from {})", effective_quality, quality);
+                debug!(effective_quality, "Compressing JPEG");
                 let mut encoder = JpegEncoder::new_with_quality(&mut buffer, effective_quality);
-                encoder.encode_image(&img)?;
+                encoder.encode_image(img)?;
             }
             "image/png" => {
                 // Always convert PNG to JPEG for better compression
                 // PNG is typically much larger than JPEG for photographic content
-                info!("Converting PNG to JPEG for better compression with quality {}", quality);
                 
                 // Use very aggressive quality setting for PNG conversion to ensure significant compression
                 // For a 50% resize, we need much lower quality to achieve actual compression
@@ -244,7 +602,7 @@ impl ImageCompressionService {
                 } else {
                     10  // Maximum compression for low quality requests
                 };
-                info!("Using very aggressive quality for PNG conversion: {}", effective_quality);
+                debug!(effective_quality, "Converting PNG to JPEG");
                 
                 let rgb_img = DynamicImage::ImageRgb8(img.to_rgb8());
                 let mut encoder = JpegEncoder::new_with_quality(&mut buffer, effective_quality);
@@ -270,7 +628,7 @@ impl ImageCompressionService {
         Ok(buffer)
     }
 
-    fn detect_content_type(&self, data: &[u8]) -> String {
+    fn detect_content_type(data: &[u8]) -> String {
         // Simple magic number detection
         if data.len() >= 3 {
             match &data[0..3] {
@@ -289,6 +647,26 @@ impl ImageCompressionService {
     }
 }
 
+/// Short label describing why a download failed, used as a metrics dimension
+fn download_error_cause(error: &ImageProcessingError) -> &'static str {
+    match error {
+        ImageProcessingError::ImageTooLarge(..) => "too_large",
+        ImageProcessingError::InvalidUrl(_) => "invalid_url",
+        ImageProcessingError::DownloadError(e) if e.is_timeout() => "timeout",
+        ImageProcessingError::DownloadError(e) if e.is_connect() => "connect",
+        ImageProcessingError::DownloadError(e) if e.is_redirect() => "redirect",
+        ImageProcessingError::DownloadError(e) if e.is_builder() => "invalid_url",
+        ImageProcessingError::DownloadError(e) => match e.status() {
+            Some(status) if status.is_client_error() => "status_4xx",
+            Some(status) if status.is_server_error() => "status_5xx",
+            Some(_) => "status_other",
+            None if e.is_body() || e.is_decode() => "body",
+            None => "other",
+        },
+        _ => "other",
+    }
+}
+
 impl Default for ImageCompressionService {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,101 @@
+//! Checks that stored items and images can be read back as recorded: sizes, the
+//! SHA-256 of the stored and the uncompressed payload, and the dictionaries items need
+
+use thiserror::Error;
+
+use crate::core::models::{CompressionAlgorithm, StoredImage, StoredItem};
+use crate::services::dictionary::{Dictionaries, decompress_item};
+use crate::utils::checksum::sha256_hex;
+
+/// Why a stored object cannot be read back as recorded
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Defect {
+    /// Something the object needs is gone: its stored data, or its dictionary
+    #[error("{0}")]
+    Missing(String),
+
+    /// The object is there but does not match what was recorded about it
+    #[error("{0}")]
+    Corrupted(String),
+}
+
+impl Defect {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Defect::Missing(_) => "missing",
+            Defect::Corrupted(_) => "corrupted",
+        }
+    }
+}
+
+/// The algorithm and original data of a stored item, or why it cannot be read back;
+/// CPU-bound, so run it off the async runtime
+pub fn check_item(item: &StoredItem, dictionaries: &Dictionaries) -> Result<(CompressionAlgorithm, Vec<u8>), Defect> {
+    let algorithm: CompressionAlgorithm = item.algorithm.parse().map_err(Defect::Corrupted)?;
+    check_stored(&item.data, item.compressed_size, item.compressed_sha256.as_deref())?;
+    if let Some(version) = item.dictionary_version
+        && !dictionaries.contains_key(&version)
+    {
+        return Err(Defect::Missing(format!("dictionary version {version} does not exist")));
+    }
+    let data = decompress_item(algorithm, item, dictionaries)
+        .map_err(|e| Defect::Corrupted(format!("does not decompress: {e}")))?;
+    if data.len() as i64 != item.original_size {
+        return Err(Defect::Corrupted(format!(
+            "decompresses to {} bytes, not the recorded {}",
+            data.len(),
+            item.original_size
+        )));
+    }
+    if let Some(expected) = &item.original_sha256 {
+        let actual = sha256_hex(&data);
+        if actual != *expected {
+            return Err(Defect::Corrupted(format!(
+                "decompressed data hashes to {actual}, not the recorded {expected}"
+            )));
+        }
+    }
+    Ok((algorithm, data))
+}
+
+/// Why a stored image or its thumbnail does not match its recorded size and checksum,
+/// if it does not; CPU-bound, so run it off the async runtime
+pub fn check_image(image: &StoredImage) -> Result<(), Defect> {
+    check_stored(&image.data, image.compressed_size, image.compressed_sha256.as_deref())?;
+    if let Some(expected) = &image.thumbnail_sha256 {
+        let Some(thumbnail) = &image.thumbnail else {
+            return Err(Defect::Missing("thumbnail is missing".to_string()));
+        };
+        let actual = sha256_hex(thumbnail);
+        if actual != *expected {
+            return Err(Defect::Corrupted(format!(
+                "thumbnail hashes to {actual}, not the recorded {expected}"
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn check_stored(data: &[u8], compressed_size: i64, compressed_sha256: Option<&str>) -> Result<(), Defect> {
+    if data.is_empty() && compressed_size > 0 {
+        return Err(Defect::Missing(format!(
+            "stored data is missing; {compressed_size} bytes were recorded"
+        )));
+    }
+    if compressed_size != data.len() as i64 {
+        return Err(Defect::Corrupted(format!(
+            "compressed_size is {} but {} bytes are stored",
+            compressed_size,
+            data.len()
+        )));
+    }
+    if let Some(expected) = compressed_sha256 {
+        let actual = sha256_hex(data);
+        if actual != expected {
+            return Err(Defect::Corrupted(format!(
+                "stored data hashes to {actual}, not the recorded {expected}"
+            )));
+        }
+    }
+    Ok(())
+}
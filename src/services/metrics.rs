@@ -0,0 +1,270 @@
+//! Prometheus metrics for HTTP traffic and the image compression pipeline, optionally
+//! mirrored to OpenTelemetry instruments for OTLP export
+
+use std::time::Duration;
+
+use opentelemetry::{
+    KeyValue, global,
+    metrics::{Counter, Histogram},
+};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+/// Stages of the compression pipeline that are timed individually
+#[derive(Debug, Clone, Copy)]
+pub enum Stage {
+    Fetch,
+    Decode,
+    Resize,
+    Encode,
+    Thumbnail,
+}
+
+impl Stage {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::Decode => "decode",
+            Stage::Resize => "resize",
+            Stage::Encode => "encode",
+            Stage::Thumbnail => "thumbnail",
+        }
+    }
+}
+
+/// All metrics exported on `GET /metrics`, registered in a private registry
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    bytes_in: IntCounterVec,
+    bytes_out: IntCounterVec,
+    compression_ratio: HistogramVec,
+    stage_duration: HistogramVec,
+    thumbnail_failures: IntCounter,
+    dedupe_hits: IntCounter,
+    download_errors: IntCounterVec,
+    otel: Option<OtelInstruments>,
+}
+
+/// OpenTelemetry counterparts of the Prometheus metrics, using OTel naming
+#[derive(Debug, Clone)]
+struct OtelInstruments {
+    http_duration: Histogram<f64>,
+    bytes_in: Counter<u64>,
+    bytes_out: Counter<u64>,
+    compression_ratio: Histogram<f64>,
+    stage_duration: Histogram<f64>,
+    thumbnail_failures: Counter<u64>,
+    dedupe_hits: Counter<u64>,
+    download_errors: Counter<u64>,
+}
+
+impl OtelInstruments {
+    fn new() -> Self {
+        let meter = global::meter(env!("CARGO_PKG_NAME"));
+        Self {
+            http_duration: meter
+                .f64_histogram("http.server.request.duration")
+                .with_unit("s")
+                .with_description("HTTP request latency")
+                .build(),
+            bytes_in: meter
+                .u64_counter("compress.input.bytes")
+                .with_unit("By")
+                .with_description("Source image bytes received for compression")
+                .build(),
+            bytes_out: meter
+                .u64_counter("compress.output.bytes")
+                .with_unit("By")
+                .with_description("Compressed image bytes produced")
+                .build(),
+            compression_ratio: meter
+                .f64_histogram("compress.ratio")
+                .with_description("Compressed size divided by original size")
+                .build(),
+            stage_duration: meter
+                .f64_histogram("compress.stage.duration")
+                .with_unit("s")
+                .with_description("Time spent in each pipeline stage")
+                .build(),
+            thumbnail_failures: meter
+                .u64_counter("compress.thumbnail.failures")
+                .with_description("Thumbnails that failed to generate")
+                .build(),
+            dedupe_hits: meter
+                .u64_counter("compress.dedupe.hits")
+                .with_description("Compressions answered with an image stored earlier")
+                .build(),
+            download_errors: meter
+                .u64_counter("image.download.errors")
+                .with_description("Failed image downloads by cause")
+                .build(),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("rust_compress_api".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route and status")
+                .buckets(vec![0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0]),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let bytes_in = IntCounterVec::new(
+            Opts::new("compress_input_bytes_total", "Source image bytes received for compression"),
+            &["input_format"],
+        )
+        .unwrap();
+        let bytes_out = IntCounterVec::new(
+            Opts::new("compress_output_bytes_total", "Compressed image bytes produced"),
+            &["output_format"],
+        )
+        .unwrap();
+        let compression_ratio = HistogramVec::new(
+            HistogramOpts::new("compression_ratio", "Compressed size divided by original size")
+                .buckets(vec![0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0, 1.25, 1.5, 2.0]),
+            &["input_format", "output_format"],
+        )
+        .unwrap();
+        let stage_duration = HistogramVec::new(
+            HistogramOpts::new("compress_stage_duration_seconds", "Time spent in each pipeline stage")
+                .buckets(vec![0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0]),
+            &["stage"],
+        )
+        .unwrap();
+        let thumbnail_failures =
+            IntCounter::new("thumbnail_failures_total", "Thumbnails that failed to generate").unwrap();
+        let dedupe_hits =
+            IntCounter::new("dedupe_hits_total", "Compressions answered with an image stored earlier").unwrap();
+        let download_errors = IntCounterVec::new(
+            Opts::new("image_download_errors_total", "Failed image downloads by cause"),
+            &["cause"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry.register(Box::new(http_duration.clone())).unwrap();
+        registry.register(Box::new(bytes_in.clone())).unwrap();
+        registry.register(Box::new(bytes_out.clone())).unwrap();
+        registry.register(Box::new(compression_ratio.clone())).unwrap();
+        registry.register(Box::new(stage_duration.clone())).unwrap();
+        registry.register(Box::new(thumbnail_failures.clone())).unwrap();
+        registry.register(Box::new(dedupe_hits.clone())).unwrap();
+        registry.register(Box::new(download_errors.clone())).unwrap();
+
+        Self {
+            registry,
+            http_requests,
+            http_duration,
+            bytes_in,
+            bytes_out,
+            compression_ratio,
+            stage_duration,
+            thumbnail_failures,
+            dedupe_hits,
+            download_errors,
+            otel: None,
+        }
+    }
+
+    /// Also records every metric through the global OpenTelemetry meter provider
+    pub fn with_otel(mut self) -> Self {
+        self.otel = Some(OtelInstruments::new());
+        self
+    }
+
+    pub fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let status_label = status.to_string();
+        let labels = [method, route, status_label.as_str()];
+        self.http_requests.with_label_values(&labels).inc();
+        self.http_duration
+            .with_label_values(&labels)
+            .observe(elapsed.as_secs_f64());
+        if let Some(otel) = &self.otel {
+            let attributes = [
+                KeyValue::new("http.request.method", method.to_string()),
+                KeyValue::new("http.route", route.to_string()),
+                KeyValue::new("http.response.status_code", i64::from(status)),
+            ];
+            otel.http_duration.record(elapsed.as_secs_f64(), &attributes);
+        }
+    }
+
+    pub fn record_compression(&self, input_format: &str, output_format: &str, bytes_in: u64, bytes_out: u64) {
+        self.bytes_in.with_label_values(&[input_format]).inc_by(bytes_in);
+        self.bytes_out.with_label_values(&[output_format]).inc_by(bytes_out);
+        if bytes_in > 0 {
+            self.compression_ratio
+                .with_label_values(&[input_format, output_format])
+                .observe(bytes_out as f64 / bytes_in as f64);
+        }
+        if let Some(otel) = &self.otel {
+            let input = KeyValue::new("input_format", input_format.to_string());
+            let output = KeyValue::new("output_format", output_format.to_string());
+            otel.bytes_in.add(bytes_in, std::slice::from_ref(&input));
+            otel.bytes_out.add(bytes_out, std::slice::from_ref(&output));
+            if bytes_in > 0 {
+                otel.compression_ratio
+                    .record(bytes_out as f64 / bytes_in as f64, &[input, output]);
+            }
+        }
+    }
+
+    pub fn record_stage(&self, stage: Stage, elapsed: Duration) {
+        self.stage_duration
+            .with_label_values(&[stage.as_str()])
+            .observe(elapsed.as_secs_f64());
+        if let Some(otel) = &self.otel {
+            otel.stage_duration
+                .record(elapsed.as_secs_f64(), &[KeyValue::new("stage", stage.as_str())]);
+        }
+    }
+
+    pub fn record_thumbnail_failure(&self) {
+        self.thumbnail_failures.inc();
+        if let Some(otel) = &self.otel {
+            otel.thumbnail_failures.add(1, &[]);
+        }
+    }
+
+    pub fn record_dedupe_hit(&self) {
+        self.dedupe_hits.inc();
+        if let Some(otel) = &self.otel {
+            otel.dedupe_hits.add(1, &[]);
+        }
+    }
+
+    pub fn record_download_error(&self, cause: &str) {
+        self.download_errors.with_label_values(&[cause]).inc();
+        if let Some(otel) = &self.otel {
+            otel.download_errors.add(1, &[KeyValue::new("cause", cause.to_string())]);
+        }
+    }
+
+    /// Renders every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding to a Vec cannot fail");
+        String::from_utf8(buffer).expect("Prometheus text format is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,27 @@
-// pub mod admin;
+#[cfg(feature = "database")]
+pub mod admin;
+pub mod auth;
+pub mod codec;
+pub mod compute;
+pub mod data;
+#[cfg(feature = "database")]
+pub mod dictionary;
+pub mod health;
 pub mod image;
+#[cfg(feature = "database")]
+pub mod integrity;
+pub mod metrics;
+pub mod rate_limit;
+pub mod stats;
+#[cfg(feature = "database")]
+pub mod transfer;
 
-// pub use admin::*;
+pub use auth::{ApiKeyIdentity, AuthError, AuthService};
+pub use codec::CodecError;
+pub use compute::{ComputeError, ComputePool};
+pub use data::{DataCompressionService, DataError};
+pub use health::HealthService;
 pub use image::*;
+pub use metrics::Metrics;
+pub use rate_limit::RateLimiter;
+pub use stats::StatsService;
@@ -0,0 +1,770 @@
+//! Token-bucket rate limiting and daily/monthly quotas
+//!
+//! Callers are identified by API key (`key:<id>`) or, without one, by client IP
+//! (`ip:<address>`). Each has a token bucket for request rate plus request and
+//! processed-byte counters per UTC day and calendar month. Usage is always tracked;
+//! limits are only enforced when `rate_limit.enabled` is set.
+//!
+//! State lives in memory by default. With the `postgres` backend it is kept in the
+//! `rate_limit_buckets` and `usage_counters` tables so several instances share it.
+//! Either way, counters from past periods are dropped once a day.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use thiserror::Error;
+
+use crate::core::config::{LimitPolicy, RateLimitConfig};
+use crate::core::models::{QuotaCounter, QuotaPeriodUsage, RateUsage, UsageResponse};
+use crate::services::ApiKeyIdentity;
+
+#[cfg(feature = "database")]
+use crate::core::database::DbPool;
+
+#[derive(Error, Debug)]
+pub enum RateLimitError {
+    #[cfg(feature = "database")]
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// The caller limits apply to, with its effective policy
+#[derive(Debug, Clone)]
+pub struct Subject {
+    pub id: String,
+    pub policy: LimitPolicy,
+}
+
+/// Which limit rejected a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Rate,
+    DailyRequests,
+    MonthlyRequests,
+    DailyBytes,
+    MonthlyBytes,
+}
+
+impl LimitKind {
+    pub fn description(self) -> &'static str {
+        match self {
+            LimitKind::Rate => "Rate limit exceeded",
+            LimitKind::DailyRequests => "Daily request quota exceeded",
+            LimitKind::MonthlyRequests => "Monthly request quota exceeded",
+            LimitKind::DailyBytes => "Daily processed-bytes quota exceeded",
+            LimitKind::MonthlyBytes => "Monthly processed-bytes quota exceeded",
+        }
+    }
+}
+
+/// Values for the `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset` headers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    /// Seconds until the limit is fully available again
+    pub reset_secs: u64,
+}
+
+#[derive(Debug)]
+pub enum Admission {
+    /// Carries the most constrained limit when limits are enforced
+    Allowed(Option<LimitStatus>),
+    Limited {
+        kind: LimitKind,
+        status: LimitStatus,
+        retry_after_secs: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Period {
+    Day,
+    Month,
+}
+
+#[cfg(feature = "database")]
+impl Period {
+    fn as_str(self) -> &'static str {
+        match self {
+            Period::Day => "day",
+            Period::Month => "month",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    requests: u64,
+    bytes: u64,
+}
+
+/// Quota periods containing `now`
+#[derive(Debug, Clone, Copy)]
+struct Windows {
+    now: DateTime<Utc>,
+    day_start: NaiveDate,
+    month_start: NaiveDate,
+    day_reset: DateTime<Utc>,
+    month_reset: DateTime<Utc>,
+}
+
+impl Windows {
+    fn at(now: DateTime<Utc>) -> Self {
+        let day_start = now.date_naive();
+        let month_start = day_start.with_day(1).expect("every month has a first day");
+        let midnight = |date: NaiveDate| date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+        Self {
+            now,
+            day_start,
+            month_start,
+            day_reset: midnight(day_start.succ_opt().unwrap_or(day_start)),
+            month_reset: midnight(month_start.checked_add_months(Months::new(1)).unwrap_or(month_start)),
+        }
+    }
+
+    fn secs_until(&self, at: DateTime<Utc>) -> u64 {
+        (at - self.now).num_seconds().max(0) as u64
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug, Default)]
+struct MemoryState {
+    buckets: HashMap<String, Bucket>,
+    usage: HashMap<(String, Period, NaiveDate), Counters>,
+    pruned_on: Option<NaiveDate>,
+}
+
+impl MemoryState {
+    fn counters(&self, subject: &str, windows: &Windows) -> (Counters, Counters) {
+        let get = |period, start| {
+            self.usage
+                .get(&(subject.to_string(), period, start))
+                .copied()
+                .unwrap_or_default()
+        };
+        (get(Period::Day, windows.day_start), get(Period::Month, windows.month_start))
+    }
+
+    fn add(&mut self, subject: &str, windows: &Windows, requests: u64, bytes: u64) {
+        for (period, start) in [(Period::Day, windows.day_start), (Period::Month, windows.month_start)] {
+            let counters = self.usage.entry((subject.to_string(), period, start)).or_default();
+            counters.requests += requests;
+            counters.bytes += bytes;
+        }
+    }
+
+    /// Whether the day's pruning is still to do; marks it done
+    fn prune_due(&mut self, windows: &Windows) -> bool {
+        let due = self.pruned_on != Some(windows.day_start);
+        self.pruned_on = Some(windows.day_start);
+        due
+    }
+
+    /// Once a day, drops counters from past periods and buckets that have refilled
+    fn prune(&mut self, windows: &Windows) {
+        if !self.prune_due(windows) {
+            return;
+        }
+        self.usage.retain(|(_, period, start), _| match period {
+            Period::Day => *start == windows.day_start,
+            Period::Month => *start == windows.month_start,
+        });
+        self.buckets
+            .retain(|_, bucket| bucket.updated.elapsed().as_secs() < 24 * 60 * 60);
+    }
+}
+
+#[derive(Debug)]
+pub struct RateLimiter {
+    enabled: bool,
+    trust_forwarded_for: bool,
+    anonymous: LimitPolicy,
+    authenticated: LimitPolicy,
+    keys: HashMap<String, LimitPolicy>,
+    memory: Mutex<MemoryState>,
+    #[cfg(feature = "database")]
+    pool: Option<DbPool>,
+}
+
+impl RateLimiter {
+    /// Limiter keeping its state in memory
+    pub fn from_config(config: &RateLimitConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            trust_forwarded_for: config.trust_forwarded_for,
+            anonymous: config.anonymous.clone(),
+            authenticated: config.authenticated.clone(),
+            keys: config
+                .keys
+                .iter()
+                .map(|key| (key.key_id.clone(), key.limits.clone()))
+                .collect(),
+            memory: Mutex::new(MemoryState::default()),
+            #[cfg(feature = "database")]
+            pool: None,
+        }
+    }
+
+    /// Keeps buckets and counters in PostgreSQL, shared by every instance
+    #[cfg(feature = "database")]
+    pub fn with_database(mut self, pool: DbPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Whether requests over their limits are rejected
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Resolves the caller to an API key or, without one, to its client IP
+    pub fn subject(&self, identity: Option<&ApiKeyIdentity>, headers: &HeaderMap, peer: Option<IpAddr>) -> Subject {
+        if let Some(identity) = identity {
+            return Subject {
+                id: format!("key:{}", identity.id),
+                policy: self.keys.get(&identity.id).unwrap_or(&self.authenticated).clone(),
+            };
+        }
+
+        let forwarded = self
+            .trust_forwarded_for
+            .then(|| {
+                headers
+                    .get("x-forwarded-for")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.split(',').next())
+                    .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+            })
+            .flatten();
+        let id = match forwarded.or(peer) {
+            Some(ip) => format!("ip:{ip}"),
+            None => "ip:unknown".to_string(),
+        };
+        Subject {
+            id,
+            policy: self.anonymous.clone(),
+        }
+    }
+
+    /// Admits or rejects one request, counting it against the caller's quotas when admitted
+    pub async fn check(&self, subject: &Subject) -> Result<Admission, RateLimitError> {
+        let windows = Windows::at(Utc::now());
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            return self.check_database(pool, subject, &windows).await;
+        }
+
+        let mut state = self.memory.lock().expect("rate limit lock poisoned");
+        state.prune(&windows);
+
+        let (day, month) = state.counters(&subject.id, &windows);
+        if self.enabled
+            && let Some(limited) = quota_exceeded(&subject.policy, &windows, day, month)
+        {
+            return Ok(limited);
+        }
+
+        let mut rate = None;
+        if self.enabled && subject.policy.requests_per_second > 0.0 {
+            let policy = &subject.policy;
+            let bucket = state.buckets.entry(subject.id.clone()).or_insert(Bucket {
+                tokens: policy.burst as f64,
+                updated: Instant::now(),
+            });
+            let tokens = refill(policy, bucket.tokens, bucket.updated.elapsed().as_secs_f64());
+            bucket.updated = Instant::now();
+            bucket.tokens = tokens;
+            if tokens < 1.0 {
+                return Ok(rate_limited(policy, tokens));
+            }
+            bucket.tokens -= 1.0;
+            rate = Some(rate_status(policy, bucket.tokens));
+        }
+
+        state.add(&subject.id, &windows, 1, 0);
+        Ok(self.admitted(&subject.policy, &windows, day, month, rate))
+    }
+
+    /// Counts processed input bytes against the caller's byte quotas
+    pub async fn record_bytes(&self, subject: &Subject, bytes: u64) -> Result<(), RateLimitError> {
+        let windows = Windows::at(Utc::now());
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            return Self::add_database(pool, subject, &windows, 0, bytes).await;
+        }
+
+        self.memory
+            .lock()
+            .expect("rate limit lock poisoned")
+            .add(&subject.id, &windows, 0, bytes);
+        Ok(())
+    }
+
+    /// Current usage and limits for the caller
+    pub async fn usage(&self, subject: &Subject) -> Result<UsageResponse, RateLimitError> {
+        let windows = Windows::at(Utc::now());
+        let policy = &subject.policy;
+
+        #[cfg(feature = "database")]
+        if let Some(pool) = &self.pool {
+            let (day, month) = Self::database_counters(pool, subject, &windows).await?;
+            let tokens = sqlx::query_scalar::<_, f64>(
+                r#"
+                SELECT LEAST($2, tokens + EXTRACT(EPOCH FROM NOW() - updated_at)::DOUBLE PRECISION * $3)
+                FROM rate_limit_buckets WHERE subject = $1
+                "#,
+            )
+            .bind(&subject.id)
+            .bind(policy.burst as f64)
+            .bind(policy.requests_per_second)
+            .fetch_optional(pool)
+            .await?;
+            return Ok(self.usage_report(subject, &windows, day, month, tokens));
+        }
+
+        let state = self.memory.lock().expect("rate limit lock poisoned");
+        let (day, month) = state.counters(&subject.id, &windows);
+        let tokens = state
+            .buckets
+            .get(&subject.id)
+            .map(|bucket| refill(policy, bucket.tokens, bucket.updated.elapsed().as_secs_f64()));
+        Ok(self.usage_report(subject, &windows, day, month, tokens))
+    }
+
+    /// Header values for an admitted request: whichever limit has the fewest requests left
+    fn admitted(
+        &self,
+        policy: &LimitPolicy,
+        windows: &Windows,
+        day: Counters,
+        month: Counters,
+        rate: Option<LimitStatus>,
+    ) -> Admission {
+        if !self.enabled {
+            return Admission::Allowed(None);
+        }
+
+        let quota = |limit: u64, used: u64, reset: DateTime<Utc>| {
+            (limit > 0).then(|| LimitStatus {
+                limit,
+                remaining: limit.saturating_sub(used + 1),
+                reset_secs: windows.secs_until(reset),
+            })
+        };
+        let status = [
+            rate,
+            quota(policy.daily_requests, day.requests, windows.day_reset),
+            quota(policy.monthly_requests, month.requests, windows.month_reset),
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|status| status.remaining);
+        Admission::Allowed(status)
+    }
+
+    fn usage_report(
+        &self,
+        subject: &Subject,
+        windows: &Windows,
+        day: Counters,
+        month: Counters,
+        tokens: Option<f64>,
+    ) -> UsageResponse {
+        let policy = &subject.policy;
+        let counter = |used: u64, limit: u64| QuotaCounter {
+            used,
+            limit: (limit > 0).then_some(limit),
+            remaining: (limit > 0).then(|| limit.saturating_sub(used)),
+        };
+        let limited_rate = policy.requests_per_second > 0.0;
+
+        UsageResponse {
+            subject: subject.id.clone(),
+            enforced: self.enabled,
+            rate: RateUsage {
+                requests_per_second: limited_rate.then_some(policy.requests_per_second),
+                burst: limited_rate.then_some(policy.burst),
+                remaining: limited_rate.then(|| tokens.unwrap_or(policy.burst as f64).floor() as u64),
+            },
+            daily: QuotaPeriodUsage {
+                period_start: windows.day_start,
+                resets_at: windows.day_reset,
+                requests: counter(day.requests, policy.daily_requests),
+                bytes: counter(day.bytes, policy.daily_bytes),
+            },
+            monthly: QuotaPeriodUsage {
+                period_start: windows.month_start,
+                resets_at: windows.month_reset,
+                requests: counter(month.requests, policy.monthly_requests),
+                bytes: counter(month.bytes, policy.monthly_bytes),
+            },
+        }
+    }
+
+    #[cfg(feature = "database")]
+    async fn check_database(
+        &self,
+        pool: &DbPool,
+        subject: &Subject,
+        windows: &Windows,
+    ) -> Result<Admission, RateLimitError> {
+        let due = self.memory.lock().expect("rate limit lock poisoned").prune_due(windows);
+        if due {
+            Self::prune_database(pool, windows).await?;
+        }
+
+        // Count the request and read the totals back in one statement. The counter rows
+        // stay locked until the transaction ends, so concurrent requests are serialized,
+        // and a rejected request is rolled back rather than counted.
+        let mut tx = pool.begin().await?;
+        let rows = sqlx::query_as::<_, (String, i64, i64)>(
+            r#"
+            INSERT INTO usage_counters (subject, period, period_start, requests, bytes)
+            VALUES ($1, $2, $3, 1, 0), ($1, $4, $5, 1, 0)
+            ON CONFLICT (subject, period, period_start) DO UPDATE SET
+                requests = usage_counters.requests + 1
+            RETURNING period, requests, bytes
+            "#,
+        )
+        .bind(&subject.id)
+        .bind(Period::Day.as_str())
+        .bind(windows.day_start)
+        .bind(Period::Month.as_str())
+        .bind(windows.month_start)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        // Usage before this request, as the in-memory backend sees it
+        let (mut day, mut month) = (Counters::default(), Counters::default());
+        for (period, requests, bytes) in rows {
+            let counters = Counters {
+                requests: (requests as u64).saturating_sub(1),
+                bytes: bytes as u64,
+            };
+            if period == Period::Day.as_str() {
+                day = counters;
+            } else {
+                month = counters;
+            }
+        }
+        if self.enabled
+            && let Some(limited) = quota_exceeded(&subject.policy, windows, day, month)
+        {
+            tx.rollback().await?;
+            return Ok(limited);
+        }
+
+        let mut rate = None;
+        if self.enabled && subject.policy.requests_per_second > 0.0 {
+            let policy = &subject.policy;
+            // Refill and lock the bucket row, then take a token if one is available
+            let tokens = sqlx::query_scalar::<_, f64>(
+                r#"
+                INSERT INTO rate_limit_buckets (subject, tokens, updated_at) VALUES ($1, $2, NOW())
+                ON CONFLICT (subject) DO UPDATE SET
+                    tokens = LEAST(
+                        $2,
+                        rate_limit_buckets.tokens
+                            + EXTRACT(EPOCH FROM NOW() - rate_limit_buckets.updated_at)::DOUBLE PRECISION * $3
+                    ),
+                    updated_at = NOW()
+                RETURNING tokens
+                "#,
+            )
+            .bind(&subject.id)
+            .bind(policy.burst as f64)
+            .bind(policy.requests_per_second)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            if tokens < 1.0 {
+                // The refill is recomputed from `updated_at`, so rolling it back loses nothing
+                tx.rollback().await?;
+                return Ok(rate_limited(policy, tokens));
+            }
+            sqlx::query("UPDATE rate_limit_buckets SET tokens = tokens - 1 WHERE subject = $1")
+                .bind(&subject.id)
+                .execute(&mut *tx)
+                .await?;
+            rate = Some(rate_status(policy, tokens - 1.0));
+        }
+
+        tx.commit().await?;
+        Ok(self.admitted(&subject.policy, windows, day, month, rate))
+    }
+
+    /// Drops counters from past periods and buckets idle for a day
+    #[cfg(feature = "database")]
+    async fn prune_database(pool: &DbPool, windows: &Windows) -> Result<(), RateLimitError> {
+        sqlx::query(
+            r#"
+            DELETE FROM usage_counters
+            WHERE (period = $1 AND period_start < $2) OR (period = $3 AND period_start < $4)
+            "#,
+        )
+        .bind(Period::Day.as_str())
+        .bind(windows.day_start)
+        .bind(Period::Month.as_str())
+        .bind(windows.month_start)
+        .execute(pool)
+        .await?;
+        sqlx::query("DELETE FROM rate_limit_buckets WHERE updated_at < NOW() - INTERVAL '1 day'")
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "database")]
+    async fn database_counters(
+        pool: &DbPool,
+        subject: &Subject,
+        windows: &Windows,
+    ) -> Result<(Counters, Counters), RateLimitError> {
+        let rows = sqlx::query_as::<_, (String, i64, i64)>(
+            r#"
+            SELECT period, requests, bytes FROM usage_counters
+            WHERE subject = $1
+              AND ((period = 'day' AND period_start = $2) OR (period = 'month' AND period_start = $3))
+            "#,
+        )
+        .bind(&subject.id)
+        .bind(windows.day_start)
+        .bind(windows.month_start)
+        .fetch_all(pool)
+        .await?;
+
+        let (mut day, mut month) = (Counters::default(), Counters::default());
+        for (period, requests, bytes) in rows {
+            let counters = Counters {
+                requests: requests as u64,
+                bytes: bytes as u64,
+            };
+            if period == Period::Day.as_str() {
+                day = counters;
+            } else {
+                month = counters;
+            }
+        }
+        Ok((day, month))
+    }
+
+    #[cfg(feature = "database")]
+    async fn add_database(
+        pool: &DbPool,
+        subject: &Subject,
+        windows: &Windows,
+        requests: u64,
+        bytes: u64,
+    ) -> Result<(), RateLimitError> {
+        sqlx::query(
+            r#"
+            INSERT INTO usage_counters (subject, period, period_start, requests, bytes)
+            VALUES ($1, $2, $3, $6, $7), ($1, $4, $5, $6, $7)
+            ON CONFLICT (subject, period, period_start) DO UPDATE SET
+                requests = usage_counters.requests + EXCLUDED.requests,
+                bytes = usage_counters.bytes + EXCLUDED.bytes
+            "#,
+        )
+        .bind(&subject.id)
+        .bind(Period::Day.as_str())
+        .bind(windows.day_start)
+        .bind(Period::Month.as_str())
+        .bind(windows.month_start)
+        .bind(requests as i64)
+        .bind(bytes as i64)
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Tokens in a bucket after `elapsed` seconds of refill, capped at the burst size
+fn refill(policy: &LimitPolicy, tokens: f64, elapsed: f64) -> f64 {
+    (tokens + elapsed * policy.requests_per_second).min(policy.burst as f64)
+}
+
+fn rate_status(policy: &LimitPolicy, tokens: f64) -> LimitStatus {
+    LimitStatus {
+        limit: policy.burst,
+        remaining: tokens.floor().max(0.0) as u64,
+        reset_secs: ((policy.burst as f64 - tokens) / policy.requests_per_second).ceil() as u64,
+    }
+}
+
+fn rate_limited(policy: &LimitPolicy, tokens: f64) -> Admission {
+    let retry_after_secs = ((1.0 - tokens) / policy.requests_per_second).ceil().max(1.0) as u64;
+    Admission::Limited {
+        kind: LimitKind::Rate,
+        status: LimitStatus {
+            remaining: 0,
+            ..rate_status(policy, tokens)
+        },
+        retry_after_secs,
+    }
+}
+
+/// The first quota the caller has used up, if any
+fn quota_exceeded(policy: &LimitPolicy, windows: &Windows, day: Counters, month: Counters) -> Option<Admission> {
+    let quotas = [
+        (LimitKind::DailyRequests, policy.daily_requests, day.requests, windows.day_reset),
+        (LimitKind::MonthlyRequests, policy.monthly_requests, month.requests, windows.month_reset),
+        (LimitKind::DailyBytes, policy.daily_bytes, day.bytes, windows.day_reset),
+        (LimitKind::MonthlyBytes, policy.monthly_bytes, month.bytes, windows.month_reset),
+    ];
+    quotas
+        .into_iter()
+        .find(|(_, limit, used, _)| *limit > 0 && used >= limit)
+        .map(|(kind, limit, _, reset)| {
+            let reset_secs = windows.secs_until(reset);
+            Admission::Limited {
+                kind,
+                status: LimitStatus {
+                    limit,
+                    remaining: 0,
+                    reset_secs,
+                },
+                retry_after_secs: reset_secs.max(1),
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn limiter(enabled: bool, policy: LimitPolicy) -> (RateLimiter, Subject) {
+        let limiter = RateLimiter::from_config(&RateLimitConfig {
+            enabled,
+            anonymous: policy,
+            ..RateLimitConfig::default()
+        });
+        let subject = limiter.subject(None, &HeaderMap::new(), Some("192.0.2.1".parse().unwrap()));
+        (limiter, subject)
+    }
+
+    fn is_limited(admission: &Admission, expected: LimitKind) -> bool {
+        matches!(admission, Admission::Limited { kind, .. } if *kind == expected)
+    }
+
+    #[test]
+    fn refill_is_capped_at_the_burst() {
+        let policy = LimitPolicy {
+            requests_per_second: 2.0,
+            burst: 5,
+            ..LimitPolicy::default()
+        };
+        assert_eq!(refill(&policy, 0.0, 1.0), 2.0);
+        assert_eq!(refill(&policy, 4.0, 10.0), 5.0);
+    }
+
+    #[tokio::test]
+    async fn token_bucket_allows_the_burst_then_limits() {
+        let (limiter, subject) = limiter(
+            true,
+            LimitPolicy {
+                requests_per_second: 0.001,
+                burst: 3,
+                ..LimitPolicy::default()
+            },
+        );
+
+        for remaining in [2, 1, 0] {
+            match limiter.check(&subject).await.unwrap() {
+                Admission::Allowed(Some(status)) => assert_eq!(status.remaining, remaining),
+                other => panic!("expected an admitted request, got {other:?}"),
+            }
+        }
+        let admission = limiter.check(&subject).await.unwrap();
+        assert!(is_limited(&admission, LimitKind::Rate));
+        if let Admission::Limited { retry_after_secs, .. } = admission {
+            assert!(retry_after_secs >= 1);
+        }
+
+        // Rejected requests are not counted against quotas
+        let usage = limiter.usage(&subject).await.unwrap();
+        assert_eq!(usage.daily.requests.used, 3);
+    }
+
+    #[tokio::test]
+    async fn request_and_byte_quotas_are_enforced() {
+        let (limiter, subject) = limiter(
+            true,
+            LimitPolicy {
+                daily_requests: 2,
+                monthly_bytes: 1000,
+                ..LimitPolicy::default()
+            },
+        );
+
+        assert!(matches!(limiter.check(&subject).await.unwrap(), Admission::Allowed(_)));
+        limiter.record_bytes(&subject, 1000).await.unwrap();
+        assert!(is_limited(&limiter.check(&subject).await.unwrap(), LimitKind::MonthlyBytes));
+
+        let (limiter, subject) = self::limiter(
+            true,
+            LimitPolicy {
+                daily_requests: 2,
+                ..LimitPolicy::default()
+            },
+        );
+        assert!(matches!(limiter.check(&subject).await.unwrap(), Admission::Allowed(_)));
+        assert!(matches!(limiter.check(&subject).await.unwrap(), Admission::Allowed(_)));
+        assert!(is_limited(&limiter.check(&subject).await.unwrap(), LimitKind::DailyRequests));
+    }
+
+    #[tokio::test]
+    async fn usage_is_tracked_when_limits_are_not_enforced() {
+        let (limiter, subject) = limiter(
+            false,
+            LimitPolicy {
+                daily_requests: 1,
+                ..LimitPolicy::default()
+            },
+        );
+        for _ in 0..3 {
+            assert!(matches!(limiter.check(&subject).await.unwrap(), Admission::Allowed(None)));
+        }
+        let usage = limiter.usage(&subject).await.unwrap();
+        assert_eq!(usage.daily.requests.used, 3);
+        assert!(!usage.enforced);
+    }
+
+    #[test]
+    fn windows_reset_at_the_next_day_and_month() {
+        let now = Utc.with_ymd_and_hms(2025, 1, 31, 23, 0, 0).unwrap();
+        let windows = Windows::at(now);
+        assert_eq!(windows.day_start, NaiveDate::from_ymd_opt(2025, 1, 31).unwrap());
+        assert_eq!(windows.month_start, NaiveDate::from_ymd_opt(2025, 1, 1).unwrap());
+        assert_eq!(windows.secs_until(windows.day_reset), 3600);
+        assert_eq!(windows.month_reset, Utc.with_ymd_and_hms(2025, 2, 1, 0, 0, 0).unwrap());
+    }
+
+    #[test]
+    fn old_periods_are_pruned_once_a_day() {
+        let mut state = MemoryState::default();
+        let yesterday = Windows::at(Utc.with_ymd_and_hms(2025, 1, 31, 12, 0, 0).unwrap());
+        let today = Windows::at(Utc.with_ymd_and_hms(2025, 2, 1, 12, 0, 0).unwrap());
+        state.add("ip:192.0.2.1", &yesterday, 1, 10);
+        state.add("ip:192.0.2.1", &today, 1, 10);
+
+        state.prune(&today);
+        assert_eq!(state.usage.len(), 2);
+        assert!(state.usage.keys().all(|(_, _, start)| *start >= today.month_start));
+        assert!(!state.prune_due(&today));
+    }
+}
@@ -0,0 +1,608 @@
+//! NDJSON export and import of items and stored images, for `GET /items/export`,
+//! `admin export` and `admin import`.
+//!
+//! Every line is one record tagged with `type`. Item records carry the item's
+//! metadata and its original data, so the importing deployment recompresses them with
+//! its own dictionaries; image records carry the stored image bytes. Exports can be
+//! wrapped in gzip or zstd, and imports detect the wrapping themselves. Imports are
+//! idempotent by ID, so an interrupted import can simply be run again.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::sync::{Arc, Mutex};
+
+use base64::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::core::database::{DbError, ImageStore, ItemRepository};
+use crate::core::models::{
+    CompressedItem, CompressionAlgorithm, ConflictPolicy, ExportCompression, ItemCursor, ItemPayload, ItemQuery,
+    SortOrder, StoredImage, StoredItem,
+};
+use crate::services::codec::{self, CodecError, Encoder};
+use crate::services::compute::{ComputeError, ComputePool};
+use crate::services::dictionary::{self, DictionaryStore};
+use crate::services::integrity;
+use crate::utils::checksum::{sha256_hex, verify_checksum};
+
+/// Content type of an unwrapped export
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Items or images read from storage per page
+const EXPORT_PAGE_SIZE: u32 = 100;
+
+/// Name hashed with an item's or image's ID to derive the ID it is renamed to, so a
+/// rerun import finds the copy it made before
+const RENAMED_ID_NAME: &[u8] = b"rust-compress-api:import:renamed";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Error, Debug)]
+pub enum TransferError {
+    #[error("Invalid record: {0}")]
+    Record(String),
+
+    #[error(transparent)]
+    Codec(#[from] CodecError),
+
+    #[error(transparent)]
+    Database(#[from] DbError),
+
+    #[error("Transfer failed: {0}")]
+    Compute(#[from] ComputeError),
+}
+
+/// An image line; `data` is the stored image in base64
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImageRecord {
+    pub id: Uuid,
+    pub format: String,
+    pub data: String,
+    pub original_size: i64,
+    pub compressed_size: i64,
+    pub original_sha256: Option<String>,
+    /// Hex SHA-256 of the decoded `data`; checked on import
+    pub compressed_sha256: Option<String>,
+    /// The thumbnail in base64, when the image has one
+    pub thumbnail: Option<String>,
+    /// Hex SHA-256 of the decoded `thumbnail`; checked on import
+    pub thumbnail_sha256: Option<String>,
+    /// The settings the image was compressed with
+    pub options: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An item line as read back. Only `name` and `data` are required, so lines made for
+/// `POST /items` import too, as new items.
+#[derive(Debug, Deserialize)]
+pub struct ItemRecord {
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// Original data in base64
+    pub data: String,
+    pub algorithm: Option<CompressionAlgorithm>,
+    pub level: Option<i32>,
+    /// Hex SHA-256 of the decoded `data`; checked on import
+    pub original_sha256: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ExportRecord {
+    Item(CompressedItem),
+    Image(ImageRecord),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum ImportRecord {
+    Item(ItemRecord),
+    Image(ImageRecord),
+}
+
+/// What to export
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Item filters; its order, cursor, page size and `trashed` are ignored
+    pub filter: ItemQuery,
+    /// Export items in the trash after the live ones
+    pub include_trash: bool,
+    /// Export stored images after the items
+    pub images: bool,
+    pub compression: Option<ExportCompression>,
+}
+
+/// An item or image left out of an export because it cannot be read back
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedRecord {
+    /// `item` or `image`
+    pub kind: &'static str,
+    pub id: Uuid,
+    pub problem: String,
+}
+
+/// Records written by an export so far
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExportSummary {
+    pub items: u64,
+    pub images: u64,
+    pub skipped: Vec<SkippedRecord>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Section {
+    Items { trashed: bool },
+    Images,
+}
+
+/// Buffer the export encoder writes to, drained after every page
+#[derive(Clone, Default)]
+struct OutputBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl OutputBuffer {
+    fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner()))
+    }
+}
+
+impl Write for OutputBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes an export a page at a time: items in creation order, then the trash, then
+/// images. Items that cannot be read back are left out and listed in the summary.
+pub struct Exporter {
+    items: Arc<dyn ItemRepository>,
+    images: Arc<dyn ImageStore>,
+    dictionaries: Arc<DictionaryStore>,
+    compute: Arc<ComputePool>,
+    sections: VecDeque<Section>,
+    query: ItemQuery,
+    image_after: Option<Uuid>,
+    /// `None` once the trailer has been written
+    encoder: Option<Encoder<OutputBuffer>>,
+    output: OutputBuffer,
+    summary: ExportSummary,
+}
+
+impl Exporter {
+    pub fn new(
+        items: Arc<dyn ItemRepository>,
+        images: Arc<dyn ImageStore>,
+        dictionaries: Arc<DictionaryStore>,
+        compute: Arc<ComputePool>,
+        options: ExportOptions,
+    ) -> Result<Self, TransferError> {
+        let mut sections = VecDeque::from([Section::Items { trashed: false }]);
+        if options.include_trash {
+            sections.push_back(Section::Items { trashed: true });
+        }
+        if options.images {
+            sections.push_back(Section::Images);
+        }
+        let query = ItemQuery {
+            sort: SortOrder::Asc,
+            after: None,
+            limit: EXPORT_PAGE_SIZE,
+            with_data: true,
+            ..options.filter
+        };
+        let output = OutputBuffer::default();
+        let (algorithm, level) = match options.compression {
+            Some(compression) => {
+                let algorithm = compression.algorithm();
+                (algorithm, codec::resolve_level(algorithm, None)?)
+            }
+            None => (CompressionAlgorithm::Identity, 0),
+        };
+        Ok(Self {
+            items,
+            images,
+            dictionaries,
+            compute,
+            sections,
+            query,
+            image_after: None,
+            encoder: Some(Encoder::new(algorithm, level, output.clone())?),
+            output,
+            summary: ExportSummary::default(),
+        })
+    }
+
+    pub fn summary(&self) -> &ExportSummary {
+        &self.summary
+    }
+
+    /// The next part of the export, or `None` once all of it has been returned
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, TransferError> {
+        loop {
+            let Some(mut encoder) = self.encoder.take() else {
+                return Ok(None);
+            };
+            let Some(&section) = self.sections.front() else {
+                self.compute.run(move || encoder.finish()).await??;
+                return Ok(Some(self.output.take()));
+            };
+
+            let (encoder, full_page) = match section {
+                Section::Items { trashed } => {
+                    self.query.trashed = trashed;
+                    let page = self.items.list_items(&self.query).await?;
+                    let full_page = page.len() == self.query.limit as usize;
+                    self.query.after = page.last().map(|item| ItemCursor {
+                        created_at: item.created_at.unwrap_or_default(),
+                        id: item.id,
+                    });
+                    let dictionaries = self.dictionaries.available_for_items(self.items.as_ref(), &page).await?;
+                    let (encoder, written, skipped) = self
+                        .compute
+                        .run(move || -> Result<_, TransferError> {
+                            let mut written = 0;
+                            let mut skipped = Vec::new();
+                            for item in page {
+                                match integrity::check_item(&item, &dictionaries) {
+                                    Ok((algorithm, data)) => {
+                                        let data = BASE64_STANDARD.encode(data);
+                                        let record = ExportRecord::Item(CompressedItem::from_stored(algorithm, item, data));
+                                        write_record(&mut encoder, &record)?;
+                                        written += 1;
+                                    }
+                                    Err(defect) => skipped.push(SkippedRecord {
+                                        kind: "item",
+                                        id: item.id,
+                                        problem: defect.to_string(),
+                                    }),
+                                }
+                            }
+                            Ok((encoder, written, skipped))
+                        })
+                        .await??;
+                    self.summary.items += written;
+                    self.summary.skipped.extend(skipped);
+                    (encoder, full_page)
+                }
+                Section::Images => {
+                    let page = self.images.list_images(self.image_after, EXPORT_PAGE_SIZE).await?;
+                    let full_page = page.len() == EXPORT_PAGE_SIZE as usize;
+                    self.image_after = page.last().map(|image| image.id);
+                    let (encoder, written, skipped) = self
+                        .compute
+                        .run(move || -> Result<_, TransferError> {
+                            let mut written = 0;
+                            let mut skipped = Vec::new();
+                            for image in page {
+                                match integrity::check_image(&image) {
+                                    Ok(()) => {
+                                        write_record(&mut encoder, &ExportRecord::Image(image_record(image)))?;
+                                        written += 1;
+                                    }
+                                    Err(defect) => skipped.push(SkippedRecord {
+                                        kind: "image",
+                                        id: image.id,
+                                        problem: defect.to_string(),
+                                    }),
+                                }
+                            }
+                            Ok((encoder, written, skipped))
+                        })
+                        .await??;
+                    self.summary.images += written;
+                    self.summary.skipped.extend(skipped);
+                    (encoder, full_page)
+                }
+            };
+            self.encoder = Some(encoder);
+            if !full_page {
+                self.sections.pop_front();
+                self.query.after = None;
+            }
+
+            // A compressed export may not have produced any output for a small page yet
+            let chunk = self.output.take();
+            if !chunk.is_empty() {
+                return Ok(Some(chunk));
+            }
+        }
+    }
+}
+
+fn write_record(encoder: &mut Encoder<OutputBuffer>, record: &ExportRecord) -> Result<(), TransferError> {
+    let line = serde_json::to_vec(record).map_err(|e| TransferError::Record(e.to_string()))?;
+    encoder.write_all(&line).map_err(CodecError::from)?;
+    encoder.write_all(b"\n").map_err(CodecError::from)?;
+    Ok(())
+}
+
+fn image_record(image: StoredImage) -> ImageRecord {
+    ImageRecord {
+        id: image.id,
+        format: image.format,
+        data: BASE64_STANDARD.encode(&image.data),
+        original_size: image.original_size,
+        compressed_size: image.compressed_size,
+        original_sha256: image.original_sha256,
+        compressed_sha256: image.compressed_sha256,
+        thumbnail: image.thumbnail.map(|thumbnail| BASE64_STANDARD.encode(thumbnail)),
+        thumbnail_sha256: image.thumbnail_sha256,
+        options: image.options,
+        created_at: image.created_at,
+    }
+}
+
+/// Reads an export, unwrapping gzip or zstd when its first bytes say it is wrapped
+pub fn open_export<'a, R: BufRead + 'a>(mut input: R) -> io::Result<Box<dyn BufRead + 'a>> {
+    let start = input.fill_buf()?;
+    if start.starts_with(&GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(input))))
+    } else if start.starts_with(&ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(input)?)))
+    } else {
+        Ok(Box::new(input))
+    }
+}
+
+/// What importing one record did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportOutcome {
+    /// Stored under its own ID, or a new one for items without an ID
+    Created,
+    /// This version is already stored, e.g. by an earlier run of the same import
+    Unchanged,
+    /// Another version is stored under its ID and was kept
+    Skipped,
+    /// Another version was stored under its ID and was replaced
+    Overwritten,
+    /// Another version is stored under its ID, so it was stored under a derived ID
+    Renamed,
+}
+
+/// Outcomes of the records imported so far, by kind of record
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ImportCounts {
+    pub created: u64,
+    pub unchanged: u64,
+    pub skipped: u64,
+    pub overwritten: u64,
+    pub renamed: u64,
+}
+
+impl ImportCounts {
+    fn add(&mut self, outcome: ImportOutcome) {
+        let count = match outcome {
+            ImportOutcome::Created => &mut self.created,
+            ImportOutcome::Unchanged => &mut self.unchanged,
+            ImportOutcome::Skipped => &mut self.skipped,
+            ImportOutcome::Overwritten => &mut self.overwritten,
+            ImportOutcome::Renamed => &mut self.renamed,
+        };
+        *count += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.created + self.unchanged + self.skipped + self.overwritten + self.renamed
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ImportSummary {
+    pub items: ImportCounts,
+    pub images: ImportCounts,
+}
+
+/// Imports export records one line at a time
+pub struct Importer {
+    items: Arc<dyn ItemRepository>,
+    images: Arc<dyn ImageStore>,
+    dictionaries: Arc<DictionaryStore>,
+    compute: Arc<ComputePool>,
+    on_conflict: ConflictPolicy,
+    summary: ImportSummary,
+}
+
+impl Importer {
+    pub fn new(
+        items: Arc<dyn ItemRepository>,
+        images: Arc<dyn ImageStore>,
+        dictionaries: Arc<DictionaryStore>,
+        compute: Arc<ComputePool>,
+        on_conflict: ConflictPolicy,
+    ) -> Self {
+        Self {
+            items,
+            images,
+            dictionaries,
+            compute,
+            on_conflict,
+            summary: ImportSummary::default(),
+        }
+    }
+
+    pub fn summary(&self) -> &ImportSummary {
+        &self.summary
+    }
+
+    /// Imports one line; blank lines are ignored and return `None`. Lines without a
+    /// `type` are items, as written by earlier exports.
+    pub async fn import_line(&mut self, line: &str) -> Result<Option<ImportOutcome>, TransferError> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut value: Value = serde_json::from_str(line).map_err(|e| TransferError::Record(e.to_string()))?;
+        if let Value::Object(record) = &mut value
+            && !record.contains_key("type")
+        {
+            record.insert("type".to_string(), Value::from("item"));
+        }
+        let record: ImportRecord = serde_json::from_value(value).map_err(|e| TransferError::Record(e.to_string()))?;
+        let outcome = match record {
+            ImportRecord::Item(record) => {
+                let outcome = self.import_item(record).await?;
+                self.summary.items.add(outcome);
+                outcome
+            }
+            ImportRecord::Image(record) => {
+                let outcome = self.import_image(record).await?;
+                self.summary.images.add(outcome);
+                outcome
+            }
+        };
+        Ok(Some(outcome))
+    }
+
+    async fn import_item(&self, record: ItemRecord) -> Result<ImportOutcome, TransferError> {
+        let data = BASE64_STANDARD
+            .decode(&record.data)
+            .map_err(|_| TransferError::Record("`data` is not valid base64".to_string()))?;
+        let algorithm = record.algorithm.unwrap_or_default();
+        let level = codec::resolve_level(algorithm, record.level)?;
+
+        let Some(id) = record.id else {
+            let payload = self.compress(algorithm, level, data).await?;
+            check_record_checksum("original_sha256", record.original_sha256.as_deref(), &payload.original_sha256)?;
+            self.items.create_item(&record.name, payload).await?;
+            return Ok(ImportOutcome::Created);
+        };
+        // Items are the same version when they were last written at the same time
+        let same_version = |stored: &StoredItem| record.updated_at.is_some() && stored.updated_at == record.updated_at;
+        let (id, outcome) = match self.items.find_item(id).await? {
+            None => (id, ImportOutcome::Created),
+            Some(stored) if same_version(&stored) => return Ok(ImportOutcome::Unchanged),
+            Some(_) => match self.on_conflict {
+                ConflictPolicy::Skip => return Ok(ImportOutcome::Skipped),
+                ConflictPolicy::Overwrite => (id, ImportOutcome::Overwritten),
+                ConflictPolicy::Rename => {
+                    let renamed = renamed_id(id);
+                    match self.items.find_item(renamed).await? {
+                        Some(stored) if same_version(&stored) => return Ok(ImportOutcome::Unchanged),
+                        _ => (renamed, ImportOutcome::Renamed),
+                    }
+                }
+            },
+        };
+
+        let payload = self.compress(algorithm, level, data).await?;
+        check_record_checksum("original_sha256", record.original_sha256.as_deref(), &payload.original_sha256)?;
+        self.items
+            .put_item(StoredItem {
+                id,
+                name: record.name,
+                compressed_size: payload.data.len() as i64,
+                data: payload.data,
+                algorithm: payload.algorithm.as_str().to_string(),
+                level: payload.level,
+                dictionary_version: payload.dictionary_version,
+                original_size: payload.original_size as i64,
+                original_sha256: Some(payload.original_sha256),
+                compressed_sha256: Some(payload.compressed_sha256),
+                created_at: record.created_at,
+                updated_at: record.updated_at,
+                deleted_at: record.deleted_at,
+            })
+            .await?;
+        Ok(outcome)
+    }
+
+    /// Compresses imported data on the compute pool; zstd uses the active dictionary
+    async fn compress(&self, algorithm: CompressionAlgorithm, level: i32, data: Vec<u8>) -> Result<ItemPayload, TransferError> {
+        let dictionary = match algorithm {
+            CompressionAlgorithm::Zstd => self.dictionaries.active(self.items.as_ref()).await?,
+            _ => None,
+        };
+        Ok(self
+            .compute
+            .run(move || dictionary::compress_item(algorithm, level, dictionary, &data))
+            .await??)
+    }
+
+    async fn import_image(&self, record: ImageRecord) -> Result<ImportOutcome, TransferError> {
+        let data = BASE64_STANDARD
+            .decode(&record.data)
+            .map_err(|_| TransferError::Record("`data` is not valid base64".to_string()))?;
+        let compressed_sha256 = sha256_hex(&data);
+        check_record_checksum("compressed_sha256", record.compressed_sha256.as_deref(), &compressed_sha256)?;
+        let thumbnail = match &record.thumbnail {
+            Some(thumbnail) => {
+                let thumbnail = BASE64_STANDARD
+                    .decode(thumbnail)
+                    .map_err(|_| TransferError::Record("`thumbnail` is not valid base64".to_string()))?;
+                check_record_checksum("thumbnail_sha256", record.thumbnail_sha256.as_deref(), &sha256_hex(&thumbnail))?;
+                Some(thumbnail)
+            }
+            None => None,
+        };
+        let mut image = StoredImage {
+            id: record.id,
+            format: record.format,
+            compressed_size: data.len() as i64,
+            data,
+            original_size: record.original_size,
+            original_sha256: record.original_sha256,
+            compressed_sha256: Some(compressed_sha256),
+            thumbnail_sha256: thumbnail.as_deref().map(sha256_hex),
+            thumbnail,
+            options: record.options,
+            created_at: record.created_at,
+        };
+
+        // Images are never changed once stored, so the same bytes mean the same image
+        let outcome = match stored_image(self.images.as_ref(), image.id).await? {
+            None => ImportOutcome::Created,
+            Some(stored) if same_image(&stored, &image) => return Ok(ImportOutcome::Unchanged),
+            Some(_) => match self.on_conflict {
+                ConflictPolicy::Skip => return Ok(ImportOutcome::Skipped),
+                ConflictPolicy::Overwrite => ImportOutcome::Overwritten,
+                ConflictPolicy::Rename => {
+                    image.id = renamed_id(image.id);
+                    match stored_image(self.images.as_ref(), image.id).await? {
+                        Some(stored) if same_image(&stored, &image) => return Ok(ImportOutcome::Unchanged),
+                        _ => ImportOutcome::Renamed,
+                    }
+                }
+            },
+        };
+        self.images.put_image(image).await?;
+        Ok(outcome)
+    }
+}
+
+/// Fails a record whose data does not hash to the checksum it carries
+fn check_record_checksum(field: &'static str, checksum: Option<&str>, actual: &str) -> Result<(), TransferError> {
+    verify_checksum(field, checksum, actual).map_err(|e| TransferError::Record(e.to_string()))
+}
+
+fn same_image(stored: &StoredImage, image: &StoredImage) -> bool {
+    stored.data == image.data && stored.thumbnail == image.thumbnail
+}
+
+async fn stored_image(images: &dyn ImageStore, id: Uuid) -> Result<Option<StoredImage>, DbError> {
+    match images.get_image(id).await {
+        Ok(image) => Ok(Some(image)),
+        Err(DbError::NotFound) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// The ID a conflicting record is stored under with [`ConflictPolicy::Rename`]; the
+/// same for every run, so rerunning an import does not copy the record again
+pub fn renamed_id(id: Uuid) -> Uuid {
+    Uuid::new_v5(&id, RENAMED_ID_NAME)
+}
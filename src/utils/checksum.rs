@@ -0,0 +1,92 @@
+//! SHA-256 checksums: the ones stored with items and images, the optional `checksum`
+//! field of uploads, and the `Content-Digest` request header (RFC 9530).
+
+use axum::http::{HeaderMap, HeaderName};
+use base64::prelude::*;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+pub const CONTENT_DIGEST: HeaderName = HeaderName::from_static("content-digest");
+
+#[derive(Error, Debug)]
+pub enum ChecksumError {
+    #[error("`{0}` must be a hex SHA-256 digest")]
+    Invalid(&'static str),
+
+    #[error("`{field}` is {expected}, but the data hashes to {actual}")]
+    Mismatch {
+        field: &'static str,
+        expected: String,
+        actual: String,
+    },
+
+    #[error("`Content-Digest` must include `sha-256=:<base64>:`")]
+    InvalidDigest,
+
+    #[error("`Content-Digest` is sha-256 {expected}, but the body hashes to {actual}")]
+    DigestMismatch { expected: String, actual: String },
+}
+
+/// Lowercase hex SHA-256 of `data`
+pub fn sha256_hex(data: &[u8]) -> String {
+    to_hex(&Sha256::digest(data))
+}
+
+pub fn to_hex(digest: &[u8]) -> String {
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Checks a client-supplied checksum against the hex SHA-256 of the data it describes.
+/// Accepts 64 hex digits, optionally prefixed with `sha256:`.
+pub fn verify_checksum(field: &'static str, checksum: Option<&str>, actual: &str) -> Result<(), ChecksumError> {
+    let Some(checksum) = checksum else {
+        return Ok(());
+    };
+    let expected = checksum.strip_prefix("sha256:").unwrap_or(checksum);
+    if expected.len() != 64 || !expected.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ChecksumError::Invalid(field));
+    }
+    if !expected.eq_ignore_ascii_case(actual) {
+        return Err(ChecksumError::Mismatch {
+            field,
+            expected: expected.to_ascii_lowercase(),
+            actual: actual.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// The SHA-256 digest of a `Content-Digest` header, if the request has one.
+/// Other algorithms in the header are ignored; a header with only those is rejected,
+/// since the body could not be checked.
+pub fn content_digest(headers: &HeaderMap) -> Result<Option<[u8; 32]>, ChecksumError> {
+    let Some(value) = headers.get(CONTENT_DIGEST) else {
+        return Ok(None);
+    };
+    let value = value.to_str().map_err(|_| ChecksumError::InvalidDigest)?;
+    for member in value.split(',') {
+        let (algorithm, digest) = member.split_once('=').ok_or(ChecksumError::InvalidDigest)?;
+        if !algorithm.trim().eq_ignore_ascii_case("sha-256") {
+            continue;
+        }
+        let digest = digest
+            .trim()
+            .strip_prefix(':')
+            .and_then(|digest| digest.strip_suffix(':'))
+            .ok_or(ChecksumError::InvalidDigest)?;
+        let digest = BASE64_STANDARD.decode(digest).map_err(|_| ChecksumError::InvalidDigest)?;
+        return digest.try_into().map(Some).map_err(|_| ChecksumError::InvalidDigest);
+    }
+    Err(ChecksumError::InvalidDigest)
+}
+
+/// Checks a request body's SHA-256 against its `Content-Digest`
+pub fn verify_content_digest(expected: [u8; 32], actual: &[u8]) -> Result<(), ChecksumError> {
+    if expected[..] != *actual {
+        return Err(ChecksumError::DigestMismatch {
+            expected: to_hex(&expected),
+            actual: to_hex(actual),
+        });
+    }
+    Ok(())
+}
@@ -1,43 +1,503 @@
 use axum::{
-    http::StatusCode,
+    extract::rejection::{JsonRejection, PathRejection, QueryRejection},
+    http::{HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
-    Json,
 };
-use serde_json::json;
-
-/// Application-wide error type
-#[derive(Debug, thiserror::Error)]
-pub enum AppError {
-    #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
-    
-    #[error("Configuration error: {0}")]
-    Config(#[from] config::ConfigError),
-    
-    #[error("Validation error: {0}")]
-    Validation(String),
-    
-    #[error("Not found: {0}")]
-    NotFound(String),
-    
-    #[error("Internal server error")]
-    Internal,
+use serde::Serialize;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::services::{AuthError, CodecError, ComputeError, DataError, ImageProcessingError};
+use crate::services::rate_limit::{LimitKind, RateLimitError};
+use crate::services::stats::StatsError;
+use crate::utils::checksum::ChecksumError;
+
+/// Media type of every error response
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+tokio::task_local! {
+    /// Id of the request being handled, set by the request-id middleware
+    pub static REQUEST_ID: String;
+}
+
+/// Declares [`ErrorCode`] from one list of variants and their wire names, so the
+/// serialized form and [`ErrorCode::as_str`] cannot drift apart
+macro_rules! error_codes {
+    ($($variant:ident => $code:tt,)+) => {
+        /// Stable, machine-readable error codes. Clients should match on these rather than
+        /// on `detail`, which is meant for humans and may change.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+        pub enum ErrorCode {
+            $(
+                #[serde(rename = $code)]
+                $variant,
+            )+
+        }
+
+        impl ErrorCode {
+            pub const ALL: &[ErrorCode] = &[$(ErrorCode::$variant),+];
+
+            pub fn as_str(self) -> &'static str {
+                match self {
+                    $(ErrorCode::$variant => $code,)+
+                }
+            }
+        }
+    };
 }
 
+error_codes! {
+    MalformedJson => "request.malformed_json",
+    InvalidBody => "request.invalid_body",
+    UnsupportedMediaType => "request.unsupported_media_type",
+    BodyTooLarge => "request.body_too_large",
+    InvalidQuery => "request.invalid_query",
+    InvalidPath => "request.invalid_path",
+    PreconditionFailed => "request.precondition_failed",
+    ValidationFailed => "request.validation_failed",
+    ChecksumMismatch => "request.checksum_mismatch",
+    RouteNotFound => "route.not_found",
+    MethodNotAllowed => "route.method_not_allowed",
+    MissingApiKey => "auth.missing_key",
+    InvalidApiKey => "auth.invalid_key",
+    InsufficientScope => "auth.insufficient_scope",
+    KeysReadOnly => "auth.read_only",
+    ApiKeyNotFound => "api_key.not_found",
+    ItemNotFound => "item.not_found",
+    ImageNotFound => "image.not_found",
+    DatabaseDisabled => "database.disabled",
+    DictionaryNotFound => "dictionary.not_found",
+    TrainingFailed => "dictionary.training_failed",
+    RateLimited => "rate_limit.exceeded",
+    RequestQuotaExceeded => "quota.requests_exceeded",
+    ByteQuotaExceeded => "quota.bytes_exceeded",
+    InvalidImageInput => "image.invalid_input",
+    InvalidResize => "image.invalid_resize",
+    ImageTooLarge => "image.too_large",
+    DecodeFailed => "image.decode_failed",
+    UnsupportedFormat => "image.unsupported_format",
+    ProcessingFailed => "image.processing_failed",
+    DecompressFailed => "data.decompress_failed",
+    DataTooLarge => "data.too_large",
+    InvalidUrl => "fetch.invalid_url",
+    FetchTimeout => "fetch.timeout",
+    FetchConnectFailed => "fetch.connect_failed",
+    TooManyRedirects => "fetch.too_many_redirects",
+    UpstreamStatus => "fetch.upstream_status",
+    FetchFailed => "fetch.failed",
+    InvalidRange => "stats.invalid_range",
+    ShuttingDown => "server.shutting_down",
+    Internal => "internal.error",
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::MalformedJson
+            | ErrorCode::InvalidQuery
+            | ErrorCode::InvalidPath
+            | ErrorCode::ValidationFailed
+            | ErrorCode::ChecksumMismatch
+            | ErrorCode::InvalidImageInput
+            | ErrorCode::InvalidResize
+            | ErrorCode::DecodeFailed
+            | ErrorCode::UnsupportedFormat
+            | ErrorCode::InvalidUrl
+            | ErrorCode::DecompressFailed
+            | ErrorCode::InvalidRange => StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidBody | ErrorCode::TrainingFailed => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::BodyTooLarge | ErrorCode::ImageTooLarge | ErrorCode::DataTooLarge => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            ErrorCode::RouteNotFound
+            | ErrorCode::ApiKeyNotFound
+            | ErrorCode::ItemNotFound
+            | ErrorCode::ImageNotFound
+            | ErrorCode::DictionaryNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::PreconditionFailed => StatusCode::PRECONDITION_FAILED,
+            ErrorCode::MissingApiKey | ErrorCode::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            ErrorCode::InsufficientScope => StatusCode::FORBIDDEN,
+            ErrorCode::KeysReadOnly => StatusCode::NOT_IMPLEMENTED,
+            ErrorCode::RateLimited | ErrorCode::RequestQuotaExceeded | ErrorCode::ByteQuotaExceeded => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            ErrorCode::FetchTimeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorCode::FetchConnectFailed
+            | ErrorCode::TooManyRedirects
+            | ErrorCode::UpstreamStatus
+            | ErrorCode::FetchFailed => StatusCode::BAD_GATEWAY,
+            ErrorCode::ShuttingDown | ErrorCode::DatabaseDisabled => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ProcessingFailed | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// RFC 9457 problem details, the body of every error response
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ProblemDetails {
+    /// URI identifying the problem type, derived from `code`
+    #[serde(rename = "type")]
+    #[schema(example = "urn:rust-compress-api:error:image.decode_failed")]
+    pub problem_type: String,
+
+    /// Short summary of the status code
+    #[schema(example = "Bad Request")]
+    pub title: String,
+
+    /// HTTP status code
+    #[schema(example = 400)]
+    pub status: u16,
+
+    /// Human-readable explanation of this occurrence
+    #[schema(example = "Invalid or corrupted image format")]
+    pub detail: String,
+
+    /// Stable machine-readable error code
+    pub code: ErrorCode,
+
+    /// Id of the request, also sent as `X-Request-Id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "0199f1b2-7c1e-7d32-9a51-3c4b8f2e6a10")]
+    pub request_id: Option<String>,
+}
+
+/// Application-wide error type, rendered as `application/problem+json`
+#[derive(Debug)]
+pub struct AppError {
+    pub code: ErrorCode,
+    pub detail: String,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            code,
+            detail: detail.into(),
+            headers: Vec::new(),
+        }
+    }
+
+    /// Logs the underlying cause and returns a generic internal error
+    pub fn internal(context: &str, cause: impl std::fmt::Debug) -> Self {
+        error!("{}: {:?}", context, cause);
+        Self::new(ErrorCode::Internal, context)
+    }
+
+    /// Adds a response header, e.g. `Retry-After`
+    pub fn with_header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn problem(&self) -> ProblemDetails {
+        let status = self.code.status();
+        ProblemDetails {
+            problem_type: format!("urn:rust-compress-api:error:{}", self.code.as_str()),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: self.detail.clone(),
+            code: self.code,
+            request_id: REQUEST_ID.try_with(Clone::clone).ok(),
+        }
+    }
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code.as_str(), self.detail)
+    }
+}
+
+impl std::error::Error for AppError {}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match &self {
-            AppError::Database(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string()),
-            AppError::Config(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Configuration error".to_string()),
-            AppError::Validation(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
-            AppError::Internal => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
+        let body = serde_json::to_vec(&self.problem()).expect("problem details serialize to JSON");
+        let mut response = (
+            self.code.status(),
+            [(header::CONTENT_TYPE, HeaderValue::from_static(PROBLEM_CONTENT_TYPE))],
+            body,
+        )
+            .into_response();
+        for (name, value) in self.headers {
+            response.headers_mut().insert(name, value);
+        }
+        response
+    }
+}
+
+impl From<ImageProcessingError> for AppError {
+    fn from(e: ImageProcessingError) -> Self {
+        match e {
+            ImageProcessingError::InvalidResizePercentage(percentage) => AppError::new(
+                ErrorCode::InvalidResize,
+                format!("Invalid resize percentage: {}. Must be between 1 and 100", percentage),
+            ),
+            ImageProcessingError::ImageTooLarge(size, max_size) => AppError::new(
+                ErrorCode::ImageTooLarge,
+                format!("Image too large: {} bytes. Maximum allowed: {} bytes", size, max_size),
+            ),
+            ImageProcessingError::InvalidUrl(reason) => {
+                AppError::new(ErrorCode::InvalidUrl, format!("Invalid image URL: {}", reason))
+            }
+            ImageProcessingError::DownloadError(e) => download_error(e),
+            ImageProcessingError::DecodeError(_) => {
+                AppError::new(ErrorCode::DecodeFailed, "Invalid or corrupted image format")
+            }
+            ImageProcessingError::UnsupportedFormat => {
+                AppError::new(ErrorCode::UnsupportedFormat, "Unsupported image format")
+            }
+            ImageProcessingError::InvalidInput(msg) => AppError::new(ErrorCode::InvalidImageInput, msg),
+            ImageProcessingError::Checksum(e) => e.into(),
+            ImageProcessingError::Compute(ComputeError::Closed) => {
+                AppError::new(ErrorCode::ShuttingDown, "Server is shutting down")
+            }
+            ImageProcessingError::Compute(e) => {
+                error!("Image processing failed: {:?}", e);
+                AppError::new(ErrorCode::ProcessingFailed, "Image processing failed")
+            }
+        }
+    }
+}
+
+fn download_error(e: reqwest::Error) -> AppError {
+    let (code, detail) = if e.is_timeout() {
+        (ErrorCode::FetchTimeout, "Timed out downloading the image".to_string())
+    } else if e.is_redirect() {
+        (ErrorCode::TooManyRedirects, "Too many redirects downloading the image".to_string())
+    } else if e.is_builder() {
+        (ErrorCode::InvalidUrl, "Invalid image URL".to_string())
+    } else if e.is_connect() {
+        (ErrorCode::FetchConnectFailed, "Could not connect to the image host".to_string())
+    } else if let Some(status) = e.status() {
+        (ErrorCode::UpstreamStatus, format!("Image host responded with {}", status))
+    } else {
+        (ErrorCode::FetchFailed, "Failed to download image from URL".to_string())
+    };
+    AppError::new(code, detail)
+}
+
+impl From<ComputeError> for AppError {
+    fn from(e: ComputeError) -> Self {
+        match e {
+            ComputeError::Closed => AppError::new(ErrorCode::ShuttingDown, "Server is shutting down"),
+            e => AppError::internal("Compute task failed", e),
+        }
+    }
+}
+
+impl From<CodecError> for AppError {
+    fn from(e: CodecError) -> Self {
+        match e {
+            CodecError::InvalidLevel { .. } => AppError::new(ErrorCode::ValidationFailed, e.to_string()),
+            CodecError::Corrupt(..) => AppError::new(ErrorCode::DecompressFailed, e.to_string()),
+            CodecError::TooLarge(_) => AppError::new(ErrorCode::DataTooLarge, e.to_string()),
+            e => AppError::internal("Compression failed", e),
+        }
+    }
+}
+
+impl From<DataError> for AppError {
+    fn from(e: DataError) -> Self {
+        match e {
+            DataError::TooLarge(_) => AppError::new(ErrorCode::BodyTooLarge, e.to_string()),
+            DataError::Body(_) => AppError::new(ErrorCode::ValidationFailed, e.to_string()),
+            DataError::Codec(e) => e.into(),
+            DataError::Compute(e) => e.into(),
+            e @ DataError::Spool(_) => AppError::internal("Failed to buffer data", e),
+        }
+    }
+}
+
+impl From<AuthError> for AppError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::ReadOnly => AppError::new(ErrorCode::KeysReadOnly, e.to_string()),
+            AuthError::InvalidInput(msg) => AppError::new(ErrorCode::ValidationFailed, msg),
+            e => AppError::internal("API key operation failed", e),
+        }
+    }
+}
+
+#[cfg(feature = "database")]
+impl From<crate::core::database::DbError> for AppError {
+    fn from(e: crate::core::database::DbError) -> Self {
+        match e {
+            crate::core::database::DbError::NotFound => AppError::new(ErrorCode::ItemNotFound, "Item not found"),
+            crate::core::database::DbError::Conflict => AppError::new(
+                ErrorCode::PreconditionFailed,
+                "Item was changed by another request; fetch it again for the current ETag",
+            ),
+            e => AppError::internal("Item storage failed", e),
+        }
+    }
+}
+
+#[cfg(feature = "database")]
+impl From<crate::services::dictionary::DictionaryError> for AppError {
+    fn from(e: crate::services::dictionary::DictionaryError) -> Self {
+        use crate::services::dictionary::DictionaryError;
+        match e {
+            DictionaryError::NoSamples | DictionaryError::Training(_) => {
+                AppError::new(ErrorCode::TrainingFailed, e.to_string())
+            }
+            DictionaryError::Database(e) => e.into(),
+            e => AppError::internal("Dictionary training failed", e),
+        }
+    }
+}
+
+#[cfg(feature = "database")]
+impl From<crate::services::transfer::TransferError> for AppError {
+    fn from(e: crate::services::transfer::TransferError) -> Self {
+        use crate::services::transfer::TransferError;
+        match e {
+            TransferError::Database(e) => e.into(),
+            TransferError::Compute(e) => e.into(),
+            e => AppError::internal("Export failed", e),
+        }
+    }
+}
+
+impl From<StatsError> for AppError {
+    fn from(e: StatsError) -> Self {
+        AppError::internal("Failed to query statistics", e)
+    }
+}
+
+impl From<RateLimitError> for AppError {
+    fn from(e: RateLimitError) -> Self {
+        AppError::internal("Failed to read usage", e)
+    }
+}
+
+impl From<LimitKind> for AppError {
+    fn from(kind: LimitKind) -> Self {
+        let code = match kind {
+            LimitKind::Rate => ErrorCode::RateLimited,
+            LimitKind::DailyRequests | LimitKind::MonthlyRequests => ErrorCode::RequestQuotaExceeded,
+            LimitKind::DailyBytes | LimitKind::MonthlyBytes => ErrorCode::ByteQuotaExceeded,
+        };
+        AppError::new(code, kind.description())
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match &rejection {
+            JsonRejection::JsonSyntaxError(_) => ErrorCode::MalformedJson,
+            JsonRejection::JsonDataError(_) => ErrorCode::InvalidBody,
+            JsonRejection::MissingJsonContentType(_) => ErrorCode::UnsupportedMediaType,
+            _ if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::BodyTooLarge,
+            _ => ErrorCode::MalformedJson,
         };
+        AppError::new(code, rejection.body_text())
+    }
+}
+
+impl From<ChecksumError> for AppError {
+    fn from(e: ChecksumError) -> Self {
+        let code = match e {
+            ChecksumError::Invalid(_) | ChecksumError::InvalidDigest => ErrorCode::ValidationFailed,
+            ChecksumError::Mismatch { .. } | ChecksumError::DigestMismatch { .. } => ErrorCode::ChecksumMismatch,
+        };
+        AppError::new(code, e.to_string())
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::new(ErrorCode::InvalidQuery, rejection.body_text())
+    }
+}
+
+impl From<PathRejection> for AppError {
+    fn from(rejection: PathRejection) -> Self {
+        AppError::new(ErrorCode::InvalidPath, rejection.body_text())
+    }
+}
 
-        let body = Json(json!({
-            "error": error_message
-        }));
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+
+    use super::*;
+
+    #[test]
+    fn codes_serialize_as_their_wire_names() {
+        for code in ErrorCode::ALL {
+            assert_eq!(serde_json::to_value(code).unwrap(), code.as_str());
+        }
+        assert_eq!(ErrorCode::ChecksumMismatch.as_str(), "request.checksum_mismatch");
+    }
+
+    #[test]
+    fn the_schema_lists_the_wire_names() {
+        let schema = serde_json::to_value(<ErrorCode as utoipa::PartialSchema>::schema()).unwrap();
+        let values = schema["enum"].as_array().unwrap();
+        assert_eq!(values.len(), ErrorCode::ALL.len());
+        assert!(values.contains(&serde_json::json!("fetch.invalid_url")));
+    }
+
+    #[test]
+    fn every_code_maps_to_an_error_status() {
+        for code in ErrorCode::ALL {
+            let status = code.status();
+            assert!(status.is_client_error() || status.is_server_error(), "{code:?} -> {status}");
+        }
+    }
+
+    #[tokio::test]
+    async fn errors_render_as_problem_json() {
+        let response = AppError::new(ErrorCode::ItemNotFound, "Item not found")
+            .with_header(header::RETRY_AFTER, HeaderValue::from_static("5"))
+            .into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], PROBLEM_CONTENT_TYPE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "5");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let problem: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            problem,
+            serde_json::json!({
+                "type": "urn:rust-compress-api:error:item.not_found",
+                "title": "Not Found",
+                "status": 404,
+                "detail": "Item not found",
+                "code": "item.not_found",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn the_request_id_is_included_when_set() {
+        let problem = REQUEST_ID
+            .scope("req-1".to_string(), async { AppError::new(ErrorCode::Internal, "boom").problem() })
+            .await;
+        assert_eq!(problem.request_id.as_deref(), Some("req-1"));
+        assert_eq!(problem.status, 500);
+    }
 
-        (status, body).into_response()
+    #[test]
+    fn service_errors_map_to_codes() {
+        let cases = [
+            (AppError::from(ComputeError::Closed), ErrorCode::ShuttingDown),
+            (AppError::from(ComputeError::Panicked), ErrorCode::Internal),
+            (AppError::from(AuthError::ReadOnly), ErrorCode::KeysReadOnly),
+            (AppError::from(AuthError::InvalidInput("x".into())), ErrorCode::ValidationFailed),
+            (AppError::from(LimitKind::Rate), ErrorCode::RateLimited),
+            (AppError::from(LimitKind::MonthlyRequests), ErrorCode::RequestQuotaExceeded),
+            (AppError::from(LimitKind::DailyBytes), ErrorCode::ByteQuotaExceeded),
+            (AppError::from(ImageProcessingError::InvalidUrl("x".into())), ErrorCode::InvalidUrl),
+            (AppError::from(ImageProcessingError::UnsupportedFormat), ErrorCode::UnsupportedFormat),
+            (AppError::from(ImageProcessingError::ImageTooLarge(2, 1)), ErrorCode::ImageTooLarge),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.code, code, "{}", error.detail);
+        }
     }
 }
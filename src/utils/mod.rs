@@ -1,3 +1,5 @@
-// pub mod errors;
+pub mod checksum;
+pub mod errors;
+pub mod spool;
 
-// pub use errors::*;
+pub use errors::*;
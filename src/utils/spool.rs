@@ -0,0 +1,227 @@
+//! Temporary storage for payloads too large to keep in memory
+//!
+//! A [`SpoolWriter`] keeps the first [`MEMORY_LIMIT`] bytes in memory and moves to an
+//! anonymous temporary file past that. The file has no name on disk and disappears
+//! when the [`Spool`] holding it is dropped.
+
+use std::fs::File;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use axum::body::{Body, Bytes};
+use tokio::io::AsyncReadExt;
+
+/// Bytes kept in memory before a spool moves to a temporary file
+pub const MEMORY_LIMIT: usize = 1024 * 1024;
+
+/// Size of the chunks a file-backed spool is streamed in
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Collects a payload in memory, then in a temporary file under `dir`
+#[derive(Debug)]
+pub struct SpoolWriter {
+    dir: PathBuf,
+    memory_limit: usize,
+    memory: Vec<u8>,
+    file: Option<File>,
+    len: u64,
+}
+
+impl SpoolWriter {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_memory_limit(dir, MEMORY_LIMIT)
+    }
+
+    pub fn with_memory_limit(dir: impl Into<PathBuf>, memory_limit: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            memory_limit,
+            memory: Vec::new(),
+            file: None,
+            len: 0,
+        }
+    }
+
+    /// Bytes written so far
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends `chunk` from async code; once the spool is on disk the write runs on a
+    /// blocking thread
+    pub async fn write_chunk(&mut self, chunk: Bytes) -> io::Result<()> {
+        if self.file.is_none() && self.memory.len() + chunk.len() <= self.memory_limit {
+            self.memory.extend_from_slice(&chunk);
+            self.len += chunk.len() as u64;
+            return Ok(());
+        }
+
+        let mut writer = std::mem::replace(self, Self::with_memory_limit(self.dir.clone(), self.memory_limit));
+        let writer = tokio::task::spawn_blocking(move || writer.write_all(&chunk).map(|_| writer))
+            .await
+            .map_err(io::Error::other)??;
+        *self = writer;
+        Ok(())
+    }
+
+    /// Ends writing and rewinds the spool for reading
+    pub fn finish(self) -> io::Result<Spool> {
+        match self.file {
+            Some(mut file) => {
+                file.flush()?;
+                file.seek(SeekFrom::Start(0))?;
+                Ok(Spool::File { file, len: self.len })
+            }
+            None => Ok(Spool::Memory(Bytes::from(self.memory))),
+        }
+    }
+}
+
+impl Write for SpoolWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.file.is_none() && self.memory.len() + buf.len() > self.memory_limit {
+            let mut file = tempfile::tempfile_in(&self.dir)?;
+            file.write_all(&self.memory)?;
+            self.memory = Vec::new();
+            self.file = Some(file);
+        }
+
+        let written = match &mut self.file {
+            Some(file) => file.write(buf)?,
+            None => {
+                self.memory.extend_from_slice(buf);
+                buf.len()
+            }
+        };
+        self.len += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some(file) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A finished payload, in memory or in a temporary file
+#[derive(Debug)]
+pub enum Spool {
+    Memory(Bytes),
+    File { file: File, len: u64 },
+}
+
+impl Spool {
+    pub fn len(&self) -> u64 {
+        match self {
+            Spool::Memory(bytes) => bytes.len() as u64,
+            Spool::File { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Blocking reader over the payload, for use on a compute worker
+    pub fn into_reader(self) -> Box<dyn Read + Send> {
+        match self {
+            Spool::Memory(bytes) => Box::new(Cursor::new(bytes)),
+            Spool::File { file, .. } => Box::new(BufReader::new(file)),
+        }
+    }
+
+    /// The whole payload in memory
+    pub async fn into_bytes(self) -> io::Result<Bytes> {
+        match self {
+            Spool::Memory(bytes) => Ok(bytes),
+            Spool::File { file, len } => {
+                let mut data = Vec::with_capacity(len as usize);
+                tokio::fs::File::from_std(file).read_to_end(&mut data).await?;
+                Ok(Bytes::from(data))
+            }
+        }
+    }
+
+    /// A response body streaming the payload; file-backed spools are read in chunks
+    pub fn into_body(self) -> Body {
+        match self {
+            Spool::Memory(bytes) => Body::from(bytes),
+            Spool::File { file, .. } => {
+                let file = Some(tokio::fs::File::from_std(file));
+                Body::from_stream(futures_util::stream::unfold(file, |file| async move {
+                    let mut file = file?;
+                    let mut chunk = vec![0; READ_CHUNK];
+                    match file.read(&mut chunk).await {
+                        Ok(0) => None,
+                        Ok(n) => {
+                            chunk.truncate(n);
+                            Some((Ok(Bytes::from(chunk)), Some(file)))
+                        }
+                        // Stop after reporting the error rather than retrying the read
+                        Err(e) => Some((Err(e), None)),
+                    }
+                }))
+            }
+        }
+    }
+}
+
+impl From<Vec<u8>> for Spool {
+    fn from(data: Vec<u8>) -> Self {
+        Spool::Memory(Bytes::from(data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn small_payloads_stay_in_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SpoolWriter::with_memory_limit(dir.path(), 8);
+        writer.write_chunk(Bytes::from_static(b"hello")).await.unwrap();
+
+        let spool = writer.finish().unwrap();
+        assert!(matches!(spool, Spool::Memory(_)));
+        assert_eq!(spool.into_bytes().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn large_payloads_move_to_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SpoolWriter::with_memory_limit(dir.path(), 8);
+        writer.write_chunk(Bytes::from_static(b"hello ")).await.unwrap();
+        writer.write_chunk(Bytes::from_static(b"spooled ")).await.unwrap();
+        writer.write_all(b"world").unwrap();
+        assert_eq!(writer.len(), 19);
+
+        let spool = writer.finish().unwrap();
+        assert!(matches!(spool, Spool::File { len: 19, .. }));
+        // The file is anonymous, so nothing is left in the directory
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        let mut data = String::new();
+        spool.into_reader().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "hello spooled world");
+    }
+
+    #[tokio::test]
+    async fn file_spools_stream_as_a_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = SpoolWriter::with_memory_limit(dir.path(), 16);
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        writer.write_all(&data).unwrap();
+
+        let body = writer.finish().unwrap().into_body();
+        assert_eq!(to_bytes(body, usize::MAX).await.unwrap(), data);
+    }
+}
@@ -0,0 +1,109 @@
+//! An app built the way `main` builds it, for tests that go through the router
+#![allow(dead_code)] // each test crate uses a different part
+
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::{Body, to_bytes};
+use axum::http::{Method, Request, Response, header};
+use rust_compress_api::api::routes::create_router;
+use rust_compress_api::core::config::AppConfig;
+use rust_compress_api::core::models::AppState;
+use rust_compress_api::server::Lifecycle;
+#[cfg(feature = "database")]
+use rust_compress_api::services::dictionary::DictionaryStore;
+use rust_compress_api::services::{
+    AuthService, ComputePool, DataCompressionService, HealthService, ImageCompressionService, Metrics, RateLimiter,
+    StatsService,
+};
+use serde_json::Value;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+pub struct TestApp {
+    pub state: AppState,
+    router: Router,
+    _storage: TempDir,
+}
+
+impl TestApp {
+    /// Every service built from `config`, writing to a temporary storage directory,
+    /// without a database
+    pub fn new(config: AppConfig) -> Self {
+        Self::build(config, |_, _| {})
+    }
+
+    /// Like [`TestApp::new`], with `customize` applied to the state before the router
+    /// is built
+    pub fn build(mut config: AppConfig, customize: impl FnOnce(&mut AppState, &AppConfig)) -> Self {
+        let storage = tempfile::tempdir().unwrap();
+        config.storage.path = storage.path().to_path_buf();
+
+        let compute = Arc::new(ComputePool::new(config.limits.compute_workers));
+        let metrics = Arc::new(Metrics::new());
+        let lifecycle = Arc::new(Lifecycle::new());
+        let mut state = AppState {
+            #[cfg(feature = "database")]
+            items: None,
+            #[cfg(feature = "database")]
+            images: None,
+            #[cfg(feature = "database")]
+            dictionaries: Arc::new(DictionaryStore::new()),
+            image_service: Arc::new(
+                ImageCompressionService::from_config(&config, compute.clone(), metrics.clone()).unwrap(),
+            ),
+            data_service: Arc::new(DataCompressionService::from_config(&config, compute.clone())),
+            compute: compute.clone(),
+            lifecycle: lifecycle.clone(),
+            metrics,
+            stats: Arc::new(StatsService::in_memory()),
+            health: Arc::new(HealthService::new(&config, compute, lifecycle)),
+            auth: Arc::new(AuthService::from_config(&config.auth).unwrap()),
+            rate_limiter: Arc::new(RateLimiter::from_config(&config.rate_limit)),
+        };
+        customize(&mut state, &config);
+
+        Self {
+            router: create_router(state.clone()),
+            state,
+            _storage: storage,
+        }
+    }
+
+    /// Items and images in a fresh in-memory store, which `POST /compress` keeps its
+    /// images in as `main` sets it up to
+    #[cfg(feature = "database")]
+    pub async fn with_database(config: AppConfig) -> Self {
+        let storage = rust_compress_api::core::database::connect("memory:", true).await.unwrap();
+        Self::build(config, |state, config| {
+            let image_service = ImageCompressionService::from_config(config, state.compute.clone(), state.metrics.clone());
+            state.image_service = Arc::new(image_service.unwrap().with_store(storage.images.clone()));
+            state.items = Some(storage.items);
+            state.images = Some(storage.images);
+        })
+    }
+
+    /// Sends a request through the router; `body` goes out as JSON
+    pub async fn send(&self, method: Method, uri: &str, headers: &[(&str, &str)], body: Option<Value>) -> Response<Body> {
+        let mut request = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let body = match body {
+            Some(body) => {
+                request = request.header(header::CONTENT_TYPE, "application/json");
+                Body::from(body.to_string())
+            }
+            None => Body::empty(),
+        };
+        self.router.clone().oneshot(request.body(body).unwrap()).await.unwrap()
+    }
+
+    pub async fn get(&self, uri: &str) -> Response<Body> {
+        self.send(Method::GET, uri, &[], None).await
+    }
+}
+
+pub async fn json(response: Response<Body>) -> Value {
+    serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap()
+}
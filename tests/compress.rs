@@ -0,0 +1,40 @@
+//! `POST /compress` over HTTP
+
+mod common;
+
+use std::io::Cursor;
+
+use axum::http::{Method, StatusCode};
+use base64::prelude::*;
+use rust_compress_api::core::config::AppConfig;
+use serde_json::json;
+
+use common::{TestApp, json};
+
+fn png() -> String {
+    let image = image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, 128]));
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    BASE64_STANDARD.encode(data)
+}
+
+#[tokio::test]
+async fn responses_carry_server_timing() {
+    let app = TestApp::new(AppConfig::default());
+    let body = json!({"image_data": png(), "filename": "gradient.png", "content_type": "image/png", "max_width": 32});
+    let response = app.send(Method::POST, "/compress", &[], Some(body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let header = response.headers()["server-timing"].to_str().unwrap().to_string();
+    let metrics: Vec<_> = header.split(", ").map(|metric| metric.split_once(";dur=").unwrap()).collect();
+    let names: Vec<_> = metrics.iter().map(|(name, _)| *name).collect();
+    assert_eq!(names, ["fetch", "decode", "resize", "encode", "thumbnail", "total"]);
+    for (name, duration) in &metrics {
+        assert!(duration.parse::<f64>().unwrap() >= 0.0, "{name}: {duration}");
+    }
+
+    // The header repeats the body's timings
+    let timings = json(response).await["timings"].clone();
+    let total = format!("{:.1}", timings["total_ms"].as_f64().unwrap());
+    assert_eq!(metrics.last().unwrap().1, total);
+}
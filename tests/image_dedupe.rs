@@ -0,0 +1,65 @@
+//! Repeat compressions of the same image are answered from the image store
+#![cfg(feature = "database")]
+
+use std::io::Cursor;
+use std::sync::Arc;
+
+use base64::Engine;
+use rust_compress_api::core::config::AppConfig;
+use rust_compress_api::core::database;
+use rust_compress_api::core::models::CompressImageRequest;
+use rust_compress_api::services::{ComputePool, ImageCompressionService, Metrics};
+
+fn png() -> String {
+    let image = image::RgbImage::from_fn(64, 48, |x, y| image::Rgb([(x * 4) as u8, (y * 5) as u8, 128]));
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    base64::prelude::BASE64_STANDARD.encode(data)
+}
+
+fn request(image_data: &str, quality: u8) -> CompressImageRequest {
+    CompressImageRequest {
+        image_data: Some(image_data.to_string()),
+        image_url: None,
+        filename: "gradient.png".to_string(),
+        content_type: "image/png".to_string(),
+        generate_thumbnail: Some(true),
+        thumbnail_size: Some(50),
+        quality: Some(quality),
+        max_width: None,
+        max_height: None,
+        checksum: None,
+    }
+}
+
+#[tokio::test]
+async fn repeat_requests_reuse_the_stored_image() {
+    let storage = database::connect("memory:", false).await.unwrap();
+    let config = AppConfig::default();
+    let service = ImageCompressionService::from_config(&config, Arc::new(ComputePool::new(1)), Arc::new(Metrics::new()))
+        .unwrap()
+        .with_store(storage.images.clone());
+    let image = png();
+
+    let first = service.compress_image(request(&image, 70)).await.unwrap();
+    assert!(!first.deduplicated);
+
+    let second = service.compress_image(request(&image, 70)).await.unwrap();
+    assert!(second.deduplicated);
+    assert_eq!(second.file_id, first.file_id);
+    assert_eq!(second.compressed_data, first.compressed_data);
+    assert_eq!(second.thumbnail_data, first.thumbnail_data);
+
+    // Only the first request stored anything
+    let stored = storage.images.list_images(None, 10).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    assert_eq!(stored[0].id.to_string(), first.file_id);
+    let blobs = storage.images.blob_stats().await.unwrap();
+    assert_eq!(blobs.references, 2);
+
+    // Different options are a different derivative
+    let other = service.compress_image(request(&image, 40)).await.unwrap();
+    assert!(!other.deduplicated);
+    assert_ne!(other.file_id, first.file_id);
+    assert_eq!(storage.images.list_images(None, 10).await.unwrap().len(), 2);
+}
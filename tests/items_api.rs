@@ -0,0 +1,227 @@
+//! The items API over HTTP, against in-memory storage with authentication off
+#![cfg(feature = "database")]
+
+mod common;
+
+use axum::body::Body;
+use axum::http::{Method, Response, StatusCode, header};
+use base64::prelude::*;
+use rust_compress_api::core::config::AppConfig;
+use serde_json::{Value, json};
+
+use common::{TestApp, json};
+
+async fn app() -> TestApp {
+    TestApp::with_database(AppConfig::default()).await
+}
+
+fn etag(response: &Response<Body>) -> String {
+    response.headers()[header::ETAG].to_str().unwrap().to_string()
+}
+
+/// Creates an item and returns its JSON
+async fn create(app: &TestApp, name: &str, data: &str) -> Value {
+    let body = json!({"name": name, "data": BASE64_STANDARD.encode(data)});
+    let response = app.send(Method::POST, "/items", &[], Some(body)).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    json(response).await
+}
+
+fn names(page: &Value) -> Vec<&str> {
+    page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["name"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn cursors_walk_every_page_once() {
+    let app = app().await;
+    for i in 0..5 {
+        create(&app, &format!("item-{i}"), "some data").await;
+    }
+
+    let mut seen = Vec::new();
+    let mut uri = "/items?limit=2&sort=asc".to_string();
+    loop {
+        let response = app.send(Method::GET, &uri, &[], None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-total-count"], "5");
+        let page = json(response).await;
+        seen.extend(names(&page).into_iter().map(str::to_string));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => uri = format!("/items?limit=2&sort=asc&cursor={cursor}"),
+            None => break,
+        }
+    }
+    assert_eq!(seen, ["item-0", "item-1", "item-2", "item-3", "item-4"]);
+
+    // Newest first by default
+    let page = json(app.send(Method::GET, "/items?limit=2", &[], None).await).await;
+    assert_eq!(names(&page), ["item-4", "item-3"]);
+}
+
+#[tokio::test]
+async fn listings_select_fields_and_filter_by_name() {
+    let app = app().await;
+    create(&app, "report-1", "one").await;
+    create(&app, "report-2", "two").await;
+    create(&app, "notes", "three").await;
+
+    let response = app.send(Method::GET, "/items?name_prefix=report&fields=name,original_size&sort=asc", &[], None).await;
+    assert_eq!(response.headers()["x-total-count"], "2");
+    let page = json(response).await;
+    assert_eq!(page["items"], json!([{"name": "report-1", "original_size": 3}, {"name": "report-2", "original_size": 3}]));
+    assert_eq!(page["next_cursor"], Value::Null);
+
+    let page = json(app.send(Method::GET, "/items?fields=data&name_contains=NOTE", &[], None).await).await;
+    assert_eq!(page["items"], json!([{"data": BASE64_STANDARD.encode("three")}]));
+}
+
+#[tokio::test]
+async fn bad_listing_parameters_are_rejected() {
+    let app = app().await;
+    for query in ["limit=0", "limit=501", "cursor=not-a-cursor", "fields=name,colour", "created_after=yesterday"] {
+        let response = app.send(Method::GET, &format!("/items?{query}"), &[], None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{query}");
+        assert_eq!(json(response).await["code"], "request.invalid_query", "{query}");
+    }
+}
+
+#[tokio::test]
+async fn if_none_match_answers_304_until_the_item_changes() {
+    let app = app().await;
+    let id = create(&app, "cached", "data").await["id"].as_str().unwrap().to_string();
+    let uri = format!("/items/{id}");
+
+    let response = app.send(Method::GET, &uri, &[], None).await;
+    let tag = etag(&response);
+    assert_eq!(json(response).await["etag"], tag.as_str());
+
+    let response = app.send(Method::GET, &uri, &[("if-none-match", &tag)], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(etag(&response), tag);
+    // Weak comparison applies to If-None-Match
+    let weak = format!("W/{tag}");
+    let response = app.send(Method::GET, &uri, &[("if-none-match", &weak)], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let page = app.send(Method::GET, "/items", &[], None).await;
+    let page_tag = etag(&page);
+    let response = app.send(Method::GET, "/items", &[("if-none-match", &page_tag)], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    app.send(Method::PUT, &uri, &[], Some(json!({"name": "renamed"}))).await;
+    let response = app.send(Method::GET, &uri, &[("if-none-match", &tag)], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(etag(&response), tag);
+    let response = app.send(Method::GET, "/items", &[("if-none-match", &page_tag)], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn raw_and_decoded_bodies_have_their_own_etags() {
+    let app = app().await;
+    let id = create(&app, "twice", "data").await["id"].as_str().unwrap().to_string();
+    let decoded_uri = format!("/items/{id}");
+    let raw_uri = format!("/items/{id}?raw=true");
+
+    let decoded = etag(&app.get(&decoded_uri).await);
+    let raw = etag(&app.get(&raw_uri).await);
+    assert_ne!(raw, decoded);
+
+    // Each tag only revalidates its own representation
+    let response = app.send(Method::GET, &raw_uri, &[("if-none-match", &decoded)], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(etag(&response), raw);
+    let response = app.send(Method::GET, &decoded_uri, &[("if-none-match", &raw)], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.send(Method::GET, &raw_uri, &[("if-none-match", &raw)], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    // Either one names the version read for If-Match
+    let response = app.send(Method::PUT, &decoded_uri, &[("if-match", &raw)], Some(json!({"name": "v2"}))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn if_match_guards_updates_and_deletes() {
+    let app = app().await;
+    let created = create(&app, "guarded", "data").await;
+    let uri = format!("/items/{}", created["id"].as_str().unwrap());
+    let first = created["etag"].as_str().unwrap().to_string();
+
+    let response = app.send(Method::PUT, &uri, &[("if-match", &first)], Some(json!({"name": "v2"}))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let second = etag(&response);
+    assert_ne!(second, first);
+
+    // The stale tag no longer matches, for renames, new data and deletes alike
+    let response = app.send(Method::PUT, &uri, &[("if-match", &first)], Some(json!({"name": "v3"}))).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(json(response).await["code"], "request.precondition_failed");
+    let data = json!({"data": BASE64_STANDARD.encode("new data")});
+    let response = app.send(Method::PUT, &uri, &[("if-match", &first)], Some(data.clone())).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    let response = app.send(Method::DELETE, &uri, &[("if-match", &first)], None).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    // Weak tags never match If-Match
+    let weak = format!("W/{second}");
+    let response = app.send(Method::PUT, &uri, &[("if-match", &weak)], Some(data.clone())).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+
+    let listed = format!("{first}, {second}");
+    let response = app.send(Method::PUT, &uri, &[("if-match", &listed)], Some(data)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = app.send(Method::DELETE, &uri, &[("if-match", "*")], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn deleted_items_move_to_the_trash_until_restored() {
+    let app = app().await;
+    let kept = create(&app, "kept", "stays").await;
+    let deleted = create(&app, "deleted", "comes back").await;
+    let uri = format!("/items/{}", deleted["id"].as_str().unwrap());
+
+    let response = app.send(Method::DELETE, &uri, &[], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Gone from the live items, for reads, writes and listings
+    let response = app.send(Method::GET, &uri, &[], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(json(response).await["code"], "item.not_found");
+    let response = app.send(Method::PUT, &uri, &[], Some(json!({"name": "edited"}))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(app.send(Method::DELETE, &uri, &[], None).await.status(), StatusCode::NOT_FOUND);
+    let response = app.send(Method::GET, "/items", &[], None).await;
+    assert_eq!(response.headers()["x-total-count"], "1");
+    assert_eq!(names(&json(response).await), ["kept"]);
+
+    let response = app.send(Method::GET, "/items/trash", &[], None).await;
+    assert_eq!(response.headers()["x-total-count"], "1");
+    let trash = json(response).await;
+    assert_eq!(names(&trash), ["deleted"]);
+    assert!(trash["items"][0]["deleted_at"].is_string());
+    assert_ne!(trash["items"][0]["etag"], deleted["etag"]);
+
+    let response = app.send(Method::POST, &format!("{uri}/restore"), &[], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let restored = json(response).await;
+    assert_eq!(restored["deleted_at"], Value::Null);
+    assert_eq!(restored["data"], BASE64_STANDARD.encode("comes back"));
+    // Restoring brings back the item exactly as it was deleted
+    assert_eq!(restored["etag"], deleted["etag"]);
+
+    let page = json(app.send(Method::GET, "/items?sort=asc", &[], None).await).await;
+    assert_eq!(names(&page), ["kept", "deleted"]);
+    let trash = json(app.send(Method::GET, "/items/trash", &[], None).await).await;
+    assert_eq!(trash["items"], json!([]));
+
+    // Only trashed items can be restored
+    let live = format!("/items/{}/restore", kept["id"].as_str().unwrap());
+    assert_eq!(app.send(Method::POST, &live, &[], None).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(app.send(Method::POST, &format!("{uri}/restore"), &[], None).await.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,105 @@
+//! Scope checks in front of the routes, with keys from a static keys file
+
+mod common;
+
+use std::io::Cursor;
+
+use axum::http::{Method, StatusCode};
+use base64::prelude::*;
+use rust_compress_api::core::config::AppConfig;
+use rust_compress_api::core::models::Scope;
+use rust_compress_api::services::AuthService;
+use serde_json::{Value, json};
+use tempfile::TempDir;
+
+use common::{TestApp, json};
+
+/// An app with authentication on and one key per scope set, by key id
+struct KeyedApp {
+    app: TestApp,
+    keys: Vec<(&'static str, String)>,
+    _dir: TempDir,
+}
+
+impl KeyedApp {
+    fn new(keys: &[(&'static str, &[Scope])]) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys.toml");
+        let mut file = String::new();
+        let mut issued = Vec::new();
+        for (id, scopes) in keys {
+            let (key, entry) = AuthService::static_key_entry(id, id, scopes).unwrap();
+            file.push_str(&entry);
+            issued.push((*id, key));
+        }
+        std::fs::write(&path, file).unwrap();
+
+        let mut config = AppConfig::default();
+        config.auth.enabled = true;
+        config.auth.keys_file = path.to_string_lossy().into_owned();
+        Self {
+            app: TestApp::new(config),
+            keys: issued,
+            _dir: dir,
+        }
+    }
+
+    async fn post(&self, id: &str, uri: &str, body: Value) -> (StatusCode, Value) {
+        let key = &self.keys.iter().find(|(key_id, _)| *key_id == id).unwrap().1;
+        let response = self.app.send(Method::POST, uri, &[("x-api-key", key)], Some(body)).await;
+        (response.status(), json(response).await)
+    }
+}
+
+fn png() -> String {
+    let image = image::RgbImage::from_fn(32, 24, |x, y| image::Rgb([(x * 8) as u8, (y * 10) as u8, 64]));
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    BASE64_STANDARD.encode(data)
+}
+
+fn image(data: &str) -> Value {
+    json!({"image_data": data, "filename": "gradient.png", "content_type": "image/png", "generate_thumbnail": false})
+}
+
+#[tokio::test]
+async fn batches_need_the_batch_scope() {
+    let app = KeyedApp::new(&[
+        ("compress", &[Scope::Compress]),
+        ("batch", &[Scope::Batch]),
+        ("admin", &[Scope::Admin]),
+    ]);
+    let batch = json!({"images": [image(&png())]});
+
+    let (status, body) = app.post("compress", "/compress/batch", batch.clone()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["code"], "auth.insufficient_scope");
+    assert_eq!(app.post("batch", "/compress/batch", batch.clone()).await.0, StatusCode::OK);
+    assert_eq!(app.post("admin", "/compress/batch", batch).await.0, StatusCode::OK);
+
+    // The batch scope does not grant single compressions
+    let (status, _) = app.post("batch", "/compress", image(&png())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(app.post("compress", "/compress", image(&png())).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn batch_results_follow_request_order_and_fail_alone() {
+    let app = KeyedApp::new(&[("batch", &[Scope::Batch])]);
+    let batch = json!({"images": [image(&png()), image(&BASE64_STANDARD.encode("not an image")), image(&png())]});
+
+    let (status, body) = app.post("batch", "/compress/batch", batch).await;
+    assert_eq!(status, StatusCode::OK);
+    let statuses: Vec<_> = body["results"].as_array().unwrap().iter().map(|result| result["status"].clone()).collect();
+    assert_eq!(statuses, [200, 400, 200]);
+    assert_eq!(body["results"][0]["result"]["filename"], "gradient.png");
+    assert!(body["results"][1]["error"]["code"].is_string());
+    assert!(body["results"][1].get("result").is_none());
+
+    let (status, body) = app.post("batch", "/compress/batch", json!({"images": []})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "request.validation_failed");
+    let images: Vec<_> = (0..17).map(|_| image("")).collect();
+    let (status, _) = app.post("batch", "/compress/batch", json!({"images": images})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,52 @@
+//! Downloading the images `POST /compress` keeps, with conditional requests
+#![cfg(feature = "database")]
+
+mod common;
+
+use std::io::Cursor;
+
+use axum::body::to_bytes;
+use axum::http::{Method, StatusCode, header};
+use base64::prelude::*;
+use rust_compress_api::core::config::AppConfig;
+use serde_json::json;
+
+use common::{TestApp, json};
+
+fn png() -> String {
+    let image = image::RgbImage::from_fn(32, 24, |x, y| image::Rgb([(x * 8) as u8, (y * 10) as u8, 64]));
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    BASE64_STANDARD.encode(data)
+}
+
+#[tokio::test]
+async fn stored_images_answer_304_for_their_etag() {
+    let app = TestApp::with_database(AppConfig::default()).await;
+    let body = json!({"image_data": png(), "filename": "gradient.png", "content_type": "image/png"});
+    let compressed = json(app.send(Method::POST, "/compress", &[], Some(body)).await).await;
+    let uri = format!("/images/{}", compressed["file_id"].as_str().unwrap());
+
+    let response = app.get(&uri).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Every image is stored as JPEG, whatever it was uploaded as
+    assert_eq!(response.headers()[header::CONTENT_TYPE], "image/jpeg");
+    let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+    assert_eq!(etag, format!("\"{}\"", compressed["compressed_sha256"].as_str().unwrap()));
+    let data = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(BASE64_STANDARD.encode(data), compressed["compressed_data"].as_str().unwrap());
+
+    let response = app.send(Method::GET, &uri, &[("if-none-match", &etag)], None).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[header::ETAG], etag.as_str());
+    let response = app.send(Method::GET, &uri, &[("if-none-match", "\"stale\"")], None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn unknown_images_are_not_found() {
+    let app = TestApp::with_database(AppConfig::default()).await;
+    let response = app.get("/images/00000000-0000-0000-0000-000000000000").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(json(response).await["code"], "image.not_found");
+}
@@ -0,0 +1,148 @@
+//! OTLP export against an in-process collector stand-in, and `traceparent` propagation
+//! to image downloads
+
+use std::io::Cursor;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::routing::{get, post};
+use rust_compress_api::core::config::{AppConfig, TelemetryConfig};
+use rust_compress_api::core::models::CompressImageRequest;
+use rust_compress_api::server::Telemetry;
+use rust_compress_api::server::telemetry;
+use rust_compress_api::services::{ComputePool, ImageCompressionService, Metrics};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_ID: &str = "00f067aa0ba902b7";
+
+/// Serves `router` on an ephemeral local port
+async fn serve(router: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    addr
+}
+
+/// An OTLP/HTTP collector that forwards every trace export body to the returned channel
+async fn collector() -> (String, mpsc::UnboundedReceiver<Bytes>) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let router = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(sender): State<mpsc::UnboundedSender<Bytes>>, body: Bytes| async move {
+                let _ = sender.send(body);
+            }),
+        )
+        .route("/v1/metrics", post(|| async {}))
+        .with_state(sender);
+    (format!("http://{}", serve(router).await), receiver)
+}
+
+fn telemetry_config(endpoint: String) -> TelemetryConfig {
+    TelemetryConfig {
+        enabled: true,
+        endpoint,
+        sampling_ratio: 1.0,
+        service_name: "telemetry-test".to_string(),
+        metrics_interval_secs: 60,
+    }
+}
+
+fn png() -> Vec<u8> {
+    let image = image::RgbImage::from_fn(16, 16, |x, y| image::Rgb([(x * 16) as u8, (y * 16) as u8, 0]));
+    let mut data = Vec::new();
+    image.write_to(&mut Cursor::new(&mut data), image::ImageFormat::Png).unwrap();
+    data
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn spans_reach_the_collector() {
+    let (endpoint, mut exports) = collector().await;
+
+    // The exporter's HTTP client blocks, so the pipeline runs off the async workers
+    tokio::task::spawn_blocking(move || {
+        let telemetry = Telemetry::init(&telemetry_config(endpoint)).unwrap().expect("telemetry is enabled");
+        let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("collector_probe", width = 640).in_scope(|| {});
+        });
+        telemetry.shutdown();
+    })
+    .await
+    .unwrap();
+
+    let export = tokio::time::timeout(Duration::from_secs(10), exports.recv())
+        .await
+        .expect("the collector received an export")
+        .unwrap();
+    // Protobuf keeps strings as-is, so the span and service names are in the body
+    for name in ["collector_probe", "telemetry-test"] {
+        assert!(export.windows(name.len()).any(|window| window == name.as_bytes()), "{name} not exported");
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn downloads_carry_the_callers_trace() {
+    let (endpoint, _exports) = collector().await;
+    let (sender, mut traceparents) = mpsc::unbounded_channel::<Option<String>>();
+    let upstream = Router::new()
+        .route(
+            "/image.png",
+            get(|State(sender): State<mpsc::UnboundedSender<Option<String>>>, headers: HeaderMap| async move {
+                let traceparent = headers.get("traceparent").and_then(|value| value.to_str().ok());
+                let _ = sender.send(traceparent.map(str::to_string));
+                ([(header::CONTENT_TYPE, "image/png")], png())
+            }),
+        )
+        .with_state(sender);
+    let upstream = serve(upstream).await;
+
+    let telemetry = tokio::task::spawn_blocking(move || Telemetry::init(&telemetry_config(endpoint)).unwrap().unwrap())
+        .await
+        .unwrap();
+    let subscriber = tracing_subscriber::registry().with(telemetry.layer());
+    let guard = tracing::subscriber::set_default(subscriber);
+
+    let config = AppConfig::default();
+    let service =
+        ImageCompressionService::from_config(&config, Arc::new(ComputePool::new(1)), Arc::new(Metrics::new())).unwrap();
+    let request = CompressImageRequest {
+        image_data: None,
+        image_url: Some(format!("http://{upstream}/image.png")),
+        filename: "image.png".to_string(),
+        content_type: "image/png".to_string(),
+        generate_thumbnail: Some(false),
+        thumbnail_size: None,
+        quality: None,
+        max_width: None,
+        max_height: None,
+        checksum: None,
+    };
+
+    // The span the request middleware would open for a caller sending `traceparent`
+    let mut incoming = HeaderMap::new();
+    let traceparent = format!("00-{TRACE_ID}-{PARENT_ID}-01");
+    incoming.insert("traceparent", HeaderValue::from_str(&traceparent).unwrap());
+    let span = tracing::info_span!("request");
+    telemetry::set_remote_parent(&span, &incoming);
+    service.compress_image(request).instrument(span).await.unwrap();
+
+    let outgoing = traceparents.recv().await.unwrap().expect("the download sent traceparent");
+    let parts: Vec<_> = outgoing.split('-').collect();
+    assert_eq!(parts.len(), 4, "{outgoing}");
+    assert_eq!(parts[1], TRACE_ID);
+    // The download continues the trace from our own span, not the caller's
+    assert_ne!(parts[2], PARENT_ID);
+    assert_eq!(parts[3], "01");
+
+    drop(guard);
+    tokio::task::spawn_blocking(move || telemetry.shutdown()).await.unwrap();
+}
@@ -0,0 +1,270 @@
+//! Export and import between two in-memory deployments: what an export holds, and how
+//! imports treat records they have seen before.
+#![cfg(feature = "database")]
+
+use std::io::{BufRead, Cursor};
+use std::sync::Arc;
+
+use rust_compress_api::core::database::{self, Storage};
+use rust_compress_api::core::models::{
+    CompressionAlgorithm, ConflictPolicy, ExportCompression, ItemPayload, ItemQuery, NewStoredImage,
+};
+use rust_compress_api::services::ComputePool;
+use rust_compress_api::services::dictionary::DictionaryStore;
+use rust_compress_api::services::transfer::{
+    ExportOptions, Exporter, ImportOutcome, ImportSummary, Importer, open_export, renamed_id,
+};
+use rust_compress_api::utils::checksum::sha256_hex;
+
+async fn storage() -> Storage {
+    database::connect("memory:", true).await.expect("storage opens")
+}
+
+fn payload(data: &[u8]) -> ItemPayload {
+    ItemPayload {
+        data: data.to_vec(),
+        algorithm: CompressionAlgorithm::Identity,
+        level: 0,
+        dictionary_version: None,
+        original_size: data.len() as u64,
+        original_sha256: sha256_hex(data),
+        compressed_sha256: sha256_hex(data),
+    }
+}
+
+async fn export(source: &Storage, options: ExportOptions) -> Vec<u8> {
+    let mut exporter = Exporter::new(
+        source.items.clone(),
+        source.images.clone(),
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(1)),
+        options,
+    )
+    .unwrap();
+    let mut output = Vec::new();
+    while let Some(chunk) = exporter.next_chunk().await.unwrap() {
+        output.extend(chunk);
+    }
+    output
+}
+
+async fn import(target: &Storage, export: &[u8], on_conflict: ConflictPolicy) -> ImportSummary {
+    let mut importer = Importer::new(
+        target.items.clone(),
+        target.images.clone(),
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(1)),
+        on_conflict,
+    );
+    for line in open_export(Cursor::new(export)).unwrap().lines() {
+        importer.import_line(&line.unwrap()).await.unwrap();
+    }
+    *importer.summary()
+}
+
+#[tokio::test]
+async fn export_holds_filtered_items_then_trash_then_images() {
+    let source = storage().await;
+    let kept = source.items.create_item("report-1.txt", payload(b"one")).await.unwrap();
+    source.items.create_item("notes.txt", payload(b"two")).await.unwrap();
+    let trashed = source.items.create_item("report-2.txt", payload(b"three")).await.unwrap();
+    source.items.delete_item(trashed.id, None).await.unwrap();
+    let image = source
+        .images
+        .save_image(NewStoredImage {
+            format: "webp".to_string(),
+            data: vec![1, 2, 3],
+            original_size: 9,
+            original_sha256: None,
+            thumbnail: None,
+            options: None,
+        })
+        .await
+        .unwrap();
+
+    let options = ExportOptions {
+        filter: ItemQuery {
+            name_prefix: Some("report".to_string()),
+            ..ItemQuery::default()
+        },
+        include_trash: true,
+        images: true,
+        compression: None,
+    };
+    let export = export(&source, options).await;
+    let records: Vec<serde_json::Value> = export
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect();
+
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[0]["type"], "item");
+    assert_eq!(records[0]["id"], kept.id.to_string());
+    assert_eq!(records[0]["data"], "b25l");
+    assert_eq!(records[1]["id"], trashed.id.to_string());
+    assert!(!records[1]["deleted_at"].is_null());
+    assert_eq!(records[2]["type"], "image");
+    assert_eq!(records[2]["id"], image.id.to_string());
+    assert_eq!(records[2]["data"], "AQID");
+}
+
+#[tokio::test]
+async fn import_keeps_ids_and_is_idempotent() {
+    let source = storage().await;
+    let item = source.items.create_item("a.txt", payload(b"alpha")).await.unwrap();
+    let image = source
+        .images
+        .save_image(NewStoredImage {
+            format: "png".to_string(),
+            data: vec![7; 16],
+            original_size: 64,
+            original_sha256: None,
+            thumbnail: Some(vec![8; 4]),
+            options: Some("jpeg;quality=75".to_string()),
+        })
+        .await
+        .unwrap();
+    let options = ExportOptions {
+        images: true,
+        compression: Some(ExportCompression::Zstd),
+        ..ExportOptions::default()
+    };
+    let export = export(&source, options).await;
+
+    let target = storage().await;
+    let first = import(&target, &export, ConflictPolicy::Skip).await;
+    assert_eq!((first.items.created, first.images.created), (1, 1));
+    let imported = target.items.get_item(item.id).await.unwrap();
+    assert_eq!(imported.etag(), item.etag());
+    assert_eq!(imported.algorithm, "identity");
+    let imported = target.images.get_image(image.id).await.unwrap();
+    assert_eq!(imported.thumbnail, image.thumbnail);
+    assert_eq!(imported.options, image.options);
+
+    let rerun = import(&target, &export, ConflictPolicy::Overwrite).await;
+    assert_eq!((rerun.items.unchanged, rerun.images.unchanged), (1, 1));
+    assert_eq!(rerun.items.total() + rerun.images.total(), 2);
+}
+
+#[tokio::test]
+async fn conflicts_follow_the_policy() {
+    let source = storage().await;
+    let item = source.items.create_item("a.txt", payload(b"alpha")).await.unwrap();
+    let export = export(&source, ExportOptions::default()).await;
+    let target = storage().await;
+    import(&target, &export, ConflictPolicy::Skip).await;
+    target.items.update_item(item.id, Some("changed.txt"), None, None).await.unwrap();
+
+    let skipped = import(&target, &export, ConflictPolicy::Skip).await;
+    assert_eq!(skipped.items.skipped, 1);
+    assert_eq!(target.items.get_item(item.id).await.unwrap().name, "changed.txt");
+
+    let renamed = import(&target, &export, ConflictPolicy::Rename).await;
+    assert_eq!(renamed.items.renamed, 1);
+    let copy = target.items.get_item(renamed_id(item.id)).await.unwrap();
+    assert_eq!(copy.name, "a.txt");
+    let rerun = import(&target, &export, ConflictPolicy::Rename).await;
+    assert_eq!(rerun.items.unchanged, 1);
+
+    let overwritten = import(&target, &export, ConflictPolicy::Overwrite).await;
+    assert_eq!(overwritten.items.overwritten, 1);
+    assert_eq!(target.items.get_item(item.id).await.unwrap().etag(), item.etag());
+}
+
+#[tokio::test]
+async fn lines_without_a_type_are_items() {
+    let target = storage().await;
+    let mut importer = Importer::new(
+        target.items.clone(),
+        target.images.clone(),
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(1)),
+        ConflictPolicy::Skip,
+    );
+    let line = r#"{"name": "legacy.txt", "data": "aGVsbG8=", "algorithm": "gzip"}"#;
+    assert_eq!(importer.import_line(line).await.unwrap(), Some(ImportOutcome::Created));
+    assert_eq!(importer.import_line("   ").await.unwrap(), None);
+    assert!(importer.import_line(r#"{"type": "item"}"#).await.is_err());
+
+    let items = target
+        .items
+        .list_items(&ItemQuery {
+            limit: 10,
+            ..ItemQuery::default()
+        })
+        .await
+        .unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0].algorithm, "gzip");
+    assert_eq!(items[0].original_size, 5);
+}
+
+#[tokio::test]
+async fn checksums_travel_with_records() {
+    let source = storage().await;
+    let item = source.items.create_item("a.txt", payload(b"alpha")).await.unwrap();
+    let mut damaged = source.items.create_item("b.txt", payload(b"bravo")).await.unwrap();
+    damaged.data = b"brave".to_vec();
+    source.items.put_item(damaged.clone()).await.unwrap();
+    let image = source
+        .images
+        .save_image(NewStoredImage {
+            format: "png".to_string(),
+            data: vec![1, 2],
+            original_size: 8,
+            original_sha256: None,
+            thumbnail: None,
+            options: None,
+        })
+        .await
+        .unwrap();
+    let mut exporter = Exporter::new(
+        source.items.clone(),
+        source.images.clone(),
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(1)),
+        ExportOptions {
+            images: true,
+            ..ExportOptions::default()
+        },
+    )
+    .unwrap();
+    let mut export = Vec::new();
+    while let Some(chunk) = exporter.next_chunk().await.unwrap() {
+        export.extend(chunk);
+    }
+
+    // Data that no longer matches its checksum is left out
+    let skipped = &exporter.summary().skipped;
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].id, damaged.id);
+    let records: Vec<serde_json::Value> = export
+        .lines()
+        .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+        .collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["original_sha256"], sha256_hex(b"alpha"));
+    assert_eq!(records[1]["compressed_sha256"], image.compressed_sha256.unwrap());
+
+    let target = storage().await;
+    let mut importer = Importer::new(
+        target.items.clone(),
+        target.images.clone(),
+        Arc::new(DictionaryStore::new()),
+        Arc::new(ComputePool::new(1)),
+        ConflictPolicy::Skip,
+    );
+    let mut tampered = records[0].clone();
+    tampered["data"] = "b21lZ2E=".into();
+    assert!(importer.import_line(&tampered.to_string()).await.is_err());
+    assert!(target.items.find_item(item.id).await.unwrap().is_none());
+
+    let mut tampered = records[1].clone();
+    tampered["data"] = "AQM=".into();
+    assert!(importer.import_line(&tampered.to_string()).await.is_err());
+
+    let line = records[0].to_string();
+    assert_eq!(importer.import_line(&line).await.unwrap(), Some(ImportOutcome::Created));
+    let imported = target.items.get_item(item.id).await.unwrap();
+    assert_eq!(imported.original_sha256, item.original_sha256);
+}